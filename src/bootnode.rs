@@ -0,0 +1,185 @@
+//! Bootnodes are well-known nodes used to seed the routing table.
+//!
+//! A bootnode can be given either as a signed ENR, or as an unsigned address and public key in the
+//! form of an `enode://` URL or (with the `libp2p` feature) a multiaddr. In the latter case, the
+//! ENR of the bootnode is requested when it is first contacted.
+use crate::{
+    node_info::{NodeContact, NonContactable},
+    Enr, IpMode,
+};
+use enr::{k256::ecdsa::VerifyingKey, NodeId};
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+#[cfg(feature = "libp2p")]
+use multiaddr::Multiaddr;
+
+/// A node that is contacted when the server starts and whenever the routing table drains.
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum Bootnode {
+    /// A bootnode whose ENR is known.
+    Enr(Enr),
+    /// A bootnode known only by its public key and UDP socket. Its ENR is requested when it is
+    /// contacted.
+    Contact(NodeContact),
+}
+
+impl Bootnode {
+    /// The node id of the bootnode.
+    pub fn node_id(&self) -> NodeId {
+        match self {
+            Bootnode::Enr(enr) => enr.node_id(),
+            Bootnode::Contact(contact) => contact.node_id(),
+        }
+    }
+
+    /// Returns the contact used to reach the bootnode under the given `IpMode`.
+    pub(crate) fn contact(&self, ip_mode: IpMode) -> Result<NodeContact, NonContactable> {
+        match self {
+            Bootnode::Enr(enr) => NodeContact::try_from_enr(enr.clone(), ip_mode),
+            Bootnode::Contact(contact) => Ok(contact.clone()),
+        }
+    }
+
+    /// Parses an `enode://<hex public key>@<ip>:<port>[?discport=<udp port>]` URL.
+    fn try_from_enode(enode: &str) -> Result<Self, &'static str> {
+        let enode = enode
+            .strip_prefix("enode://")
+            .ok_or("An enode URL must start with enode://")?;
+        let (public_key, address) = enode
+            .split_once('@')
+            .ok_or("An enode URL must contain an address")?;
+
+        let mut public_key_bytes = vec![4u8];
+        public_key_bytes
+            .extend(hex::decode(public_key).map_err(|_| "Invalid enode public key encoding")?);
+        let public_key = VerifyingKey::from_sec1_bytes(&public_key_bytes)
+            .map_err(|_| "Invalid enode public key")?;
+
+        let (address, query) = match address.split_once('?') {
+            Some((address, query)) => (address, Some(query)),
+            None => (address, None),
+        };
+        let mut socket_addr: SocketAddr = address.parse().map_err(|_| "Invalid enode address")?;
+        if let Some(query) = query {
+            for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+                if key == "discport" {
+                    socket_addr.set_port(value.parse().map_err(|_| "Invalid enode discport")?);
+                }
+            }
+        }
+        if socket_addr.port() == 0 || socket_addr.ip().is_unspecified() {
+            return Err("An enode URL must contain a contactable address");
+        }
+        // Normalize IPv4-mapped addresses so that the address is contactable.
+        if let IpAddr::V6(ip) = socket_addr.ip() {
+            if let Some(ip) = crate::ipmode::to_ipv4_mapped(&ip) {
+                socket_addr.set_ip(ip.into());
+            }
+        }
+
+        Ok(Bootnode::Contact(NodeContact::new(
+            public_key.into(),
+            socket_addr,
+            None,
+        )))
+    }
+
+    /// Converts a multiaddr to a bootnode. See [`NodeContact::try_from_multiaddr`].
+    #[cfg(feature = "libp2p")]
+    fn try_from_multiaddr(multiaddr: &str) -> Result<Self, &'static str> {
+        let multiaddr = Multiaddr::from_str(multiaddr).map_err(|_| "Invalid multiaddr")?;
+        NodeContact::try_from_multiaddr(multiaddr).map(Bootnode::Contact)
+    }
+}
+
+impl From<Enr> for Bootnode {
+    fn from(enr: Enr) -> Self {
+        Bootnode::Enr(enr)
+    }
+}
+
+impl FromStr for Bootnode {
+    type Err = &'static str;
+
+    /// Parses a bootnode from a base64 encoded ENR (`enr:-...`), an `enode://` URL, or, with the
+    /// `libp2p` feature, a multiaddr string.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.starts_with("enr:") {
+            return Enr::from_str(s)
+                .map(Bootnode::Enr)
+                .map_err(|_| "Invalid ENR");
+        }
+        if s.starts_with("enode://") {
+            return Bootnode::try_from_enode(s);
+        }
+        #[cfg(feature = "libp2p")]
+        if s.starts_with('/') {
+            return Bootnode::try_from_multiaddr(s);
+        }
+        Err("Unsupported bootnode format")
+    }
+}
+
+impl std::fmt::Display for Bootnode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Bootnode::Enr(enr) => write!(f, "{enr}"),
+            Bootnode::Contact(contact) => write!(f, "{contact}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enr::{CombinedKey, EnrKey};
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_parse_enr_bootnode() {
+        let key = CombinedKey::generate_secp256k1();
+        let enr = Enr::builder()
+            .ip4(Ipv4Addr::LOCALHOST)
+            .udp4(9000)
+            .build(&key)
+            .unwrap();
+
+        let bootnode: Bootnode = enr.to_base64().parse().unwrap();
+        assert_eq!(bootnode, Bootnode::Enr(enr.clone()));
+        assert_eq!(bootnode.node_id(), enr.node_id());
+    }
+
+    #[test]
+    fn test_parse_enode_bootnode() {
+        let key = CombinedKey::generate_secp256k1();
+        let CombinedKey::Secp256k1(ref secret) = key else {
+            unreachable!()
+        };
+        let public_key = secret.verifying_key().to_encoded_point(false);
+        let enode = format!(
+            "enode://{}@10.0.0.1:30303?discport=30301",
+            hex::encode(&public_key.as_bytes()[1..])
+        );
+
+        let bootnode: Bootnode = enode.parse().unwrap();
+        let contact = bootnode.contact(IpMode::Ip4).unwrap();
+        assert_eq!(bootnode.node_id(), NodeId::from(key.public()));
+        assert_eq!(
+            contact.socket_addr(),
+            SocketAddr::new(Ipv4Addr::new(10, 0, 0, 1).into(), 30301)
+        );
+        assert!(contact.enr().is_none());
+    }
+
+    #[test]
+    fn test_parse_invalid_bootnodes() {
+        assert!("enode://abcd@10.0.0.1:30303".parse::<Bootnode>().is_err());
+        assert!("enr:-invalid".parse::<Bootnode>().is_err());
+        assert!("10.0.0.1:30303".parse::<Bootnode>().is_err());
+    }
+}
@@ -1,9 +1,15 @@
 //! A set of configuration parameters to tune the discovery protocol.
 use crate::{
-    kbucket::MAX_NODES_PER_BUCKET, socket::ListenConfig, Enr, Executor, PermitBanList,
-    ProtocolIdentity, RateLimiter, RateLimiterBuilder,
+    distance_strategy::{DistanceStrategy, NeighbourDistances},
+    enr_store::EnrStore,
+    handler::{IdentityScheme, IdentitySchemes},
+    kbucket::MAX_NODES_PER_BUCKET,
+    packet::MAX_PACKET_SIZE,
+    socket::ListenConfig,
+    BanPolicy, Bootnode, Enr, Error, Executor, ExternalAddressMode, PermitBanList,
+    ProtocolIdentity, RateLimiter, RateLimiterBuilder, RequestRateLimiter, Transport,
 };
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 /// Configuration parameters that define the performance of the discovery network.
 #[derive(Clone)]
@@ -18,6 +24,10 @@ pub struct Config {
     /// interval will respond faster to IP changes. Default is 2 minutes.
     pub vote_duration: Duration,
 
+    /// The prefix length of the IPv6 subnets whose peers share a single vote when determining our
+    /// external IP. IPv4 peers share a vote per /24 subnet. Default: 64.
+    pub vote_ipv6_subnet_prefix: u8,
+
     /// The timeout after which a `QueryPeer` in an ongoing query is marked unresponsive.
     /// Unresponsive peers don't count towards the parallelism limits for a query.
     /// Hence, we may potentially end up making more requests to good peers. Default: 2 seconds.
@@ -38,11 +48,35 @@ pub struct Config {
     /// Updates the local ENR IP and port based on PONG responses from peers. Default: true.
     pub enr_update: bool,
 
+    /// The transports advertised at the external IP address agreed on by peers, in addition to
+    /// UDP. Each transport keeps its own port, or takes its port of the other IP version if it
+    /// has none for the voted one. The IP address is shared by all transports, so the advertised
+    /// address of all transports changes along with the UDP socket. Default: empty.
+    pub enr_update_transports: Vec<Transport>,
+
+    /// Advertises the `enr_update_transports` on the external UDP port agreed on by peers instead
+    /// of their own ports. This suits transports sharing the port of the discovery socket.
+    /// Default: false.
+    pub enr_update_share_udp_port: bool,
+
+    /// How the external IPv4 socket is advertised in the local ENR. The
+    /// `enr_update_transports` are also advertised at a static socket. Static and never
+    /// advertised sockets are applied when the server is created, regardless of `enr_update`.
+    /// Default: [`ExternalAddressMode::Auto`].
+    pub ipv4_external_address: ExternalAddressMode,
+
+    /// How the external IPv6 socket is advertised in the local ENR. The
+    /// `enr_update_transports` are also advertised at a static socket. Static and never
+    /// advertised sockets are applied when the server is created, regardless of `enr_update`.
+    /// Default: [`ExternalAddressMode::Auto`].
+    pub ipv6_external_address: ExternalAddressMode,
+
     /// The maximum number of nodes we return to a find nodes request. The default is 16.
     pub max_nodes_response: usize,
 
-    /// The minimum number of peer's who agree on an external IP port before updating the
-    /// local ENR. Default: 10.
+    /// The minimum number of peers of distinct subnets who agree on an external IP port before
+    /// updating the local ENR. Votes are only counted from peers we initiated a session with.
+    /// Default: 10.
     pub enr_peer_update_min: usize,
 
     /// The number of peers to request in parallel in a single query. Default: 3.
@@ -64,15 +98,97 @@ pub struct Config {
     /// seconds.
     pub ping_interval: Duration,
 
+    /// When the sequence number of the local ENR increases, all peers we have a session with are
+    /// pinged immediately, spread evenly over this window, rather than on their next regular
+    /// ping. An [`crate::Event::EnrUpdatePropagated`] reports how many peers requested the updated
+    /// ENR. If set to None, updates are only propagated by the regular pings. Default: None.
+    pub enr_update_propagation_window: Option<Duration>,
+
+    /// The minimum time between the beginnings of two rounds propagating updates of the local ENR.
+    /// An update within this interval of the previous round is propagated once the interval has
+    /// passed, together with any further updates. Default: 10 seconds.
+    pub enr_update_propagation_min_interval: Duration,
+
     /// Reports all discovered ENR's when traversing the DHT to the event stream. Default true.
     pub report_discovered_peers: bool,
 
+    /// Selects the log2-distances requested from each peer in the FINDNODE requests of a query.
+    /// See [`crate::distance_strategy`] for the built-in strategies. Default: the distance of the
+    /// target and its two neighbouring distances ([`NeighbourDistances`] with a count of 3).
+    pub distance_strategy: Arc<dyn DistanceStrategy>,
+
+    /// The interval between automatic lookups that refresh under-filled buckets of the routing
+    /// table. If the table is healthy, the interval is progressively increased. Setting this to
+    /// `None` disables automatic refreshes. Default: None.
+    pub table_refresh_interval: Option<Duration>,
+
+    /// A list of nodes that are contacted when the server starts and whenever the number of
+    /// entries in the routing table drops below `bootnode_reseed_threshold`. Bootnodes are never
+    /// permanently forgotten, they are re-added to the routing table on every re-seed. Default:
+    /// empty.
+    pub bootnodes: Vec<Bootnode>,
+
+    /// The number of entries in the routing table below which the bootnodes are contacted again.
+    /// Default: 16.
+    pub bootnode_reseed_threshold: usize,
+
+    /// The interval at which the size of the routing table is compared to
+    /// `bootnode_reseed_threshold`. Default: 30 seconds.
+    pub bootnode_reseed_interval: Duration,
+
     /// A set of configuration parameters for setting inbound request rate limits. See
     /// [`RateLimiterBuilder`] for options. This is only functional if the packet filter is
     /// enabled via the `enable_packet_filter` option. See the `Default` implementation for
     /// default values. If set to None, inbound requests are not filtered.
     pub filter_rate_limiter: Option<RateLimiter>,
 
+    /// Rate limits for inbound requests, applied per request type once a request has been
+    /// decrypted. See [`crate::RequestRateLimiterBuilder`] for options. Unlike the
+    /// `filter_rate_limiter`, this does not require the packet filter to be enabled. If set to
+    /// None, decrypted requests are not limited. Default: None.
+    pub request_rate_limiter: Option<RequestRateLimiter>,
+
+    /// The maximum number of requests we have in flight to a single peer. Further requests to the
+    /// peer are queued until a response is received or a request fails. If set to None, the
+    /// number of concurrent requests is not limited. Default: None.
+    pub max_in_flight_requests_per_peer: Option<usize>,
+
+    /// Derives the timeout of requests from the round-trip times measured to each peer, instead
+    /// of using `request_timeout` for all peers. Retries back off exponentially. Peers without
+    /// measured round trips use `request_timeout`, and adaptive timeouts are bounded by 200ms and
+    /// four times the `request_timeout`. Queries likewise stop waiting for a peer with measured
+    /// round trips after twice its retransmission timeout instead of `query_peer_timeout`.
+    /// Default: false.
+    pub adaptive_request_timeout: bool,
+
+    /// The maximum number of requests we send per second, across all peers. Requests exceeding
+    /// the rate are queued and sent in a round-robin fashion across peers. If set to None, the
+    /// outbound rate is not limited. Default: None.
+    pub outbound_requests_per_second: Option<u32>,
+
+    /// The time a request can wait in the outbound queue, due to the
+    /// `max_in_flight_requests_per_peer` and `outbound_requests_per_second` limits. Requests that
+    /// are not sent within this time fail with [`crate::RequestError::Timeout`]. Default: 10
+    /// seconds.
+    pub outbound_queue_timeout: Duration,
+
+    /// The maximum number of requests waiting in the outbound queue. Further requests fail with
+    /// [`crate::RequestError::OutboundQueueFull`]. Default: 1024.
+    pub max_queued_outbound_requests: usize,
+
+    /// The number of bytes of NODES responses that can be sent per minute to an address that has
+    /// not yet answered one of our PINGs. Responses exceeding the budget are truncated. This
+    /// protects against our node being used to amplify traffic towards spoofed source addresses.
+    /// A full answer of `max_nodes_response` ENRs of the maximum size takes 6 packets, so the
+    /// default fits several full answers per minute. If set to None, responses are not limited.
+    /// Default: 16 full packets (20480 bytes).
+    pub unverified_response_byte_budget: Option<usize>,
+
+    /// Automatically bans peers whose misbehaviour, as recorded in their [`crate::PeerStats`],
+    /// crosses the threshold of the policy. Peers are banned for `ban_duration`. If set to None,
+    /// peers are never banned based on their statistics. Default: None.
+    pub ban_policy: Option<BanPolicy>,
+
     /// The maximum number of node-ids allowed per IP address before the IP address gets banned.
     /// Having this set to None, disables this feature. Default value is 10. This is only
     /// applicable if the `enable_packet_filter` option is set.
@@ -100,10 +216,33 @@ pub struct Config {
     /// receive a single INCOMING connection in this duration, we consider ourselves contactable,
     /// until we update or change our IP address again. If we fail to receive an incoming
     /// connection in this duration, we revoke our ENR address advertisement for 6 hours, before
-    /// trying again. This can be set to None, to always advertise and never revoke. The default is
-    /// Some(5 minutes).
+    /// trying again. This can be set to None, to always advertise and never revoke. The check is
+    /// disabled when `enr_update` is false. The default is Some(5 minutes).
     pub auto_nat_listen_duration: Option<Duration>,
 
+    /// The number of connected peers asked to dial back our address after our ENR socket is
+    /// updated. Each successful dial-back counts as an incoming connection, confirming that we are
+    /// contactable within seconds rather than waiting for `auto_nat_listen_duration`. If set to
+    /// None, no dial-backs are requested. Default: None.
+    pub dial_back_peers: Option<usize>,
+
+    /// Whether to dial back peers that request it, using a fresh socket and node id. Dial-backs
+    /// are only sent to the IP address the request was received from. Default: false.
+    pub serve_dial_back: bool,
+
+    /// The identity schemes used to perform handshakes. The `v4` (secp256k1) and `ed25519`
+    /// schemes are built in, and are the only schemes node records carry keys of. Their
+    /// implementations can be replaced, but no other scheme can be added.
+    pub identity_schemes: IdentitySchemes,
+
+    /// Persists the local ENR across restarts. The stored record is loaded when the server is
+    /// created and every update of the local ENR is written back, so that the sequence number of
+    /// the local ENR never goes backwards. Writes happen on a background thread. Dropping the
+    /// [`crate::Discv5`] server outside of a tokio runtime waits for pending writes; inside a
+    /// runtime it does not, so a process exiting right after may lose the latest update. See
+    /// [`crate::enr_store`]. Default: None.
+    pub enr_store: Option<Arc<dyn EnrStore>>,
+
     /// A custom executor which can spawn the discv5 tasks. This must be a tokio runtime, with
     /// timing support. By default, the executor that created the discv5 struct will be used.
     pub executor: Option<Box<dyn Executor + Send + Sync>>,
@@ -137,12 +276,17 @@ impl ConfigBuilder {
             enable_packet_filter: false,
             request_timeout: Duration::from_secs(1),
             vote_duration: Duration::from_secs(120),
+            vote_ipv6_subnet_prefix: 64,
             query_peer_timeout: Duration::from_secs(2),
             query_timeout: Duration::from_secs(60),
             request_retries: 1,
             session_timeout: Duration::from_secs(86400),
             session_cache_capacity: 1000,
             enr_update: true,
+            enr_update_transports: Vec::new(),
+            enr_update_share_udp_port: false,
+            ipv4_external_address: ExternalAddressMode::Auto,
+            ipv6_external_address: ExternalAddressMode::Auto,
             max_nodes_response: 16,
             enr_peer_update_min: 10,
             query_parallelism: 3,
@@ -150,13 +294,32 @@ impl ConfigBuilder {
             incoming_bucket_limit: MAX_NODES_PER_BUCKET,
             table_filter: |_| true,
             ping_interval: Duration::from_secs(300),
+            enr_update_propagation_window: None,
+            enr_update_propagation_min_interval: Duration::from_secs(10),
             report_discovered_peers: true,
+            distance_strategy: Arc::new(NeighbourDistances::default()),
+            table_refresh_interval: None,
+            bootnodes: Vec::new(),
+            bootnode_reseed_threshold: MAX_NODES_PER_BUCKET,
+            bootnode_reseed_interval: Duration::from_secs(30),
             filter_rate_limiter,
+            request_rate_limiter: None,
+            max_in_flight_requests_per_peer: None,
+            adaptive_request_timeout: false,
+            outbound_requests_per_second: None,
+            outbound_queue_timeout: Duration::from_secs(10),
+            max_queued_outbound_requests: 1024,
+            unverified_response_byte_budget: Some(16 * MAX_PACKET_SIZE),
+            ban_policy: None,
             filter_max_nodes_per_ip: Some(10),
             filter_max_bans_per_ip: Some(5),
             permit_ban_list: PermitBanList::default(),
             ban_duration: Some(Duration::from_secs(3600)), // 1 hour
             auto_nat_listen_duration: Some(Duration::from_secs(300)), // 5 minutes
+            dial_back_peers: None,
+            serve_dial_back: false,
+            identity_schemes: IdentitySchemes::default(),
+            enr_store: None,
             executor: None,
             listen_config,
             protocol_identity: ProtocolIdentity::default(),
@@ -184,6 +347,16 @@ impl ConfigBuilder {
         self
     }
 
+    /// The prefix length of the IPv6 subnets whose peers share a single vote when determining our
+    /// external IP.
+    pub fn vote_ipv6_subnet_prefix(&mut self, prefix: u8) -> &mut Self {
+        if prefix > 128 {
+            panic!("An IPv6 subnet prefix cannot be longer than 128 bits");
+        }
+        self.config.vote_ipv6_subnet_prefix = prefix;
+        self
+    }
+
     /// The timeout after which a `QueryPeer` in an ongoing query is marked unresponsive.
     /// Unresponsive peers don't count towards the parallelism limits for a query.
     /// Hence, we may potentially end up making more requests to good peers.
@@ -222,14 +395,46 @@ impl ConfigBuilder {
         self
     }
 
+    /// Advertises the given transport at the external IP address when peers agree on a new
+    /// external socket, keeping its own port.
+    pub fn enr_update_transport(&mut self, transport: Transport) -> &mut Self {
+        self.config.enr_update_transports.push(transport);
+        self
+    }
+
+    /// Advertises the `enr_update_transports` on the external UDP port agreed on by peers, for
+    /// transports sharing the port of the discovery socket.
+    pub fn enr_update_share_udp_port(&mut self) -> &mut Self {
+        self.config.enr_update_share_udp_port = true;
+        self
+    }
+
+    /// Sets how the external IPv4 socket is advertised in the local ENR.
+    pub fn ipv4_external_address(&mut self, mode: ExternalAddressMode) -> &mut Self {
+        if matches!(mode, ExternalAddressMode::Static(socket) if !socket.is_ipv4()) {
+            panic!("The static external IPv4 address must be an IPv4 socket");
+        }
+        self.config.ipv4_external_address = mode;
+        self
+    }
+
+    /// Sets how the external IPv6 socket is advertised in the local ENR.
+    pub fn ipv6_external_address(&mut self, mode: ExternalAddressMode) -> &mut Self {
+        if matches!(mode, ExternalAddressMode::Static(socket) if !socket.is_ipv6()) {
+            panic!("The static external IPv6 address must be an IPv6 socket");
+        }
+        self.config.ipv6_external_address = mode;
+        self
+    }
+
     /// The maximum number of nodes we response to a find nodes request.
     pub fn max_nodes_response(&mut self, max: usize) -> &mut Self {
         self.config.max_nodes_response = max;
         self
     }
 
-    /// The minimum number of peer's who agree on an external IP port before updating the
-    /// local ENR.
+    /// The minimum number of peers of distinct subnets who agree on an external IP port before
+    /// updating the local ENR.
     pub fn enr_peer_update_min(&mut self, min: usize) -> &mut Self {
         if min < 2 {
             panic!("Setting enr_peer_update_min to a value less than 2 will cause issues with discovery with peers behind NAT");
@@ -271,18 +476,119 @@ impl ConfigBuilder {
         self
     }
 
+    /// Pings all peers we have a session with, spread over the given window, when the sequence
+    /// number of the local ENR increases.
+    pub fn enr_update_propagation_window(&mut self, window: Option<Duration>) -> &mut Self {
+        self.config.enr_update_propagation_window = window;
+        self
+    }
+
+    /// The minimum time between the beginnings of two rounds propagating updates of the local ENR.
+    pub fn enr_update_propagation_min_interval(&mut self, interval: Duration) -> &mut Self {
+        self.config.enr_update_propagation_min_interval = interval;
+        self
+    }
+
     /// Disables reporting of discovered peers through the event stream.
     pub fn disable_report_discovered_peers(&mut self) -> &mut Self {
         self.config.report_discovered_peers = false;
         self
     }
 
+    /// Sets the strategy selecting the distances requested from each peer during a query.
+    pub fn distance_strategy(&mut self, strategy: impl DistanceStrategy + 'static) -> &mut Self {
+        self.config.distance_strategy = Arc::new(strategy);
+        self
+    }
+
+    /// The interval between automatic lookups that refresh under-filled buckets of the routing
+    /// table. Setting this to `None` disables automatic refreshes.
+    pub fn table_refresh_interval(&mut self, interval: Option<Duration>) -> &mut Self {
+        self.config.table_refresh_interval = interval;
+        self
+    }
+
+    /// A list of nodes that are contacted when the server starts and whenever the routing table
+    /// drains.
+    pub fn bootnodes(&mut self, bootnodes: Vec<Bootnode>) -> &mut Self {
+        self.config.bootnodes = bootnodes;
+        self
+    }
+
+    /// Adds a node to the list of bootnodes.
+    pub fn add_bootnode(&mut self, bootnode: impl Into<Bootnode>) -> &mut Self {
+        self.config.bootnodes.push(bootnode.into());
+        self
+    }
+
+    /// The number of entries in the routing table below which the bootnodes are contacted again.
+    pub fn bootnode_reseed_threshold(&mut self, threshold: usize) -> &mut Self {
+        self.config.bootnode_reseed_threshold = threshold;
+        self
+    }
+
+    /// The interval at which the routing table is checked to decide whether the bootnodes are
+    /// contacted again.
+    pub fn bootnode_reseed_interval(&mut self, interval: Duration) -> &mut Self {
+        self.config.bootnode_reseed_interval = interval;
+        self
+    }
+
     /// A rate limiter for limiting inbound requests.
     pub fn filter_rate_limiter(&mut self, rate_limiter: Option<RateLimiter>) -> &mut Self {
         self.config.filter_rate_limiter = rate_limiter;
         self
     }
 
+    /// A rate limiter for limiting decrypted inbound requests per request type.
+    pub fn request_rate_limiter(&mut self, rate_limiter: Option<RequestRateLimiter>) -> &mut Self {
+        self.config.request_rate_limiter = rate_limiter;
+        self
+    }
+
+    /// The maximum number of requests we have in flight to a single peer.
+    pub fn max_in_flight_requests_per_peer(&mut self, max: Option<usize>) -> &mut Self {
+        self.config.max_in_flight_requests_per_peer = max;
+        self
+    }
+
+    /// Derives request timeouts from the round-trip times measured to each peer.
+    pub fn adaptive_request_timeout(&mut self, enabled: bool) -> &mut Self {
+        self.config.adaptive_request_timeout = enabled;
+        self
+    }
+
+    /// The maximum number of requests we send per second, across all peers.
+    pub fn outbound_requests_per_second(&mut self, rate: Option<u32>) -> &mut Self {
+        self.config.outbound_requests_per_second = rate;
+        self
+    }
+
+    /// The time a request can wait in the outbound queue before it fails.
+    pub fn outbound_queue_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.outbound_queue_timeout = timeout;
+        self
+    }
+
+    /// The maximum number of requests waiting in the outbound queue.
+    pub fn max_queued_outbound_requests(&mut self, max: usize) -> &mut Self {
+        self.config.max_queued_outbound_requests = max;
+        self
+    }
+
+    /// The number of bytes of NODES responses that can be sent per minute to an address that has
+    /// not answered one of our PINGs.
+    pub fn unverified_response_byte_budget(&mut self, budget: Option<usize>) -> &mut Self {
+        self.config.unverified_response_byte_budget = budget;
+        self
+    }
+
+    /// Sets the policy to automatically ban misbehaving peers.
+    pub fn ban_policy(&mut self, ban_policy: Option<BanPolicy>) -> &mut Self {
+        self.config.ban_policy = ban_policy;
+        self
+    }
+
     /// If the filter is enabled, sets the maximum number of nodes per IP before banning
     /// the IP.
     pub fn filter_max_nodes_per_ip(&mut self, max_nodes_per_ip: Option<usize>) -> &mut Self {
@@ -330,6 +636,36 @@ impl ConfigBuilder {
         self
     }
 
+    /// Sets the number of connected peers asked to dial back our address after our ENR socket is
+    /// updated.
+    pub fn dial_back_peers(&mut self, peers: Option<usize>) -> &mut Self {
+        self.config.dial_back_peers = peers;
+        self
+    }
+
+    /// Whether to dial back peers that request it.
+    pub fn serve_dial_back(&mut self, serve_dial_back: bool) -> &mut Self {
+        self.config.serve_dial_back = serve_dial_back;
+        self
+    }
+
+    /// Replaces the implementation of the built-in identity scheme of the same name, `v4` or
+    /// `ed25519`. Fails with [`Error::KeyTypeNotSupported`] for any other scheme, as node records
+    /// do not carry keys of other schemes.
+    pub fn identity_scheme(
+        &mut self,
+        scheme: impl IdentityScheme + 'static,
+    ) -> Result<&mut Self, Error> {
+        self.config.identity_schemes.register(scheme)?;
+        Ok(self)
+    }
+
+    /// Persists the local ENR across restarts in the given store.
+    pub fn enr_store(&mut self, store: impl EnrStore + 'static) -> &mut Self {
+        self.config.enr_store = Some(Arc::new(store));
+        self
+    }
+
     /// A custom executor which can spawn the discv5 tasks. This must be a tokio runtime, with
     /// timing support.
     pub fn executor(&mut self, executor: Box<dyn Executor + Send + Sync>) -> &mut Self {
@@ -352,11 +688,22 @@ impl ConfigBuilder {
 
         // If enr-update is set to false, then it is non-intuitive for discv5 to revoke ENR details
         // when determining NAT status. So we will not do this.
-        if self.config.enr_update {
+        if !self.config.enr_update {
+            self.config.auto_nat_listen_duration = None;
+        }
+
+        // If the sockets of both IP versions are pinned, there is nothing learned from peers that
+        // could be revoked.
+        if !(self.config.ipv4_external_address.is_auto()
+            || self.config.ipv6_external_address.is_auto())
+        {
             self.config.auto_nat_listen_duration = None;
         }
 
         assert!(self.config.incoming_bucket_limit <= MAX_NODES_PER_BUCKET);
+        assert_ne!(self.config.max_in_flight_requests_per_peer, Some(0));
+        assert_ne!(self.config.outbound_requests_per_second, Some(0));
+        assert!(!self.config.bootnode_reseed_interval.is_zero());
 
         self.config.clone()
     }
@@ -368,12 +715,18 @@ impl std::fmt::Debug for Config {
             .field("filter_enabled", &self.enable_packet_filter)
             .field("request_timeout", &self.request_timeout)
             .field("vote_duration", &self.vote_duration)
+            .field("vote_ipv6_subnet_prefix", &self.vote_ipv6_subnet_prefix)
             .field("query_timeout", &self.query_timeout)
             .field("query_peer_timeout", &self.query_peer_timeout)
             .field("request_retries", &self.request_retries)
+            .field("adaptive_request_timeout", &self.adaptive_request_timeout)
             .field("session_timeout", &self.session_timeout)
             .field("session_cache_capacity", &self.session_cache_capacity)
             .field("enr_update", &self.enr_update)
+            .field("enr_update_transports", &self.enr_update_transports)
+            .field("enr_update_share_udp_port", &self.enr_update_share_udp_port)
+            .field("ipv4_external_address", &self.ipv4_external_address)
+            .field("ipv6_external_address", &self.ipv6_external_address)
             .field("query_parallelism", &self.query_parallelism)
             .field("report_discovered_peers", &self.report_discovered_peers)
             .field("ip_limit", &self.ip_limit)
@@ -382,6 +735,40 @@ impl std::fmt::Debug for Config {
             .field("ip_limit", &self.ip_limit)
             .field("incoming_bucket_limit", &self.incoming_bucket_limit)
             .field("ping_interval", &self.ping_interval)
+            .field(
+                "enr_update_propagation_window",
+                &self.enr_update_propagation_window,
+            )
+            .field(
+                "enr_update_propagation_min_interval",
+                &self.enr_update_propagation_min_interval,
+            )
+            .field("table_refresh_interval", &self.table_refresh_interval)
+            .field("bootnodes", &self.bootnodes)
+            .field("bootnode_reseed_threshold", &self.bootnode_reseed_threshold)
+            .field("bootnode_reseed_interval", &self.bootnode_reseed_interval)
+            .field(
+                "max_in_flight_requests_per_peer",
+                &self.max_in_flight_requests_per_peer,
+            )
+            .field(
+                "outbound_requests_per_second",
+                &self.outbound_requests_per_second,
+            )
+            .field("outbound_queue_timeout", &self.outbound_queue_timeout)
+            .field(
+                "max_queued_outbound_requests",
+                &self.max_queued_outbound_requests,
+            )
+            .field(
+                "unverified_response_byte_budget",
+                &self.unverified_response_byte_budget,
+            )
+            .field("ban_policy", &self.ban_policy)
+            .field("dial_back_peers", &self.dial_back_peers)
+            .field("serve_dial_back", &self.serve_dial_back)
+            .field("identity_schemes", &self.identity_schemes)
+            .field("enr_store", &self.enr_store.is_some())
             .field("ban_duration", &self.ban_duration)
             .field("listen_config", &self.listen_config)
             .finish()
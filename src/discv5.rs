@@ -13,17 +13,24 @@
 //! The server can be shutdown using the [`Discv5::shutdown`] function.
 
 use crate::{
+    enr_store::{self, EnrWriter},
     error::{Error, QueryError, RequestError},
+    external_address,
+    handler::{PeerRtt, RttTable},
     kbucket::{
         self, ConnectionDirection, ConnectionState, FailureReason, InsertResult, KBucketsTable,
         NodeStatus, UpdateResult,
     },
     node_info::{NodeAddress, NodeContact},
-    service::{QueryKind, Service, ServiceRequest, TalkRequest},
+    peer_stats::{PeerStats, PeerStatsStore},
+    permit_ban::{BanEntry, BanSource},
+    service::{ProbeResult, QueryKind, Service, ServiceRequest, TalkRequest},
+    signer::{rotate_local_key, swap_local_enr, update_enr},
     socket::UnrecognizedFrame,
-    Config, Enr, IpMode,
+    Config, Enr, EnrUpdate, IpMode, IpSubnet, PermitBanList, Signer, Transport,
 };
-use enr::{CombinedKey, EnrKey, Error as EnrError, NodeId};
+use enr::{CombinedKey, Error as EnrError, NodeId};
+use futures::stream::{self, StreamExt};
 use parking_lot::RwLock;
 use std::{
     future::Future,
@@ -50,6 +57,20 @@ lazy_static! {
 
 pub(crate) mod test;
 
+/// An ENR found by [`Discv5::find_node_verified`].
+#[derive(Debug, Clone)]
+pub struct VerifiedEnr {
+    /// The ENR found.
+    pub enr: Enr,
+    /// Whether the node responded to a PING.
+    pub alive: bool,
+    /// The time it took the node to respond to the PING.
+    pub rtt: Option<Duration>,
+    /// The peers that reported the ENR during the query. This is empty if the ENR was only known
+    /// from the local routing table.
+    pub reported_by: Vec<NodeId>,
+}
+
 /// Events that can be produced by the `Discv5` event stream.
 #[derive(Debug)]
 #[non_exhaustive]
@@ -81,6 +102,37 @@ pub enum Event {
     TalkRequest(TalkRequest),
     /// A received unrecognized frame.
     UnrecognizedFrame(UnrecognizedFrame),
+    /// An automatic lookup refreshing an under-filled bucket of the routing table has completed.
+    TableRefreshed {
+        /// The log2-distance of the refreshed bucket.
+        distance: u64,
+        /// The number of nodes found by the lookup.
+        found: usize,
+    },
+    /// A request to a configured bootnode has failed.
+    BootnodeUnreachable {
+        /// The node id of the bootnode.
+        node_id: NodeId,
+        /// The socket the request was sent to.
+        socket: SocketAddr,
+    },
+    /// A round of pings propagating an update of the local ENR has ended. See
+    /// [`crate::ConfigBuilder::enr_update_propagation_window`].
+    EnrUpdatePropagated {
+        /// The sequence number of the local ENR that was propagated.
+        seq: u64,
+        /// The number of peers that were pinged.
+        pinged: usize,
+        /// The number of peers that requested the updated ENR.
+        acknowledged: usize,
+    },
+    /// The local key has been rotated. All sessions have been dropped.
+    KeyRotated {
+        /// The node id of the replaced key.
+        old_node_id: NodeId,
+        /// The node id of the new key.
+        new_node_id: NodeId,
+    },
 }
 
 /// The main Discv5 Service struct. This provides the user-level API for performing queries and
@@ -95,23 +147,73 @@ pub struct Discv5 {
     kbuckets: Arc<RwLock<KBucketsTable<NodeId, Enr>>>,
     /// The local ENR of the server.
     local_enr: Arc<RwLock<Enr>>,
-    /// The key associated with the local ENR, required for updating the local ENR.
-    enr_key: Arc<RwLock<CombinedKey>>,
+    /// The signer holding the key associated with the local ENR, required for updating the local
+    /// ENR.
+    enr_key: Arc<RwLock<Arc<dyn Signer>>>,
+    /// Writes updates of the local ENR to the configured ENR store.
+    enr_writer: Option<EnrWriter>,
+    /// The round-trip times measured to peers.
+    rtts: Arc<RwLock<RttTable>>,
+    /// Statistics about the behaviour of peers.
+    peer_stats: Arc<RwLock<PeerStatsStore>>,
     // Type of socket we are using
     ip_mode: IpMode,
 }
 
 impl Discv5 {
-    pub fn new(
-        local_enr: Enr,
-        enr_key: CombinedKey,
+    pub fn new(local_enr: Enr, enr_key: CombinedKey, config: Config) -> Result<Self, &'static str> {
+        Self::with_signer(local_enr, Box::new(enr_key), config)
+    }
+
+    /// Creates a discv5 instance whose secret key is held by the given [`Signer`], for example a
+    /// separate signing process.
+    pub fn with_signer(
+        mut local_enr: Enr,
+        enr_key: Box<dyn Signer>,
         mut config: Config,
     ) -> Result<Self, &'static str> {
         // ensure the keypair matches the one that signed the enr.
-        if local_enr.public_key() != enr_key.public() {
+        if local_enr.public_key() != enr_key.public_key() {
             return Err("Provided keypair does not match the provided ENR");
         }
 
+        // Continue from the local ENR of the previous run, if it was stored.
+        if let Some(store) = config.enr_store.as_ref() {
+            match store.load() {
+                Ok(Some(stored)) => {
+                    let reconciled = enr_store::reconcile(
+                        local_enr,
+                        stored.clone(),
+                        &config.listen_config,
+                        enr_key.as_ref(),
+                    )
+                    .map_err(|_| "Failed to sign the local ENR")?;
+                    if reconciled != stored {
+                        enr_store::persist(Some(store), &reconciled);
+                    }
+                    local_enr = reconciled;
+                }
+                Ok(None) => enr_store::persist(Some(store), &local_enr),
+                Err(error) => warn!(%error, "Failed to load the stored local ENR"),
+            }
+        }
+
+        // Advertise the pinned external sockets, and remove those never advertised.
+        let pinned_update = external_address::pinned_enr_update(
+            &local_enr,
+            config.ipv4_external_address,
+            config.ipv6_external_address,
+            &config.enr_update_transports,
+            config.enr_update_share_udp_port,
+        );
+        if let Some(updated) = pinned_update
+            .apply(&local_enr, enr_key.as_ref())
+            .map_err(|_| "Failed to sign the local ENR")?
+        {
+            enr_store::persist(config.enr_store.as_ref(), &updated);
+            local_enr = updated;
+        }
+
         // If an executor is not provided, assume a current tokio runtime is running. If not panic.
         if config.executor.is_none() {
             config.executor = Some(Box::<crate::executor::TokioExecutor>::default());
@@ -130,7 +232,7 @@ impl Discv5 {
         };
 
         let local_enr = Arc::new(RwLock::new(local_enr));
-        let enr_key = Arc::new(RwLock::new(enr_key));
+        let enr_key = Arc::new(RwLock::new(Arc::from(enr_key)));
         let kbuckets = Arc::new(RwLock::new(KBucketsTable::new(
             local_enr.read().node_id().into(),
             Duration::from_secs(60),
@@ -138,11 +240,16 @@ impl Discv5 {
             table_filter,
             bucket_filter,
         )));
+        let rtts = Arc::new(RwLock::new(RttTable::new(config.session_cache_capacity)));
+        let peer_stats = Arc::new(RwLock::new(PeerStatsStore::new(
+            config.session_cache_capacity,
+        )));
 
         // Update the PermitBan list based on initial configuration
         *PERMIT_BAN_LIST.write() = config.permit_ban_list.clone();
 
         let ip_mode = IpMode::new_from_listen_config(&config.listen_config);
+        let enr_writer = config.enr_store.clone().map(EnrWriter::new);
 
         Ok(Discv5 {
             config,
@@ -151,6 +258,9 @@ impl Discv5 {
             kbuckets,
             local_enr,
             enr_key,
+            enr_writer,
+            rtts,
+            peer_stats,
             ip_mode,
         })
     }
@@ -166,7 +276,10 @@ impl Discv5 {
         let (service_exit, service_channel) = Service::spawn(
             self.local_enr.clone(),
             self.enr_key.clone(),
+            self.enr_writer.clone(),
             self.kbuckets.clone(),
+            self.rtts.clone(),
+            self.peer_stats.clone(),
             self.config.clone(),
         )
         .await?;
@@ -329,6 +442,104 @@ impl Discv5 {
         None
     }
 
+    /// Returns the round-trip time statistics of a peer, if any round trips to it were measured.
+    ///
+    /// Round trips are measured for requests answered without retransmissions or handshakes.
+    pub fn peer_rtt(&self, node_id: &NodeId) -> Option<PeerRtt> {
+        self.rtts.read().get(node_id)
+    }
+
+    /// Returns the statistics recorded about a peer, if we have interacted with it recently.
+    pub fn peer_stats(&self, node_id: &NodeId) -> Option<PeerStats> {
+        let mut stats = self.peer_stats.read().get(node_id)?;
+        stats.rtt = self.rtts.read().get(node_id);
+        Some(stats)
+    }
+
+    /// Returns the statistics of all peers we have interacted with recently.
+    pub fn all_peer_stats(&self) -> Vec<(NodeId, PeerStats)> {
+        let rtts = self.rtts.read();
+        self.peer_stats
+            .read()
+            .iter()
+            .map(|(node_id, stats)| {
+                let mut stats = stats.clone();
+                stats.rtt = rtts.get(node_id);
+                (*node_id, stats)
+            })
+            .collect()
+    }
+
+    /// Resolves the most recent ENR of a node.
+    ///
+    /// The routing table and the ENRs of nodes we have active sessions with are checked first. If
+    /// the node is known, its ENR is requested directly from it. Otherwise, or if the node does not
+    /// respond, an iterative lookup targeting the `NodeId` is performed and the ENR is requested
+    /// from the node found. The verified ENR with the highest sequence number is returned, or
+    /// `None` if the node could not be found.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn resolve(
+        &self,
+        node_id: NodeId,
+    ) -> impl Future<Output = Result<Option<Enr>, QueryError>> + 'static {
+        let channel = self.clone_channel();
+        let ip_mode = self.ip_mode;
+
+        async move {
+            let channel = channel.map_err(|_| QueryError::ServiceNotStarted)?;
+
+            // Check the routing table and active sessions
+            let (callback_send, callback_recv) = oneshot::channel();
+            channel
+                .send(ServiceRequest::FindEnr(node_id, callback_send))
+                .await
+                .map_err(|_| QueryError::ChannelFailed("Service channel closed".into()))?;
+            let known_enr = callback_recv
+                .await
+                .map_err(|e| QueryError::ChannelFailed(e.to_string()))?;
+
+            // If the node is known, ask it directly for its latest ENR
+            if let Some(enr) = known_enr.clone() {
+                if let Some(latest_enr) = request_own_enr(&channel, enr, ip_mode).await {
+                    return Ok(freshest_enr(known_enr, latest_enr));
+                }
+            }
+
+            // Otherwise search for the node in the DHT
+            let (callback_send, callback_recv) = oneshot::channel();
+            let query_kind = QueryKind::FindNode {
+                target_node: node_id,
+            };
+            channel
+                .send(ServiceRequest::StartQuery(query_kind, callback_send))
+                .await
+                .map_err(|_| QueryError::ChannelFailed("Service channel closed".into()))?;
+            let found_enrs = callback_recv
+                .await
+                .map_err(|e| QueryError::ChannelFailed(e.to_string()))?;
+
+            let known_seq = known_enr.as_ref().map(|enr| enr.seq());
+            let mut resolved_enr = found_enrs
+                .into_iter()
+                .filter(|enr| enr.node_id() == node_id)
+                .fold(known_enr, freshest_enr);
+
+            // Only contact the node again if the lookup found a different record than the one
+            // that already failed to respond.
+            if let Some(enr) = resolved_enr.clone() {
+                if known_seq.is_none_or(|seq| enr.seq() > seq) {
+                    if let Some(latest_enr) = request_own_enr(&channel, enr, ip_mode).await {
+                        resolved_enr = freshest_enr(resolved_enr, latest_enr);
+                    }
+                }
+            }
+
+            Ok(resolved_enr)
+        }
+    }
+
     /// Sends a PING request to a node.
     pub fn send_ping(
         &self,
@@ -354,16 +565,48 @@ impl Discv5 {
         }
     }
 
+    /// Probes whether a node is reachable by establishing a session with it and sending a PING.
+    ///
+    /// Unlike [`Discv5::send_ping`], the node is not inserted into the routing table. The result
+    /// reports which step of the probe failed, if any.
+    pub fn probe(
+        &self,
+        enr: Enr,
+    ) -> impl Future<Output = Result<ProbeResult, RequestError>> + 'static {
+        let (callback_send, callback_recv) = oneshot::channel();
+        let channel = self.clone_channel();
+
+        async move {
+            let channel = channel.map_err(|_| RequestError::ServiceNotStarted)?;
+
+            let event = ServiceRequest::Probe(enr, callback_send);
+
+            // send the request
+            channel
+                .send(event)
+                .await
+                .map_err(|_| RequestError::ChannelFailed("Service channel closed".into()))?;
+            // await the response
+            callback_recv
+                .await
+                .map_err(|e| RequestError::ChannelFailed(e.to_string()))
+        }
+    }
+
     /// Bans a node from the server. This will remove the node from the routing table if it exists
     /// and block all incoming packets from the node until the timeout specified. Setting the
     /// timeout to `None` creates a permanent ban.
     pub fn ban_node(&self, node_id: &NodeId, duration_of_ban: Option<Duration>) {
         let time_to_unban = duration_of_ban.map(|v| Instant::now() + v);
         self.remove_node(node_id);
-        PERMIT_BAN_LIST
-            .write()
-            .ban_nodes
-            .insert(*node_id, time_to_unban);
+        PERMIT_BAN_LIST.write().ban_nodes.insert(
+            *node_id,
+            BanEntry::new(
+                BanSource::Manual,
+                "Banned by the application",
+                time_to_unban,
+            ),
+        );
     }
 
     /// Removes a banned node from the banned list.
@@ -384,7 +627,14 @@ impl Discv5 {
     /// Bans an IP from the server.  This will block all incoming packets from the IP.
     pub fn ban_ip(&self, ip: std::net::IpAddr, duration_of_ban: Option<Duration>) {
         let time_to_unban = duration_of_ban.map(|v| Instant::now() + v);
-        PERMIT_BAN_LIST.write().ban_ips.insert(ip, time_to_unban);
+        PERMIT_BAN_LIST.write().ban_ips.insert(
+            ip,
+            BanEntry::new(
+                BanSource::Manual,
+                "Banned by the application",
+                time_to_unban,
+            ),
+        );
     }
 
     /// Removes a banned IP from the banned list.
@@ -402,40 +652,55 @@ impl Discv5 {
         PERMIT_BAN_LIST.write().permit_ips.remove(ip);
     }
 
-    /// Updates the local ENR TCP/UDP socket.
-    pub fn update_local_enr_socket(&self, socket_addr: SocketAddr, is_tcp: bool) -> bool {
-        let mut local_enr = self.local_enr.write();
-        match (is_tcp, socket_addr) {
-            (false, SocketAddr::V4(specific_socket_addr)) => {
-                if Some(specific_socket_addr) != local_enr.udp4_socket() {
-                    return local_enr
-                        .set_udp_socket(socket_addr, &self.enr_key.read())
-                        .is_ok();
-                }
-            }
-            (true, SocketAddr::V4(specific_socket_addr)) => {
-                if Some(specific_socket_addr) != local_enr.tcp4_socket() {
-                    return local_enr
-                        .set_tcp_socket(socket_addr, &self.enr_key.read())
-                        .is_ok();
-                }
-            }
-            (false, SocketAddr::V6(specific_socket_addr)) => {
-                if Some(specific_socket_addr) != local_enr.udp6_socket() {
-                    return local_enr
-                        .set_udp_socket(socket_addr, &self.enr_key.read())
-                        .is_ok();
-                }
-            }
-            (true, SocketAddr::V6(specific_socket_addr)) => {
-                if Some(specific_socket_addr) != local_enr.tcp6_socket() {
-                    return local_enr
-                        .set_tcp_socket(socket_addr, &self.enr_key.read())
-                        .is_ok();
-                }
+    /// Bans all IPs of a subnet from the server. This will block all incoming packets from IPs
+    /// within the subnet.
+    pub fn ban_subnet(&self, subnet: IpSubnet, duration_of_ban: Option<Duration>) {
+        let time_to_unban = duration_of_ban.map(|v| Instant::now() + v);
+        PERMIT_BAN_LIST.write().ban_subnets.insert(
+            subnet,
+            BanEntry::new(
+                BanSource::Manual,
+                "Banned by the application",
+                time_to_unban,
+            ),
+        );
+    }
+
+    /// Removes a banned subnet from the banned list. IPs within the subnet that were banned
+    /// individually remain banned.
+    pub fn ban_subnet_remove(&self, subnet: &IpSubnet) {
+        PERMIT_BAN_LIST.write().ban_subnets.remove(subnet);
+    }
+
+    /// Permits all IPs of a subnet, allowing all packets from the subnet to bypass the packet
+    /// filter.
+    pub fn permit_subnet(&self, subnet: IpSubnet) {
+        PERMIT_BAN_LIST.write().permit_subnets.insert(subnet, ());
+    }
+
+    /// Removes a subnet from the permit list.
+    pub fn permit_subnet_remove(&self, subnet: &IpSubnet) {
+        PERMIT_BAN_LIST.write().permit_subnets.remove(subnet);
+    }
+
+    /// Returns a copy of the current permit and ban list, for example to
+    /// [`export`](PermitBanList::export) it before shutting down.
+    pub fn permit_ban_list(&self) -> PermitBanList {
+        PERMIT_BAN_LIST.read().clone()
+    }
+
+    /// Updates the socket of a transport in the local ENR. The IP address of the socket's version
+    /// is shared by all transports. Returns `true` if the ENR was updated.
+    pub fn update_local_enr_socket(&self, socket_addr: SocketAddr, transport: Transport) -> bool {
+        match self.update_local_enr(|update| {
+            update.socket(socket_addr, &transport);
+        }) {
+            Ok(updated) => updated,
+            Err(error) => {
+                warn!(%socket_addr, ?transport, %error, "Failed to update the local ENR socket");
+                false
             }
         }
-        false
     }
 
     /// Allows application layer to insert an arbitrary field into the local ENR.
@@ -444,12 +709,114 @@ impl Discv5 {
         key: &str,
         value: &T,
     ) -> Result<Option<Vec<u8>>, EnrError> {
-        self.local_enr
-            .write()
-            .insert(key, value, &self.enr_key.read())
+        self.apply_enr_update(|enr, enr_key| enr.insert(key, value, enr_key))
             .map(|v| v.map(|v| v.to_vec()))
     }
 
+    /// Applies multiple changes to the local ENR with a single signature and sequence number
+    /// increment.
+    ///
+    /// ```ignore
+    /// discv5.update_local_enr(|update| {
+    ///     update
+    ///         .insert("eth2", &fork_digest)
+    ///         .insert("attnets", &attnets)
+    ///         .quic_socket(quic_socket);
+    /// })?;
+    /// ```
+    ///
+    /// Returns whether the record changed. The local ENR is left unchanged if the update fails,
+    /// for example because the updated record exceeds the maximum size of 300 bytes.
+    pub fn update_local_enr(&self, update: impl FnOnce(&mut EnrUpdate)) -> Result<bool, EnrError> {
+        let mut changes = EnrUpdate::default();
+        update(&mut changes);
+
+        let Some((updated, ())) = swap_local_enr(&self.local_enr, &self.enr_key, |enr, signer| {
+            Ok(changes.apply(enr, signer)?.map(|updated| (updated, ())))
+        })?
+        else {
+            return Ok(false);
+        };
+        self.local_enr_updated(updated);
+        Ok(true)
+    }
+
+    /// Applies an update to the local ENR and signs it.
+    fn apply_enr_update<T>(
+        &self,
+        mut update: impl FnMut(&mut Enr, &CombinedKey) -> Result<T, EnrError>,
+    ) -> Result<T, EnrError> {
+        let (updated, result) = swap_local_enr(&self.local_enr, &self.enr_key, |enr, signer| {
+            let mut updated = enr.clone();
+            let result = update_enr(&mut updated, signer, &mut update)?;
+            Ok(Some((updated, result)))
+        })?
+        .expect("updates always produce a record");
+        self.local_enr_updated(updated);
+        Ok(result)
+    }
+
+    /// Queues the updated local ENR to be written to the ENR store and informs the service of the
+    /// update, to propagate it to peers.
+    fn local_enr_updated(&self, local_enr: Enr) {
+        if let Some(writer) = self.enr_writer.as_ref() {
+            writer.write(local_enr);
+        }
+        if let Some(channel) = self.service_channel.as_ref() {
+            if let Err(e) = channel.try_send(ServiceRequest::LocalEnrUpdated) {
+                debug!(error = %e, "Failed to inform the service of the local ENR update");
+            }
+        }
+    }
+
+    /// Replaces the key of the local node, changing its node id.
+    ///
+    /// The local ENR is signed with the new key and the routing table is rebuilt around the new
+    /// node id, keeping the known nodes. All sessions are bound to the old node id and are dropped,
+    /// so all nodes of the routing table are marked as disconnected and requests in progress fail
+    /// with [`RequestError::KeyRotated`]. The handler switches the key, the ENR and the node id in
+    /// the same step in which it drops the sessions, and the new node id is returned once the
+    /// rotation is complete.
+    pub async fn rotate_key(&self, new_key: impl Signer + 'static) -> Result<NodeId, EnrError> {
+        let new_key: Arc<dyn Signer> = Arc::new(new_key);
+        let Some(channel) = self.service_channel.as_ref() else {
+            // Without a running service there are no sessions, the key is replaced directly.
+            return self.replace_key(new_key);
+        };
+
+        let (callback_send, callback_recv) = oneshot::channel();
+        if channel
+            .send(ServiceRequest::RotateKey(new_key.clone(), callback_send))
+            .await
+            .is_err()
+        {
+            warn!("Failed to send the new key to the service");
+            return self.replace_key(new_key);
+        }
+        match callback_recv.await {
+            Ok(result) => result,
+            Err(_) => {
+                // The service stopped. The key may have been replaced before it did.
+                warn!("The service stopped while rotating the local key");
+                if self.local_enr.read().public_key() == new_key.public_key() {
+                    return Ok(self.local_enr.read().node_id());
+                }
+                self.replace_key(new_key)
+            }
+        }
+    }
+
+    /// Replaces the key of the local node while the service is not running.
+    fn replace_key(&self, new_key: Arc<dyn Signer>) -> Result<NodeId, EnrError> {
+        let updated = rotate_local_key(&self.local_enr, &self.enr_key, new_key)?;
+        let new_node_id = updated.node_id();
+        self.kbuckets.write().set_local_key(new_node_id.into());
+        if let Some(writer) = self.enr_writer.as_ref() {
+            writer.write(updated);
+        }
+        Ok(new_node_id)
+    }
+
     /// Returns an iterator over all ENR node IDs of nodes currently contained in the routing table.
     pub fn table_entries_id(&self) -> Vec<NodeId> {
         self.kbuckets
@@ -658,6 +1025,108 @@ impl Discv5 {
         }
     }
 
+    /// Runs an iterative `FIND_NODE` request and verifies that the nodes found are alive.
+    ///
+    /// Each ENR found by the query is sent a PING, with at most `max_concurrent_pings` PINGs in
+    /// flight at once. The results are annotated with whether the node responded, the time it
+    /// took to respond (including establishing a session, if required) and the peers that
+    /// reported the ENR during the query. Responding nodes are ranked first, otherwise the
+    /// results are ordered by their distance to the target.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn find_node_verified(
+        &self,
+        target_node: NodeId,
+        max_concurrent_pings: usize,
+    ) -> impl Future<Output = Result<Vec<VerifiedEnr>, QueryError>> + 'static {
+        let channel = self.clone_channel();
+
+        async move {
+            let channel = channel.map_err(|_| QueryError::ServiceNotStarted)?;
+            let (callback_send, callback_recv) = oneshot::channel();
+
+            let event = ServiceRequest::StartReportedQuery(target_node, callback_send);
+            channel
+                .send(event)
+                .await
+                .map_err(|_| QueryError::ChannelFailed("Service channel closed".into()))?;
+
+            let found_enrs = callback_recv
+                .await
+                .map_err(|e| QueryError::ChannelFailed(e.to_string()))?;
+
+            let mut verified_enrs = stream::iter(found_enrs)
+                .map(|(enr, reported_by)| {
+                    let channel = channel.clone();
+                    async move {
+                        let (callback_send, callback_recv) = oneshot::channel();
+                        let sent_at = Instant::now();
+                        let pong = match channel
+                            .send(ServiceRequest::Ping(enr.clone(), Some(callback_send)))
+                            .await
+                        {
+                            Ok(()) => callback_recv.await.ok().and_then(Result::ok),
+                            Err(_) => None,
+                        };
+                        VerifiedEnr {
+                            enr,
+                            alive: pong.is_some(),
+                            rtt: pong.map(|_| sent_at.elapsed()),
+                            reported_by,
+                        }
+                    }
+                })
+                .buffered(max_concurrent_pings.max(1))
+                .collect::<Vec<_>>()
+                .await;
+
+            // The sort is stable, preserving the order by distance.
+            verified_enrs.sort_by_key(|verified_enr| !verified_enr.alive);
+            Ok(verified_enrs)
+        }
+    }
+
+    /// Runs an iterative `FIND_NODE` request towards a random target at the given log2-distance
+    /// from the local node.
+    ///
+    /// This will return the nodes found by the query that lie at exactly the requested distance.
+    /// This is useful to refresh a specific bucket of the routing table or to crawl the DHT.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn find_nodes_at_distance(
+        &self,
+        distance: u64,
+    ) -> impl Future<Output = Result<Vec<Enr>, QueryError>> + 'static {
+        let channel = self.clone_channel();
+        let local_key = kbucket::Key::from(self.local_enr.read().node_id());
+
+        async move {
+            let channel = channel.map_err(|_| QueryError::ServiceNotStarted)?;
+            let target_node = local_key
+                .random_at_log2_distance(distance)
+                .ok_or(QueryError::InvalidDistance(distance))?
+                .into_preimage();
+            let (callback_send, callback_recv) = oneshot::channel();
+
+            let query_kind = QueryKind::FindNode { target_node };
+
+            let event = ServiceRequest::StartQuery(query_kind, callback_send);
+            channel
+                .send(event)
+                .await
+                .map_err(|_| QueryError::ChannelFailed("Service channel closed".into()))?;
+
+            let mut found_enrs = callback_recv
+                .await
+                .map_err(|e| QueryError::ChannelFailed(e.to_string()))?;
+            found_enrs
+                .retain(|enr| local_key.log2_distance(&enr.node_id().into()) == Some(distance));
+            Ok(found_enrs)
+        }
+    }
+
     /// Starts a `FIND_NODE` request.
     ///
     /// This will return less than or equal to `num_nodes` ENRs which satisfy the
@@ -736,8 +1205,50 @@ impl Discv5 {
     }
 }
 
+/// Requests the ENR of a node from the node itself, via a FINDNODE request at distance 0. Only an
+/// ENR matching the node id of the requested node is returned.
+async fn request_own_enr(
+    channel: &mpsc::Sender<ServiceRequest>,
+    enr: Enr,
+    ip_mode: IpMode,
+) -> Option<Enr> {
+    let node_id = enr.node_id();
+    let node_contact = NodeContact::try_from_enr(enr, ip_mode).ok()?;
+
+    let (callback_send, callback_recv) = oneshot::channel();
+    let event = ServiceRequest::FindNodeDesignated(node_contact, vec![0], callback_send);
+    channel.send(event).await.ok()?;
+
+    match callback_recv.await.ok()? {
+        Ok(nodes) => nodes
+            .into_iter()
+            .filter(|enr| enr.node_id() == node_id)
+            .max_by_key(|enr| enr.seq()),
+        Err(error) => {
+            debug!(%node_id, %error, "Failed to request the ENR of the node");
+            None
+        }
+    }
+}
+
+/// Returns the ENR with the highest sequence number.
+fn freshest_enr(current: Option<Enr>, candidate: Enr) -> Option<Enr> {
+    match current {
+        Some(current) if current.seq() >= candidate.seq() => Some(current),
+        _ => Some(candidate),
+    }
+}
+
 impl Drop for Discv5 {
     fn drop(&mut self) {
         self.shutdown();
+        // Complete pending writes of the local ENR, so that a restarted server continues from the
+        // latest record. Waiting blocks the thread, which must not happen on a runtime thread;
+        // there the writes complete in the background.
+        if tokio::runtime::Handle::try_current().is_err() {
+            if let Some(writer) = self.enr_writer.as_ref() {
+                writer.flush();
+            }
+        }
     }
 }
@@ -1,12 +1,13 @@
 #![cfg(test)]
 
-use crate::{socket::ListenConfig, Discv5, *};
+use crate::{node_info::NodeContact, service::DIAL_BACK_PROTOCOL, socket::ListenConfig, Discv5, *};
 use alloy_rlp::bytes::Bytes;
 use enr::{k256, CombinedKey, Enr, EnrKey, NodeId};
 use rand_core::{RngCore, SeedableRng};
 use std::{
     collections::HashMap,
-    net::{Ipv4Addr, Ipv6Addr},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
 };
 
 fn init() {
@@ -818,3 +819,666 @@ async fn test_bucket_limits() {
     // Number of entries should be equal to `bucket_limit`.
     assert_eq!(discv5.kbuckets.read().iter_ref().count(), bucket_limit);
 }
+
+#[tokio::test]
+async fn test_bootnodes_seed_routing_table() {
+    init();
+    let ip: Ipv4Addr = "127.0.0.1".parse().unwrap();
+    let bootnode = build_nodes(1, 10100).await.remove(0);
+
+    // A bootnode that is not listening.
+    let unreachable_key = CombinedKey::generate_secp256k1();
+    let unreachable_enr = Enr::builder()
+        .ip4(ip)
+        .udp4(10102)
+        .build(&unreachable_key)
+        .unwrap();
+
+    let enr_key = CombinedKey::generate_secp256k1();
+    let listen_config = ListenConfig::Ipv4 { ip, port: 10101 };
+    let config = ConfigBuilder::new(listen_config)
+        .request_timeout(Duration::from_millis(200))
+        .add_bootnode(bootnode.local_enr())
+        .add_bootnode(unreachable_enr.clone())
+        .build();
+    let enr = Enr::builder().ip4(ip).udp4(10101).build(&enr_key).unwrap();
+    let mut discv5 = Discv5::new(enr, enr_key, config).unwrap();
+    discv5.start().await.unwrap();
+    let mut events = discv5.event_stream().await.unwrap();
+
+    let unreachable = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(Event::BootnodeUnreachable { node_id, .. }) = events.recv().await {
+                return node_id;
+            }
+        }
+    })
+    .await
+    .expect("Unreachable bootnode should be reported");
+    assert_eq!(unreachable, unreachable_enr.node_id());
+
+    let bootnode_id = bootnode.local_enr().node_id();
+    let table = discv5.table_entries();
+    assert!(table
+        .iter()
+        .any(|(node_id, _, status)| *node_id == bootnode_id && status.is_connected()));
+}
+
+/// Resolve the ENR of a node that is not in our routing table. The node has updated its ENR since
+/// it was added to the routing tables of its peers, so the most recent record must be requested
+/// from the node itself.
+#[tokio::test]
+async fn test_resolve() {
+    init();
+    let total_nodes = 8;
+    // Seed is chosen for a linear topology.
+    let keypairs = generate_deterministic_keypair(total_nodes + 1, 5);
+    let target_node_id = NodeId::from(keypairs[0].public());
+    let mut nodes = build_nodes_from_keypairs(keypairs, 10110).await;
+    let node_enrs: Vec<Enr<CombinedKey>> = nodes.iter().map(|n| n.local_enr()).collect();
+
+    // link the nodes together
+    for (node, previous_node_enr) in nodes.iter_mut().skip(1).zip(node_enrs.clone()) {
+        node.add_enr(previous_node_enr).unwrap();
+    }
+
+    // update the ENR of the target
+    assert!(update_enr(&mut nodes[0], "test", &"value"));
+    let latest_seq = nodes[0].local_enr().seq();
+    assert!(latest_seq > node_enrs[0].seq());
+
+    let last_node = nodes.last().unwrap();
+    assert!(last_node.find_enr(&target_node_id).is_none());
+    let resolved = last_node
+        .resolve(target_node_id)
+        .await
+        .unwrap()
+        .expect("The target is found");
+    assert_eq!(resolved.node_id(), target_node_id);
+    assert_eq!(resolved.seq(), latest_seq);
+
+    // An unknown node can not be resolved
+    let unknown = last_node.resolve(NodeId::random()).await.unwrap();
+    assert!(unknown.is_none());
+}
+
+#[tokio::test]
+async fn test_find_nodes_at_distance() {
+    init();
+    let keypairs = generate_deterministic_keypair(6, 1);
+    let mut nodes = build_nodes_from_keypairs(keypairs, 10130).await;
+    let querying_node = nodes.remove(0);
+    let bootstrap_node = nodes.remove(0);
+
+    // The querying node only knows the bootstrap node, which knows all remaining nodes.
+    querying_node.add_enr(bootstrap_node.local_enr()).unwrap();
+    for node in nodes.iter() {
+        bootstrap_node.add_enr(node.local_enr()).unwrap();
+    }
+
+    // The bootstrap node is asked for the distances around the random target. These always
+    // include a node at the queried distance if that distance differs from the distance of the
+    // bootstrap node.
+    let local_key: Key<NodeId> = querying_node.local_enr().node_id().into();
+    let bootstrap_distance = local_key
+        .log2_distance(&bootstrap_node.local_enr().node_id().into())
+        .unwrap();
+    let (target, distance) = nodes
+        .iter()
+        .map(|node| {
+            let node_id = node.local_enr().node_id();
+            (node_id, local_key.log2_distance(&node_id.into()).unwrap())
+        })
+        .find(|(_, distance)| *distance != bootstrap_distance)
+        .expect("Seed provides a node at a different distance than the bootstrap node");
+
+    let found = querying_node
+        .find_nodes_at_distance(distance)
+        .await
+        .unwrap();
+    assert!(found.iter().any(|enr| enr.node_id() == target));
+    assert!(found
+        .iter()
+        .all(|enr| local_key.log2_distance(&enr.node_id().into()) == Some(distance)));
+
+    for invalid_distance in [0, 257] {
+        assert_eq!(
+            querying_node
+                .find_nodes_at_distance(invalid_distance)
+                .await
+                .unwrap_err(),
+            QueryError::InvalidDistance(invalid_distance)
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_request_rate_limit() {
+    init();
+    let ip: Ipv4Addr = "127.0.0.1".parse().unwrap();
+    let requester = build_nodes(1, 10140).await.remove(0);
+
+    let enr_key = CombinedKey::generate_secp256k1();
+    let request_rate_limiter = RequestRateLimiterBuilder::new()
+        .findnode_n_every(1, Duration::from_secs(60))
+        .action(RateLimitedAction::RespondEmpty)
+        .build()
+        .unwrap();
+    let config = ConfigBuilder::new(ListenConfig::Ipv4 { ip, port: 10141 })
+        .request_rate_limiter(Some(request_rate_limiter))
+        .build();
+    let enr = Enr::builder().ip4(ip).udp4(10141).build(&enr_key).unwrap();
+    let mut limited = Discv5::new(enr, enr_key, config).unwrap();
+    limited.start().await.unwrap();
+
+    let found = requester
+        .find_node_designated_peer(limited.local_enr(), vec![0])
+        .await
+        .unwrap();
+    assert_eq!(found, vec![limited.local_enr()]);
+
+    // The quota is exhausted, the request is answered with an empty response.
+    let found = requester
+        .find_node_designated_peer(limited.local_enr(), vec![0])
+        .await
+        .unwrap();
+    assert!(found.is_empty());
+    assert!(limited.metrics().requests_rate_limited >= 1);
+
+    // Other request types are not limited.
+    assert!(requester.send_ping(limited.local_enr()).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_peer_rtt() {
+    init();
+    let nodes = build_nodes(2, 10150).await;
+    let remote_id = nodes[1].local_enr().node_id();
+    assert!(nodes[0].peer_rtt(&remote_id).is_none());
+
+    // The first request establishes the session and is not measured. The service may also ping
+    // the newly connected peer.
+    for _ in 0..3 {
+        nodes[0].send_ping(nodes[1].local_enr()).await.unwrap();
+    }
+
+    let rtt = nodes[0]
+        .peer_rtt(&remote_id)
+        .expect("Round trips are measured");
+    assert!(rtt.samples >= 2);
+    assert!(rtt.srtt < Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_peer_stats() {
+    init();
+    let nodes = build_nodes(2, 10160).await;
+    let remote_id = nodes[1].local_enr().node_id();
+    let local_id = nodes[0].local_enr().node_id();
+    assert!(nodes[0].peer_stats(&remote_id).is_none());
+
+    for _ in 0..3 {
+        nodes[0].send_ping(nodes[1].local_enr()).await.unwrap();
+    }
+
+    let stats = nodes[0].peer_stats(&remote_id).expect("Stats are recorded");
+    assert!(stats.requests_sent >= 3);
+    assert!(stats.responses_received >= 3);
+    assert_eq!(stats.invalid_responses, 0);
+    assert!(stats.last_seen.is_some());
+    assert!(stats.rtt.is_some());
+    assert!(nodes[0]
+        .all_peer_stats()
+        .iter()
+        .any(|(node_id, _)| *node_id == remote_id));
+
+    let remote_stats = nodes[1].peer_stats(&local_id).expect("Stats are recorded");
+    assert!(remote_stats.requests_received >= 3);
+}
+
+#[tokio::test]
+async fn test_find_node_verified() {
+    init();
+    let mut nodes = build_nodes(5, 10170).await;
+    let querying_node = nodes.remove(0);
+    let bootstrap_node = nodes.remove(0);
+
+    // The querying node only knows the bootstrap node, which knows all remaining nodes.
+    querying_node.add_enr(bootstrap_node.local_enr()).unwrap();
+    for node in nodes.iter() {
+        bootstrap_node.add_enr(node.local_enr()).unwrap();
+    }
+
+    let target = nodes[0].local_enr().node_id();
+    let results = querying_node.find_node_verified(target, 2).await.unwrap();
+
+    let bootstrap_id = bootstrap_node.local_enr().node_id();
+    let target_result = results
+        .iter()
+        .find(|result| result.enr.node_id() == target)
+        .expect("The target is found");
+    assert!(target_result.reported_by.contains(&bootstrap_id));
+    for result in results {
+        assert!(result.alive);
+        assert!(result.rtt.is_some());
+    }
+}
+
+#[tokio::test]
+async fn test_probe() {
+    init();
+    let nodes = build_nodes(2, 10180).await;
+    let remote_enr = nodes[1].local_enr();
+
+    let result = nodes[0].probe(remote_enr.clone()).await.unwrap();
+    assert!(result.session_established);
+    assert!(result.failure.is_none());
+    assert!(result.rtt.is_some());
+    assert_eq!(
+        result.observed_addr,
+        Some(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 10180))
+    );
+    // The probed node is not inserted into the routing table.
+    assert!(nodes[0].find_enr(&remote_enr.node_id()).is_none());
+
+    // Nothing listens on this port.
+    let enr_key = CombinedKey::generate_secp256k1();
+    let unreachable_enr = Enr::builder()
+        .ip4(Ipv4Addr::LOCALHOST)
+        .udp4(10182)
+        .build(&enr_key)
+        .unwrap();
+    let result = nodes[0].probe(unreachable_enr).await.unwrap();
+    assert!(!result.session_established);
+    assert!(result.observed_addr.is_none());
+    assert!(matches!(
+        result.failure,
+        Some((ProbeStep::Handshake, RequestError::Timeout))
+    ));
+
+    let enr_key = CombinedKey::generate_secp256k1();
+    let non_contactable_enr = Enr::builder().build(&enr_key).unwrap();
+    let result = nodes[0].probe(non_contactable_enr).await.unwrap();
+    assert!(matches!(result.failure, Some((ProbeStep::Contact, _))));
+}
+
+#[tokio::test]
+async fn test_dial_back() {
+    init();
+    let ip: Ipv4Addr = "127.0.0.1".parse().unwrap();
+    let mut nodes = Vec::new();
+    for port in 10190..10192 {
+        let enr_key = CombinedKey::generate_secp256k1();
+        let config = ConfigBuilder::new(ListenConfig::Ipv4 { ip, port })
+            .serve_dial_back(true)
+            .build();
+        let enr = Enr::builder().ip4(ip).udp4(port).build(&enr_key).unwrap();
+        let mut discv5 = Discv5::new(enr, enr_key, config).unwrap();
+        discv5.start().await.unwrap();
+        nodes.push(discv5);
+    }
+    let mut events = nodes[0].event_stream().await.unwrap();
+    let contact = NodeContact::try_from_enr(nodes[1].local_enr(), IpMode::Ip4).unwrap();
+
+    let request = alloy_rlp::encode(nodes[0].local_enr());
+    let response = nodes[0]
+        .talk_req(contact.clone(), DIAL_BACK_PROTOCOL.to_vec(), request)
+        .await
+        .unwrap();
+    assert_eq!(response, vec![1]);
+
+    // The dial-back arrives from a fresh node id.
+    let remote_id = nodes[1].local_enr().node_id();
+    let mut dial_back_sessions = 0;
+    while let Ok(Some(event)) =
+        tokio::time::timeout(Duration::from_millis(100), events.recv()).await
+    {
+        if let Event::SessionEstablished(enr, _) = event {
+            if enr.node_id() != remote_id {
+                dial_back_sessions += 1;
+            }
+        }
+    }
+    assert_eq!(dial_back_sessions, 1);
+
+    // Dial-backs to other nodes are refused.
+    let request = alloy_rlp::encode(nodes[1].local_enr());
+    let response = nodes[0]
+        .talk_req(contact.clone(), DIAL_BACK_PROTOCOL.to_vec(), request)
+        .await
+        .unwrap();
+    assert_eq!(response, vec![0]);
+}
+
+/// Ed25519 nodes establish sessions with each other and with secp256k1 nodes.
+#[tokio::test]
+async fn test_ed25519_sessions() {
+    init();
+    let keys = vec![
+        CombinedKey::generate_ed25519(),
+        CombinedKey::generate_ed25519(),
+        CombinedKey::generate_secp256k1(),
+    ];
+    let nodes = build_nodes_from_keypairs(keys, 10200).await;
+
+    for (from, to) in [(0, 1), (1, 0), (2, 0), (0, 2)] {
+        let result = nodes[from].probe(nodes[to].local_enr()).await.unwrap();
+        assert!(
+            result.session_established && result.failure.is_none(),
+            "session from node {} to node {} failed: {:?}",
+            from,
+            to,
+            result
+        );
+    }
+}
+
+/// A node whose key is held by a separate signing process establishes sessions as initiator and
+/// recipient, and signs updates of its ENR.
+#[cfg(unix)]
+#[tokio::test]
+async fn test_socket_signer() {
+    init();
+    let path = std::env::temp_dir().join(format!("discv5-signer-{}.sock", rand::random::<u64>()));
+    let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+    let key = CombinedKey::generate_ed25519();
+    let public_key = key.public();
+    signer::serve_signer(listener, key, Default::default());
+    let signer = signer::SocketSigner::connect(&path).unwrap();
+    assert_eq!(signer.public_key(), public_key);
+
+    // The initial ENR is built by the signing process.
+    let ip = Ipv4Addr::LOCALHOST;
+    let signing_key = CombinedKey::generate_secp256k1();
+    let mut enr = Enr::builder()
+        .ip4(ip)
+        .udp4(10210)
+        .build(&signing_key)
+        .unwrap();
+    crate::signer::update_enr(&mut enr, &signer, |enr, key| enr.set_seq(1, key)).unwrap();
+    assert_eq!(enr.public_key(), public_key);
+
+    let config = ConfigBuilder::new(ListenConfig::Ipv4 { ip, port: 10210 }).build();
+    let mut node = Discv5::with_signer(enr, Box::new(signer), config).unwrap();
+    node.start().await.unwrap();
+    let other = build_nodes(1, 10211).await.remove(0);
+
+    let result = other.probe(node.local_enr()).await.unwrap();
+    assert!(result.session_established && result.failure.is_none());
+    let result = node.probe(other.local_enr()).await.unwrap();
+    assert!(result.session_established && result.failure.is_none());
+
+    assert!(node.update_local_enr_socket("127.0.0.1:10212".parse().unwrap(), Transport::Tcp));
+    let enr = node.local_enr();
+    assert_eq!(enr.tcp4(), Some(10212));
+    assert_eq!(enr.public_key(), public_key);
+    assert!(enr.verify());
+
+    let _ = std::fs::remove_file(path);
+}
+
+/// Rotating the key changes the node id, keeps the routing table and drops sessions, which are
+/// re-established under the new identity.
+#[tokio::test]
+async fn test_rotate_key() {
+    init();
+    let nodes = build_nodes(2, 10220).await;
+    let result = nodes[0].probe(nodes[1].local_enr()).await.unwrap();
+    assert!(result.session_established && result.failure.is_none());
+    nodes[0].add_enr(nodes[1].local_enr()).unwrap();
+    let mut events = nodes[0].event_stream().await.unwrap();
+
+    let old_enr = nodes[0].local_enr();
+    let new_key = CombinedKey::generate_ed25519();
+    let new_public_key = new_key.public();
+    let new_node_id = nodes[0].rotate_key(new_key).await.unwrap();
+
+    let enr = nodes[0].local_enr();
+    assert_ne!(new_node_id, old_enr.node_id());
+    assert_eq!(enr.node_id(), new_node_id);
+    assert_eq!(enr.public_key(), new_public_key);
+    assert_eq!(enr.seq(), old_enr.seq() + 1);
+    assert_eq!(enr.udp4_socket(), old_enr.udp4_socket());
+    assert!(enr.verify());
+    assert_eq!(
+        nodes[0].table_entries_id(),
+        vec![nodes[1].local_enr().node_id()]
+    );
+
+    let event = tokio::time::timeout(Duration::from_secs(1), events.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(
+        event,
+        Event::KeyRotated { old_node_id, new_node_id: id }
+            if old_node_id == old_enr.node_id() && id == new_node_id
+    ));
+
+    for (from, to) in [(0, 1), (1, 0)] {
+        let result = nodes[from].probe(nodes[to].local_enr()).await.unwrap();
+        assert!(
+            result.session_established && result.failure.is_none(),
+            "session from node {} to node {} failed: {:?}",
+            from,
+            to,
+            result
+        );
+    }
+
+    // Concurrent rotations are applied one after the other.
+    let seq = nodes[0].local_enr().seq();
+    let (first, second) = tokio::join!(
+        nodes[0].rotate_key(CombinedKey::generate_secp256k1()),
+        nodes[0].rotate_key(CombinedKey::generate_secp256k1())
+    );
+    let (first, second) = (first.unwrap(), second.unwrap());
+    assert_ne!(first, second);
+    assert!([first, second].contains(&nodes[0].local_enr().node_id()));
+    assert_eq!(nodes[0].local_enr().seq(), seq + 2);
+}
+
+/// The local ENR is persisted across restarts, so that its sequence number does not go backwards.
+#[tokio::test]
+async fn test_enr_store() {
+    init();
+    let path = std::env::temp_dir().join(format!("discv5-enr-{}.txt", rand::random::<u64>()));
+    let ip = Ipv4Addr::LOCALHOST;
+    let secret = CombinedKey::generate_secp256k1().encode();
+    let new_node = || {
+        let key = CombinedKey::secp256k1_from_bytes(&mut secret.clone()).unwrap();
+        let config = ConfigBuilder::new(ListenConfig::Ipv4 { ip, port: 10230 })
+            .enr_store(FileEnrStore::new(&path))
+            .build();
+        let enr = Enr::builder().ip4(ip).udp4(10230).build(&key).unwrap();
+        Discv5::new(enr, key, config).unwrap()
+    };
+
+    let node = new_node();
+    assert_eq!(node.local_enr().seq(), 1);
+    node.enr_insert("eth2", &[1u8].as_slice()).unwrap();
+    assert!(node.update_local_enr_socket("127.0.0.1:10231".parse().unwrap(), Transport::Tcp));
+    assert_eq!(node.local_enr().seq(), 3);
+    drop(node);
+
+    // The configured record differs from the stored one, so the sequence number is increased.
+    let node = new_node();
+    assert_eq!(node.local_enr().seq(), 4);
+    assert_eq!(node.local_enr().tcp4(), None);
+    node.enr_insert("eth2", &[1u8].as_slice()).unwrap();
+    assert!(node.update_local_enr_socket("127.0.0.1:10231".parse().unwrap(), Transport::Tcp));
+    let last_enr = node.local_enr();
+    // Updates are written in the background.
+    node.enr_writer.as_ref().unwrap().flush();
+    assert_eq!(
+        FileEnrStore::new(&path).load().unwrap(),
+        Some(last_enr.clone())
+    );
+    drop(node);
+
+    // Restarting without changes keeps the stored record.
+    let node = new_node();
+    let enr = node.local_enr();
+    assert_eq!(enr.seq(), last_enr.seq() + 1);
+    drop(node);
+    assert_eq!(new_node().local_enr(), enr);
+
+    let _ = std::fs::remove_file(path);
+}
+
+/// Updates of the local ENR are propagated to peers immediately, and the peers requesting the
+/// updated record are reported.
+#[tokio::test]
+async fn test_enr_update_propagation() {
+    init();
+    let ip = Ipv4Addr::LOCALHOST;
+    let enr_key = CombinedKey::generate_secp256k1();
+    let config = ConfigBuilder::new(ListenConfig::Ipv4 { ip, port: 10240 })
+        .enr_update_propagation_window(Some(Duration::from_millis(200)))
+        .build();
+    let enr = Enr::builder().ip4(ip).udp4(10240).build(&enr_key).unwrap();
+    let mut node = Discv5::new(enr, enr_key, config).unwrap();
+    node.start().await.unwrap();
+    let mut events = node.event_stream().await.unwrap();
+
+    let peers = build_nodes(2, 10241).await;
+    for peer in &peers {
+        peer.add_enr(node.local_enr()).unwrap();
+        peer.send_ping(node.local_enr()).await.unwrap();
+    }
+
+    node.enr_insert("eth2", &[1u8].as_slice()).unwrap();
+    let seq = node.local_enr().seq();
+    let event = loop {
+        match tokio::time::timeout(Duration::from_secs(3), events.recv())
+            .await
+            .unwrap()
+            .unwrap()
+        {
+            event @ Event::EnrUpdatePropagated { .. } => break event,
+            _ => continue,
+        }
+    };
+    assert!(matches!(
+        event,
+        Event::EnrUpdatePropagated { seq: propagated, pinged: 2, acknowledged: 2 } if propagated == seq
+    ));
+    for peer in &peers {
+        assert_eq!(
+            peer.find_enr(&node.local_enr().node_id()).unwrap().seq(),
+            seq
+        );
+    }
+}
+
+/// Multiple fields of the local ENR are updated with a single sequence number increment.
+#[tokio::test]
+async fn test_update_local_enr() {
+    init();
+    let node = build_nodes(1, 10250).await.remove(0);
+    let enr = node.local_enr();
+
+    let updated = node
+        .update_local_enr(|update| {
+            update
+                .insert("eth2", &[1u8; 16].as_slice())
+                .insert("attnets", &[0xffu8; 8].as_slice())
+                .tcp_socket("127.0.0.1:10251".parse().unwrap())
+                .quic_socket("127.0.0.1:10252".parse().unwrap());
+        })
+        .unwrap();
+    assert!(updated);
+    let updated_enr = node.local_enr();
+    assert_eq!(updated_enr.seq(), enr.seq() + 1);
+    assert_eq!(updated_enr.tcp4(), Some(10251));
+    assert_eq!(updated_enr.udp4_socket(), enr.udp4_socket());
+
+    // Updates exceeding the maximum size leave the ENR unchanged.
+    assert_eq!(
+        node.update_local_enr(|update| {
+            update.insert("large", &[0u8; 300].as_slice());
+        }),
+        Err(enr::Error::ExceedsMaxSize)
+    );
+    assert_eq!(node.local_enr(), updated_enr);
+
+    // Updates that do not change the content do not increment the sequence number.
+    assert_eq!(
+        node.update_local_enr(|update| {
+            update.insert("eth2", &[1u8; 16].as_slice());
+        }),
+        Ok(false)
+    );
+    assert_eq!(node.local_enr(), updated_enr);
+}
+
+#[tokio::test]
+async fn test_pinned_external_addresses() {
+    init();
+    let key = CombinedKey::generate_secp256k1();
+    let static_socket: SocketAddr = "1.2.3.4:9000".parse().unwrap();
+    let config = ConfigBuilder::new(ListenConfig::Ipv4 {
+        ip: Ipv4Addr::LOCALHOST,
+        port: 10260,
+    })
+    .enr_update_transport(Transport::Quic)
+    .ipv4_external_address(ExternalAddressMode::Static(static_socket))
+    .ipv6_external_address(ExternalAddressMode::Never)
+    .build();
+    // Neither IP version is learned from peers, so none is revoked.
+    assert_eq!(config.auto_nat_listen_duration, None);
+
+    let enr = Enr::builder()
+        .ip4(Ipv4Addr::LOCALHOST)
+        .udp4(10260)
+        .add_value("quic", &10261u16)
+        .ip6(Ipv6Addr::LOCALHOST)
+        .udp6(10260)
+        .build(&key)
+        .unwrap();
+    let node = Discv5::new(enr.clone(), key, config).unwrap();
+
+    let local_enr = node.local_enr();
+    assert_eq!(local_enr.seq(), enr.seq() + 1);
+    assert_eq!(
+        local_enr.udp4_socket().map(SocketAddr::V4),
+        Some(static_socket)
+    );
+    // The QUIC port is kept at the pinned IP address.
+    assert_eq!(
+        Transport::Quic.socket(&local_enr, false),
+        Some(SocketAddr::new(static_socket.ip(), 10261))
+    );
+    assert_eq!(local_enr.udp6_socket(), None);
+    assert_eq!(local_enr.ip6(), None);
+
+    // Pinning a single IP version does not disable the NAT check, and the pinned socket is still
+    // advertised.
+    let key = CombinedKey::generate_secp256k1();
+    let config = ConfigBuilder::new(ListenConfig::Ipv4 {
+        ip: Ipv4Addr::LOCALHOST,
+        port: 10262,
+    })
+    .ipv4_external_address(ExternalAddressMode::Static(static_socket))
+    .build();
+    assert!(config.enr_update);
+    assert!(config.auto_nat_listen_duration.is_some());
+
+    let enr = Enr::builder()
+        .ip4(Ipv4Addr::LOCALHOST)
+        .udp4(10262)
+        .ip6(Ipv6Addr::LOCALHOST)
+        .udp6(10262)
+        .build(&key)
+        .unwrap();
+    let node = Discv5::new(enr, key, config).unwrap();
+    let local_enr = node.local_enr();
+    assert_eq!(
+        local_enr.udp4_socket().map(SocketAddr::V4),
+        Some(static_socket)
+    );
+    assert_eq!(
+        local_enr.udp6_socket().map(SocketAddr::V6),
+        Some(SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 10262))
+    );
+}
@@ -0,0 +1,238 @@
+//! Strategies selecting the log2-distances requested from peers in the FINDNODE requests of a
+//! query.
+//!
+//! When searching for a target, each contacted peer is asked for the nodes at the log2-distance
+//! of the target from the peer. As buckets may be sparsely populated, neighbouring distances can
+//! also be requested to obtain more nodes per request. Different networks benefit from different
+//! strategies, which can be configured via [`crate::ConfigBuilder::distance_strategy`].
+use crate::kbucket::{Key, MAX_NODES_PER_BUCKET};
+use enr::NodeId;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The maximum number of distances a strategy can request from a single peer.
+pub const MAX_DISTANCES_PER_REQUEST: usize = 127;
+
+/// Selects the distances requested in the FINDNODE requests of a query.
+pub trait DistanceStrategy: Send + Sync {
+    /// Returns the log2-distances to request from `peer` when searching for `target`. The peer is
+    /// never the target itself.
+    ///
+    /// Distances outside of the range 1-256 are ignored and at most
+    /// [`MAX_DISTANCES_PER_REQUEST`] distances are requested. If no valid distance is returned,
+    /// the log2-distance of the target from the peer is requested.
+    fn distances(&self, target: &NodeId, peer: &NodeId) -> Vec<u64>;
+
+    /// Informs the strategy of the number of nodes a peer returned for a FINDNODE request of a
+    /// query. The default implementation ignores this.
+    fn on_nodes_response(&self, _distances: &[u64], _received: usize) {}
+}
+
+/// Requests the log2-distance of the target from the peer, followed by the closest neighbouring
+/// distances, alternating above and below.
+///
+/// As an example, with a `count` of 5 and a target at a distance of 12 from the peer, the distances
+/// `[12, 13, 11, 14, 10]` are requested. A `count` of 1 requests the single exact distance. This is
+/// the default strategy, with a `count` of 3.
+#[derive(Debug, Clone)]
+pub struct NeighbourDistances {
+    count: usize,
+}
+
+impl NeighbourDistances {
+    /// Creates a strategy requesting `count` distances from each peer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is 0 or larger than [`MAX_DISTANCES_PER_REQUEST`].
+    pub fn new(count: usize) -> Self {
+        assert!(
+            count > 0 && count <= MAX_DISTANCES_PER_REQUEST,
+            "The number of distances must be between 1 and {}",
+            MAX_DISTANCES_PER_REQUEST
+        );
+        NeighbourDistances { count }
+    }
+}
+
+impl Default for NeighbourDistances {
+    fn default() -> Self {
+        NeighbourDistances::new(3)
+    }
+}
+
+impl DistanceStrategy for NeighbourDistances {
+    fn distances(&self, target: &NodeId, peer: &NodeId) -> Vec<u64> {
+        findnode_log2distance(*target, *peer, self.count).unwrap_or_default()
+    }
+}
+
+/// Adapts the number of requested distances to how full the NODES responses of peers are.
+///
+/// Starting from `min` distances, one more neighbouring distance is requested whenever a peer
+/// returns less than half a bucket of nodes, and one less whenever a peer returns a full bucket,
+/// staying within `min` and `max`. The state is shared across all queries.
+#[derive(Debug)]
+pub struct AdaptiveDistances {
+    min: usize,
+    max: usize,
+    current: AtomicUsize,
+}
+
+impl AdaptiveDistances {
+    /// Creates a strategy requesting between `min` and `max` distances from each peer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min` is 0, `max` is smaller than `min` or larger than
+    /// [`MAX_DISTANCES_PER_REQUEST`].
+    pub fn new(min: usize, max: usize) -> Self {
+        assert!(
+            min > 0 && min <= max && max <= MAX_DISTANCES_PER_REQUEST,
+            "Invalid range of distances"
+        );
+        AdaptiveDistances {
+            min,
+            max,
+            current: AtomicUsize::new(min),
+        }
+    }
+
+    /// The number of distances currently requested from each peer.
+    pub fn current(&self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for AdaptiveDistances {
+    fn default() -> Self {
+        AdaptiveDistances::new(1, 5)
+    }
+}
+
+impl DistanceStrategy for AdaptiveDistances {
+    fn distances(&self, target: &NodeId, peer: &NodeId) -> Vec<u64> {
+        findnode_log2distance(*target, *peer, self.current()).unwrap_or_default()
+    }
+
+    fn on_nodes_response(&self, _distances: &[u64], received: usize) {
+        let (min, max) = (self.min, self.max);
+        let _ = self
+            .current
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+                if received >= MAX_NODES_PER_BUCKET {
+                    Some(current.saturating_sub(1).max(min))
+                } else if received < MAX_NODES_PER_BUCKET / 2 {
+                    Some((current + 1).min(max))
+                } else {
+                    None
+                }
+            });
+    }
+}
+
+/// Calculates the log2 distance for a destination peer given a target and the size (number of
+/// distances to request).
+///
+/// As the iteration increases, FINDNODE requests adjacent distances from the exact peer distance.
+///
+/// As an example, if the target has a distance of 12 from the remote peer, the sequence of distances that are sent for increasing iterations would be [12, 13, 11, 14, 10, .. ].
+fn findnode_log2distance(target: NodeId, peer: NodeId, size: usize) -> Option<Vec<u64>> {
+    if size > MAX_DISTANCES_PER_REQUEST {
+        // invoke and endless loop - coding error
+        panic!("Iterations cannot be greater than 127");
+    }
+
+    let dst_key: Key<NodeId> = peer.into();
+    let distance = dst_key.log2_distance(&target.into())?;
+
+    let mut result_list = vec![distance];
+    let mut difference = 1;
+    while result_list.len() < size {
+        if distance + difference <= 256 {
+            result_list.push(distance + difference);
+        }
+        if result_list.len() < size {
+            if let Some(d) = distance.checked_sub(difference) {
+                result_list.push(d);
+            }
+        }
+        difference += 1;
+    }
+    Some(result_list[..size].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log2distance() {
+        let target = NodeId::new(&[0u8; 32]);
+        let mut destination = [0u8; 32];
+        destination[10] = 1; // gives a log2 distance of 169
+        let destination = NodeId::new(&destination);
+
+        let expected_distances = vec![169, 170, 168, 171, 167, 172, 166, 173, 165];
+
+        assert_eq!(
+            findnode_log2distance(target, destination, expected_distances.len()).unwrap(),
+            expected_distances
+        );
+    }
+
+    #[test]
+    fn test_log2distance_lower() {
+        let target = NodeId::new(&[0u8; 32]);
+        let mut destination = [0u8; 32];
+        destination[31] = 8; // gives a log2 distance of 5
+        let destination = NodeId::new(&destination);
+
+        let expected_distances = vec![4, 5, 3, 6, 2, 7, 1, 8, 0, 9, 10];
+
+        assert_eq!(
+            findnode_log2distance(target, destination, expected_distances.len()).unwrap(),
+            expected_distances
+        );
+    }
+
+    #[test]
+    fn test_log2distance_upper() {
+        let target = NodeId::new(&[0u8; 32]);
+        let mut destination = [0u8; 32];
+        destination[0] = 8; // gives a log2 distance of 252
+        let destination = NodeId::new(&destination);
+
+        let expected_distances = vec![252, 253, 251, 254, 250, 255, 249, 256, 248, 247, 246];
+
+        assert_eq!(
+            findnode_log2distance(target, destination, expected_distances.len()).unwrap(),
+            expected_distances
+        );
+    }
+
+    #[test]
+    fn test_adaptive_distances() {
+        let strategy = AdaptiveDistances::new(1, 3);
+        let target = NodeId::new(&[0u8; 32]);
+        let mut destination = [0u8; 32];
+        destination[10] = 1; // gives a log2 distance of 169
+        let destination = NodeId::new(&destination);
+
+        assert_eq!(strategy.distances(&target, &destination), vec![169]);
+
+        // Sparse responses widen the request
+        strategy.on_nodes_response(&[169], 2);
+        assert_eq!(strategy.distances(&target, &destination), vec![169, 170]);
+        strategy.on_nodes_response(&[169, 170], 0);
+        strategy.on_nodes_response(&[169, 170, 168], 0);
+        assert_eq!(strategy.current(), 3);
+
+        // Partially filled responses keep the request as is
+        strategy.on_nodes_response(&[169, 170, 168], 10);
+        assert_eq!(strategy.current(), 3);
+
+        // Full responses narrow the request
+        strategy.on_nodes_response(&[169, 170, 168], MAX_NODES_PER_BUCKET);
+        assert_eq!(strategy.distances(&target, &destination), vec![169, 170]);
+    }
+}
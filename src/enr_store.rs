@@ -0,0 +1,241 @@
+//! Persistence of the local ENR across restarts.
+//!
+//! Peers only accept an updated record of a node if its sequence number is higher than the one
+//! they have cached. A node that builds a fresh ENR on every start would advertise a sequence
+//! number that may be lower than the one of its previous run, and peers would ignore it. An
+//! [`EnrStore`] configured via [`ConfigBuilder::enr_store`](crate::ConfigBuilder::enr_store)
+//! provides the last local ENR when the server is created, and receives every update of the local
+//! ENR. Updates are written on a background thread, and pending writes are completed when the
+//! server is dropped.
+//!
+//! When the server is created, the stored record is reconciled with the configured one. The
+//! configured record takes precedence, except for the UDP sockets learned from peers in a
+//! previous run: if the configured record advertises no UDP socket for an IP version and the
+//! stored record does, with the port we listen on for that IP version, the stored socket is kept.
+//! If the resulting content is that of the stored record, the stored record is used as is.
+//! Otherwise the record is signed with a sequence number higher than the stored one.
+use crate::{
+    signer::{update_enr, Signer},
+    socket::ListenConfig,
+    Enr,
+};
+use enr::Error as EnrError;
+use std::{
+    fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing::{debug, warn};
+
+mod writer;
+
+pub(crate) use writer::EnrWriter;
+
+/// Loads and stores the local ENR.
+pub trait EnrStore: Send + Sync {
+    /// Loads the last stored local ENR, if any.
+    fn load(&self) -> io::Result<Option<Enr>>;
+
+    /// Stores the local ENR, replacing the previously stored one.
+    fn store(&self, enr: &Enr) -> io::Result<()>;
+}
+
+/// Stores the local ENR in its base64 text form in a file.
+#[derive(Debug, Clone)]
+pub struct FileEnrStore {
+    path: PathBuf,
+}
+
+impl FileEnrStore {
+    /// Creates a store persisting the ENR to the file at `path`. The file is created on the first
+    /// update.
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        FileEnrStore {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl EnrStore for FileEnrStore {
+    fn load(&self) -> io::Result<Option<Enr>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        contents
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn store(&self, enr: &Enr) -> io::Result<()> {
+        // Write to a temporary file first, so that the stored record is never truncated.
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, enr.to_base64())?;
+        fs::rename(&tmp_path, &self.path)
+    }
+}
+
+/// Writes the local ENR to the store, if one is configured. Failures are logged.
+pub(crate) fn persist(store: Option<&Arc<dyn EnrStore>>, enr: &Enr) {
+    if let Some(store) = store {
+        if let Err(error) = store.store(enr) {
+            warn!(%error, "Failed to store the local ENR");
+        }
+    }
+}
+
+/// Reconciles the configured local ENR with the stored one. See the module docs.
+pub(crate) fn reconcile(
+    configured: Enr,
+    stored: Enr,
+    listen_config: &ListenConfig,
+    signer: &dyn Signer,
+) -> Result<Enr, EnrError> {
+    if stored.public_key() != configured.public_key() || !stored.verify() {
+        debug!("Ignoring the stored ENR of a different key");
+        return Ok(configured);
+    }
+
+    let mut learned_sockets = Vec::new();
+    if configured.udp4_socket().is_none() {
+        learned_sockets.extend(stored.udp4_socket().map(SocketAddr::V4));
+    }
+    if configured.udp6_socket().is_none() {
+        learned_sockets.extend(stored.udp6_socket().map(SocketAddr::V6));
+    }
+    learned_sockets
+        .retain(|socket| listen_port(listen_config, socket.is_ipv6()) == Some(socket.port()));
+
+    let mut enr = configured;
+    if !learned_sockets.is_empty() {
+        update_enr(&mut enr, signer, |enr, key| {
+            learned_sockets
+                .iter()
+                .try_for_each(|socket| enr.set_udp_socket(*socket, key))
+        })?;
+    }
+
+    if enr.iter().eq(stored.iter()) {
+        return Ok(stored);
+    }
+    let seq = stored
+        .seq()
+        .checked_add(1)
+        .ok_or(EnrError::SequenceNumberTooHigh)?
+        .max(enr.seq());
+    if enr.seq() != seq {
+        update_enr(&mut enr, signer, |enr, key| enr.set_seq(seq, key))?;
+    }
+    Ok(enr)
+}
+
+/// The UDP port we listen on for the given IP version.
+fn listen_port(listen_config: &ListenConfig, ipv6: bool) -> Option<u16> {
+    match (listen_config, ipv6) {
+        (ListenConfig::Ipv4 { port, .. }, false) | (ListenConfig::Ipv6 { port, .. }, true) => {
+            Some(*port)
+        }
+        (ListenConfig::DualStack { ipv4_port, .. }, false) => Some(*ipv4_port),
+        (ListenConfig::DualStack { ipv6_port, .. }, true) => Some(*ipv6_port),
+        (ListenConfig::FromSockets { ipv4, .. }, false) => {
+            ipv4.as_ref()?.local_addr().ok().map(|addr| addr.port())
+        }
+        (ListenConfig::FromSockets { ipv6, .. }, true) => {
+            ipv6.as_ref()?.local_addr().ok().map(|addr| addr.port())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enr::CombinedKey;
+    use std::net::Ipv4Addr;
+
+    fn listen_config() -> ListenConfig {
+        ListenConfig::Ipv4 {
+            ip: Ipv4Addr::UNSPECIFIED,
+            port: 9000,
+        }
+    }
+
+    #[test]
+    fn unchanged_content_keeps_stored_enr() {
+        let key = CombinedKey::generate_secp256k1();
+        let configured = Enr::builder().build(&key).unwrap();
+        let mut stored = configured.clone();
+        stored.set_seq(10, &key).unwrap();
+
+        let enr = reconcile(configured, stored.clone(), &listen_config(), &key).unwrap();
+        assert_eq!(enr, stored);
+    }
+
+    #[test]
+    fn changed_content_bumps_stored_seq() {
+        let key = CombinedKey::generate_secp256k1();
+        let configured = Enr::builder()
+            .add_value("eth2", &[1u8])
+            .build(&key)
+            .unwrap();
+        let mut stored = Enr::builder().build(&key).unwrap();
+        stored.set_seq(10, &key).unwrap();
+
+        let enr = reconcile(configured, stored, &listen_config(), &key).unwrap();
+        assert_eq!(enr.seq(), 11);
+        assert!(enr.get_decodable::<Vec<u8>>("eth2").is_some());
+        assert!(enr.verify());
+    }
+
+    #[test]
+    fn learned_socket_is_kept_on_listen_port() {
+        let key = CombinedKey::generate_secp256k1();
+        let configured = Enr::builder().build(&key).unwrap();
+
+        let mut stored = configured.clone();
+        stored
+            .set_udp_socket("1.2.3.4:9000".parse().unwrap(), &key)
+            .unwrap();
+        let enr = reconcile(configured.clone(), stored.clone(), &listen_config(), &key).unwrap();
+        assert_eq!(enr, stored);
+
+        // The learned socket does not match the port we listen on.
+        stored
+            .set_udp_socket("1.2.3.4:9001".parse().unwrap(), &key)
+            .unwrap();
+        let enr = reconcile(configured, stored.clone(), &listen_config(), &key).unwrap();
+        assert_eq!(enr.udp4_socket(), None);
+        assert_eq!(enr.seq(), stored.seq() + 1);
+    }
+
+    #[test]
+    fn stored_enr_of_another_key_is_ignored() {
+        let key = CombinedKey::generate_secp256k1();
+        let configured = Enr::builder().build(&key).unwrap();
+        let other_key = CombinedKey::generate_secp256k1();
+        let mut stored = Enr::builder().build(&other_key).unwrap();
+        stored.set_seq(10, &other_key).unwrap();
+
+        let enr = reconcile(configured.clone(), stored, &listen_config(), &key).unwrap();
+        assert_eq!(enr, configured);
+    }
+
+    #[test]
+    fn file_store_round_trip() {
+        let path = std::env::temp_dir().join(format!("discv5-enr-{}.txt", rand::random::<u64>()));
+        let store = FileEnrStore::new(&path);
+        assert!(store.load().unwrap().is_none());
+
+        let key = CombinedKey::generate_secp256k1();
+        let enr = Enr::builder().build(&key).unwrap();
+        store.store(&enr).unwrap();
+        assert_eq!(store.load().unwrap(), Some(enr));
+
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,168 @@
+//! Writing updates of the local ENR to the [`EnrStore`] off the service task.
+use super::EnrStore;
+use crate::Enr;
+use std::{
+    sync::{mpsc, Arc},
+    thread,
+};
+use tracing::warn;
+
+/// A message to the thread of an [`EnrWriter`].
+enum WriterMessage {
+    /// Stores the record, unless a newer one has been stored.
+    Store(Enr),
+    /// Acknowledges that all previously sent records have been handled.
+    Flush(mpsc::Sender<()>),
+}
+
+/// Writes updates of the local ENR to the store on a background thread, so that slow storage does
+/// not block the service. Records queued while a write is in progress are coalesced, and a record
+/// is never replaced by one with a lower sequence number.
+#[derive(Clone)]
+pub struct EnrWriter {
+    sender: mpsc::Sender<WriterMessage>,
+}
+
+impl EnrWriter {
+    /// Spawns the writing thread. The thread stops once all clones of the writer are dropped.
+    pub(crate) fn new(store: Arc<dyn EnrStore>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stored_seq = None;
+            while let Ok(message) = receiver.recv() {
+                let mut latest = None;
+                let mut flushes = Vec::new();
+                for message in std::iter::once(message).chain(receiver.try_iter()) {
+                    match message {
+                        WriterMessage::Store(enr) => latest = freshest(latest, enr),
+                        WriterMessage::Flush(ack) => flushes.push(ack),
+                    }
+                }
+                // A failed write leaves `stored_seq` unchanged, so that the record is written again
+                // with the next update, even if that update does not increase the sequence number.
+                if let Some(enr) = latest.filter(|enr| stored_seq < Some(enr.seq())) {
+                    match store.store(&enr) {
+                        Ok(()) => stored_seq = Some(enr.seq()),
+                        Err(error) => warn!(%error, "Failed to store the local ENR"),
+                    }
+                }
+                for ack in flushes {
+                    let _ = ack.send(());
+                }
+            }
+        });
+        EnrWriter { sender }
+    }
+
+    /// Queues the record to be stored.
+    pub(crate) fn write(&self, enr: Enr) {
+        if self.sender.send(WriterMessage::Store(enr)).is_err() {
+            warn!("Failed to store the local ENR, the writer stopped");
+        }
+    }
+
+    /// Waits until all queued records have been stored. This blocks the calling thread.
+    pub(crate) fn flush(&self) {
+        let (ack, done) = mpsc::channel();
+        if self.sender.send(WriterMessage::Flush(ack)).is_ok() {
+            let _ = done.recv();
+        }
+    }
+}
+
+/// Returns the record with the highest sequence number, preferring the later one.
+fn freshest(current: Option<Enr>, candidate: Enr) -> Option<Enr> {
+    match current {
+        Some(current) if current.seq() > candidate.seq() => Some(current),
+        _ => Some(candidate),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enr::CombinedKey;
+    use parking_lot::Mutex;
+    use std::io;
+
+    /// Records the sequence numbers of the stored records.
+    #[derive(Default)]
+    struct RecordingStore(Mutex<Vec<u64>>);
+
+    impl EnrStore for RecordingStore {
+        fn load(&self) -> io::Result<Option<Enr>> {
+            Ok(None)
+        }
+
+        fn store(&self, enr: &Enr) -> io::Result<()> {
+            self.0.lock().push(enr.seq());
+            Ok(())
+        }
+    }
+
+    /// Fails every write until `fail` is cleared.
+    struct FailingStore {
+        fail: Mutex<bool>,
+        stored: Mutex<Vec<u64>>,
+    }
+
+    impl EnrStore for FailingStore {
+        fn load(&self) -> io::Result<Option<Enr>> {
+            Ok(None)
+        }
+
+        fn store(&self, enr: &Enr) -> io::Result<()> {
+            if *self.fail.lock() {
+                return Err(io::Error::other("disk full"));
+            }
+            self.stored.lock().push(enr.seq());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failed_writes_are_retried() {
+        let key = CombinedKey::generate_secp256k1();
+        let mut enr = Enr::builder().build(&key).unwrap();
+        enr.set_seq(5, &key).unwrap();
+
+        let store = Arc::new(FailingStore {
+            fail: Mutex::new(true),
+            stored: Mutex::new(Vec::new()),
+        });
+        let writer = EnrWriter::new(store.clone());
+        writer.write(enr.clone());
+        writer.flush();
+        assert!(store.stored.lock().is_empty());
+
+        // The same record is written again once the store recovers.
+        *store.fail.lock() = false;
+        writer.write(enr);
+        writer.flush();
+        assert_eq!(*store.stored.lock(), vec![5]);
+    }
+
+    #[test]
+    fn older_records_are_not_stored() {
+        let key = CombinedKey::generate_secp256k1();
+        let mut enr = Enr::builder().build(&key).unwrap();
+        let older = enr.clone();
+        enr.set_seq(5, &key).unwrap();
+
+        let store = Arc::new(RecordingStore::default());
+        let writer = EnrWriter::new(store.clone());
+        writer.write(enr.clone());
+        writer.flush();
+        assert_eq!(*store.0.lock(), vec![5]);
+
+        // A record cloned before the last update arrives late.
+        writer.write(older);
+        writer.flush();
+        assert_eq!(*store.0.lock(), vec![5]);
+
+        enr.set_seq(6, &key).unwrap();
+        writer.write(enr);
+        writer.flush();
+        assert_eq!(*store.0.lock(), vec![5, 6]);
+    }
+}
@@ -0,0 +1,244 @@
+//! Atomic updates of multiple fields of the local ENR.
+//!
+//! Each update of the local ENR increments its sequence number, which peers learn about from our
+//! PINGs and answer by requesting the updated record. An [`EnrUpdate`] collects several changes,
+//! which [`Discv5::update_local_enr`](crate::Discv5::update_local_enr) applies with a single
+//! signature and sequence number increment.
+use crate::{signer::sign_content, Enr, Signer};
+use alloy_rlp::Encodable;
+use enr::Error as EnrError;
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, SocketAddr},
+};
+
+/// The keys of a record that are maintained by the signer.
+const RESERVED_KEYS: [&[u8]; 3] = [b"id", b"secp256k1", b"ed25519"];
+
+/// A transport whose port is advertised in the ENR. The IP addresses are shared by all
+/// transports.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Transport {
+    /// The `udp` and `udp6` keys.
+    Udp,
+    /// The `tcp` and `tcp6` keys.
+    Tcp,
+    /// The `quic` and `quic6` keys.
+    Quic,
+    /// Custom keys holding the port of a transport.
+    Custom {
+        /// The key of the port used with the IPv4 address.
+        ipv4_key: String,
+        /// The key of the port used with the IPv6 address.
+        ipv6_key: String,
+    },
+}
+
+impl Transport {
+    /// The key of the transport's port for the given IP version.
+    pub fn port_key(&self, ipv6: bool) -> &str {
+        match (self, ipv6) {
+            (Transport::Udp, false) => "udp",
+            (Transport::Udp, true) => "udp6",
+            (Transport::Tcp, false) => "tcp",
+            (Transport::Tcp, true) => "tcp6",
+            (Transport::Quic, false) => "quic",
+            (Transport::Quic, true) => "quic6",
+            (Transport::Custom { ipv4_key, .. }, false) => ipv4_key,
+            (Transport::Custom { ipv6_key, .. }, true) => ipv6_key,
+        }
+    }
+
+    /// The port of the transport advertised in the ENR for the given IP version, if any.
+    pub(crate) fn port(&self, enr: &Enr, ipv6: bool) -> Option<u16> {
+        enr.get_decodable::<u16>(self.port_key(ipv6))?.ok()
+    }
+
+    /// The socket of the transport advertised in the ENR for the given IP version, if any.
+    pub fn socket(&self, enr: &Enr, ipv6: bool) -> Option<SocketAddr> {
+        let port = self.port(enr, ipv6)?;
+        let ip: IpAddr = if ipv6 {
+            enr.ip6()?.into()
+        } else {
+            enr.ip4()?.into()
+        };
+        Some(SocketAddr::new(ip, port))
+    }
+}
+
+/// A set of changes to the local ENR, applied in order.
+#[derive(Debug, Clone, Default)]
+pub struct EnrUpdate {
+    /// The keys to change and their RLP encoded values. A value of `None` removes the key.
+    changes: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl EnrUpdate {
+    /// Inserts or replaces the value of a key.
+    pub fn insert<T: Encodable + ?Sized>(&mut self, key: impl AsRef<[u8]>, value: &T) -> &mut Self {
+        let mut encoded = Vec::new();
+        value.encode(&mut encoded);
+        self.changes.push((key.as_ref().to_vec(), Some(encoded)));
+        self
+    }
+
+    /// Removes a key.
+    pub fn remove(&mut self, key: impl AsRef<[u8]>) -> &mut Self {
+        self.changes.push((key.as_ref().to_vec(), None));
+        self
+    }
+
+    /// Sets the IP address of the socket's version.
+    pub fn ip(&mut self, ip: IpAddr) -> &mut Self {
+        match ip {
+            IpAddr::V4(ip) => self.insert("ip", &ip),
+            IpAddr::V6(ip) => self.insert("ip6", &ip),
+        }
+    }
+
+    /// Sets the IP address and the port of the transport for the socket's version.
+    pub fn socket(&mut self, socket: SocketAddr, transport: &Transport) -> &mut Self {
+        self.ip(socket.ip());
+        self.insert(transport.port_key(socket.is_ipv6()), &socket.port())
+    }
+
+    /// Sets the IP address and UDP port of the socket's version.
+    pub fn udp_socket(&mut self, socket: SocketAddr) -> &mut Self {
+        self.socket(socket, &Transport::Udp)
+    }
+
+    /// Sets the IP address and TCP port of the socket's version.
+    pub fn tcp_socket(&mut self, socket: SocketAddr) -> &mut Self {
+        self.socket(socket, &Transport::Tcp)
+    }
+
+    /// Sets the IP address and QUIC port of the socket's version.
+    pub fn quic_socket(&mut self, socket: SocketAddr) -> &mut Self {
+        self.socket(socket, &Transport::Quic)
+    }
+
+    /// Applies the changes to the record and signs the result with an incremented sequence
+    /// number. Returns `None` if the changes leave the content of the record unchanged.
+    ///
+    /// Fails if a key maintained by the signer is changed, or if the updated record exceeds the
+    /// maximum size of 300 bytes.
+    pub(crate) fn apply(&self, enr: &Enr, signer: &dyn Signer) -> Result<Option<Enr>, EnrError> {
+        let current: BTreeMap<Vec<u8>, Vec<u8>> = enr
+            .iter()
+            .map(|(key, value)| (key.clone(), value.to_vec()))
+            .collect();
+        let mut content = current.clone();
+        for (key, value) in &self.changes {
+            if RESERVED_KEYS.contains(&key.as_slice()) {
+                return Err(EnrError::UnsupportedIdentityScheme);
+            }
+            match value {
+                Some(value) => content.insert(key.clone(), value.clone()),
+                None => content.remove(key),
+            };
+        }
+        if content == current {
+            return Ok(None);
+        }
+
+        let seq = enr
+            .seq()
+            .checked_add(1)
+            .ok_or(EnrError::SequenceNumberTooHigh)?;
+        sign_content(seq, content, signer).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enr::{CombinedKey, EnrKey};
+
+    #[test]
+    fn changes_are_applied_with_a_single_increment() {
+        let key = CombinedKey::generate_secp256k1();
+        let enr = Enr::builder()
+            .add_value("attnets", &[0u8; 8].as_slice())
+            .build(&key)
+            .unwrap();
+
+        let mut update = EnrUpdate::default();
+        update
+            .insert("eth2", &[1u8; 16].as_slice())
+            .insert("syncnets", &[0u8].as_slice())
+            .remove("attnets")
+            .udp_socket("1.2.3.4:9000".parse().unwrap())
+            .quic_socket("1.2.3.4:9001".parse().unwrap());
+        let updated = update.apply(&enr, &key).unwrap().unwrap();
+
+        assert_eq!(updated.seq(), enr.seq() + 1);
+        assert!(updated.verify());
+        assert_eq!(updated.public_key(), key.public());
+        assert_eq!(updated.udp4_socket(), Some("1.2.3.4:9000".parse().unwrap()));
+        assert_eq!(updated.get_decodable::<u16>("quic"), Some(Ok(9001)));
+        assert_eq!(
+            updated.get_decodable::<alloy_rlp::Bytes>("eth2"),
+            Some(Ok(vec![1u8; 16].into()))
+        );
+        assert!(updated.get_raw_rlp("attnets").is_none());
+    }
+
+    #[test]
+    fn unchanged_content_is_not_signed() {
+        let key = CombinedKey::generate_secp256k1();
+        let enr = Enr::builder()
+            .add_value("eth2", &[1u8].as_slice())
+            .build(&key)
+            .unwrap();
+
+        let mut update = EnrUpdate::default();
+        update.insert("eth2", &[1u8].as_slice()).remove("absent");
+        assert_eq!(update.apply(&enr, &key), Ok(None));
+    }
+
+    #[test]
+    fn transport_sockets_share_the_ip() {
+        let key = CombinedKey::generate_secp256k1();
+        let enr = Enr::builder().build(&key).unwrap();
+        let custom = Transport::Custom {
+            ipv4_key: "webrtc".into(),
+            ipv6_key: "webrtc6".into(),
+        };
+
+        let mut update = EnrUpdate::default();
+        update
+            .socket("1.2.3.4:9000".parse().unwrap(), &Transport::Tcp)
+            .socket("[::1]:9001".parse().unwrap(), &custom);
+        let updated = update.apply(&enr, &key).unwrap().unwrap();
+
+        assert_eq!(updated.seq(), enr.seq() + 1);
+        assert_eq!(
+            Transport::Tcp.socket(&updated, false),
+            Some("1.2.3.4:9000".parse().unwrap())
+        );
+        assert_eq!(
+            custom.socket(&updated, true),
+            Some("[::1]:9001".parse().unwrap())
+        );
+        assert_eq!(updated.get_decodable::<u16>("webrtc6"), Some(Ok(9001)));
+        assert_eq!(custom.socket(&updated, false), None);
+        assert_eq!(Transport::Udp.socket(&updated, false), None);
+    }
+
+    #[test]
+    fn invalid_updates_are_rejected() {
+        let key = CombinedKey::generate_secp256k1();
+        let enr = Enr::builder().build(&key).unwrap();
+
+        let mut update = EnrUpdate::default();
+        update.insert("eth2", &[0u8; 300].as_slice());
+        assert_eq!(update.apply(&enr, &key), Err(EnrError::ExceedsMaxSize));
+
+        let mut update = EnrUpdate::default();
+        update.remove("id");
+        assert_eq!(
+            update.apply(&enr, &key),
+            Err(EnrError::UnsupportedIdentityScheme)
+        );
+    }
+}
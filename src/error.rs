@@ -97,6 +97,10 @@ pub enum RequestError {
     ServiceNotStarted,
     /// The request was sent to ourselves.
     SelfRequest,
+    /// The local key was rotated while the request was in progress.
+    KeyRotated,
+    /// The queue of outbound requests is full, the request was not sent.
+    OutboundQueueFull,
     /// The channel to the underlying threads failed.
     ChannelFailed(String),
     /// An invalid ENR was provided.
@@ -125,6 +129,8 @@ pub enum QueryError {
     EncryptionFailed(String),
     /// The multiaddr provided was invalid.
     InvalidMultiaddr(String),
+    /// The log2-distance provided is not in the range 1-256.
+    InvalidDistance(u64),
 }
 
 impl fmt::Display for Error {
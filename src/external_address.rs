@@ -0,0 +1,185 @@
+//! The external address advertised in the local ENR for each IP version.
+//!
+//! By default the external socket of an IP version is learned from the PONG responses of peers
+//! (see [`Config::enr_peer_update_min`](crate::Config::enr_peer_update_min)) and revoked if we do
+//! not appear to be contactable (see
+//! [`Config::auto_nat_listen_duration`](crate::Config::auto_nat_listen_duration)). Nodes with a known
+//! public address, such as those behind a load balancer, can instead pin the address of an IP
+//! version, or never advertise one, while the other IP version is still discovered.
+use crate::{Enr, EnrUpdate, Transport};
+use std::net::SocketAddr;
+
+/// How the external socket of an IP version is advertised in the local ENR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExternalAddressMode {
+    /// The socket is learned from peers and revoked if we are not contactable. This requires the
+    /// ENR update to be enabled.
+    #[default]
+    Auto,
+    /// The given socket is always advertised. Votes of peers for this IP version are ignored.
+    Static(SocketAddr),
+    /// No socket is advertised for this IP version.
+    Never,
+}
+
+impl ExternalAddressMode {
+    /// Whether the socket is learned from peers.
+    pub fn is_auto(&self) -> bool {
+        matches!(self, ExternalAddressMode::Auto)
+    }
+}
+
+/// Adds the external socket of an IP version to the update, as the UDP socket of the local ENR.
+/// The given transports are advertised at the IP address of the socket. They keep their own port,
+/// taken from the other IP version if they have none for the socket's version, unless
+/// `share_udp_port` is set, in which case they are advertised on the UDP port.
+pub(crate) fn add_external_socket(
+    update: &mut EnrUpdate,
+    enr: &Enr,
+    socket: SocketAddr,
+    transports: &[Transport],
+    share_udp_port: bool,
+) {
+    update.udp_socket(socket);
+    let ipv6 = socket.is_ipv6();
+    for transport in transports {
+        let port = if share_udp_port {
+            Some(socket.port())
+        } else {
+            transport
+                .port(enr, ipv6)
+                .or_else(|| transport.port(enr, !ipv6))
+        };
+        if let Some(port) = port {
+            update.socket(SocketAddr::new(socket.ip(), port), transport);
+        }
+    }
+}
+
+/// The update applying the pinned modes of both IP versions to the local ENR, for UDP and the
+/// given transports. See [`add_external_socket`].
+pub(crate) fn pinned_enr_update(
+    enr: &Enr,
+    ipv4_mode: ExternalAddressMode,
+    ipv6_mode: ExternalAddressMode,
+    transports: &[Transport],
+    share_udp_port: bool,
+) -> EnrUpdate {
+    let mut update = EnrUpdate::default();
+    for (mode, ipv6) in [(ipv4_mode, false), (ipv6_mode, true)] {
+        match mode {
+            ExternalAddressMode::Auto => {}
+            ExternalAddressMode::Static(socket) => {
+                add_external_socket(&mut update, enr, socket, transports, share_udp_port);
+            }
+            ExternalAddressMode::Never => {
+                update.remove(if ipv6 { "ip6" } else { "ip" });
+                update.remove(Transport::Udp.port_key(ipv6));
+                for transport in transports {
+                    update.remove(transport.port_key(ipv6));
+                }
+            }
+        }
+    }
+    update
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enr::CombinedKey;
+
+    #[test]
+    fn pinned_modes_are_applied_per_ip_version() {
+        let key = CombinedKey::generate_secp256k1();
+        let enr = Enr::builder()
+            .ip4("10.0.0.1".parse().unwrap())
+            .udp4(9000)
+            .tcp4(9001)
+            .ip6("::1".parse().unwrap())
+            .udp6(9000)
+            .tcp6(9000)
+            .build(&key)
+            .unwrap();
+
+        let static_socket: SocketAddr = "1.2.3.4:9100".parse().unwrap();
+        let update = pinned_enr_update(
+            &enr,
+            ExternalAddressMode::Static(static_socket),
+            ExternalAddressMode::Never,
+            &[Transport::Tcp],
+            false,
+        );
+        let updated = update.apply(&enr, &key).unwrap().unwrap();
+        assert_eq!(updated.seq(), enr.seq() + 1);
+        assert_eq!(Transport::Udp.socket(&updated, false), Some(static_socket));
+        // The TCP port is kept at the pinned IP address.
+        assert_eq!(
+            Transport::Tcp.socket(&updated, false),
+            Some("1.2.3.4:9001".parse().unwrap())
+        );
+        assert_eq!(updated.ip6(), None);
+        assert_eq!(updated.udp6(), None);
+        assert_eq!(updated.tcp6(), None);
+
+        // Applying the modes again leaves the record unchanged.
+        let update = pinned_enr_update(
+            &updated,
+            ExternalAddressMode::Static(static_socket),
+            ExternalAddressMode::Never,
+            &[Transport::Tcp],
+            false,
+        );
+        assert_eq!(update.apply(&updated, &key), Ok(None));
+
+        // Transports sharing the UDP port are advertised on the pinned port.
+        let update = pinned_enr_update(
+            &enr,
+            ExternalAddressMode::Static(static_socket),
+            ExternalAddressMode::Auto,
+            &[Transport::Tcp],
+            true,
+        );
+        let updated = update.apply(&enr, &key).unwrap().unwrap();
+        assert_eq!(Transport::Tcp.socket(&updated, false), Some(static_socket));
+
+        // Auto modes leave the record to the votes of peers.
+        let update = pinned_enr_update(
+            &enr,
+            ExternalAddressMode::Auto,
+            ExternalAddressMode::Auto,
+            &[Transport::Tcp],
+            false,
+        );
+        assert_eq!(update.apply(&enr, &key), Ok(None));
+    }
+
+    #[test]
+    fn transports_without_a_port_of_the_ip_version_take_the_other_one() {
+        let key = CombinedKey::generate_secp256k1();
+        let enr = Enr::builder()
+            .ip4("10.0.0.1".parse().unwrap())
+            .udp4(9000)
+            .add_value("quic", &9002u16)
+            .build(&key)
+            .unwrap();
+
+        let socket: SocketAddr = "[2001:db8::1]:9100".parse().unwrap();
+        let mut update = EnrUpdate::default();
+        add_external_socket(
+            &mut update,
+            &enr,
+            socket,
+            &[Transport::Quic, Transport::Tcp],
+            false,
+        );
+        let updated = update.apply(&enr, &key).unwrap().unwrap();
+        assert_eq!(Transport::Udp.socket(&updated, true), Some(socket));
+        assert_eq!(
+            Transport::Quic.socket(&updated, true),
+            Some("[2001:db8::1]:9002".parse().unwrap())
+        );
+        // A transport without any port is not advertised.
+        assert_eq!(Transport::Tcp.socket(&updated, true), None);
+    }
+}
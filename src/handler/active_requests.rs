@@ -1,4 +1,7 @@
-use super::*;
+use super::{
+    rtt::{RttTable, MAX_ADAPTIVE_TIMEOUT_FACTOR, MIN_ADAPTIVE_TIMEOUT},
+    *,
+};
 use delay_map::HashMapDelay;
 use more_asserts::debug_unreachable;
 use std::collections::hash_map::Entry;
@@ -11,25 +14,71 @@ pub(super) struct ActiveRequests {
     // requests with active requests sent.
     /// A mapping of all active raw requests message nonces to their NodeAddress.
     active_requests_nonce_mapping: HashMapDelay<MessageNonce, NodeAddress>,
+    /// The configured timeout of requests.
+    request_timeout: Duration,
+    /// The measured round-trip times of peers.
+    rtts: Arc<RwLock<RttTable>>,
+    /// Whether request timeouts are derived from the measured round-trip times.
+    adaptive_timeouts: bool,
 }
 
 impl ActiveRequests {
-    pub fn new(request_timeout: Duration) -> Self {
+    pub fn new(
+        request_timeout: Duration,
+        rtts: Arc<RwLock<RttTable>>,
+        adaptive_timeouts: bool,
+    ) -> Self {
         ActiveRequests {
             active_requests_mapping: HashMap::new(),
             active_requests_nonce_mapping: HashMapDelay::new(request_timeout),
+            request_timeout,
+            rtts,
+            adaptive_timeouts,
         }
     }
 
     /// Insert a new request into the active requests mapping.
     pub fn insert(&mut self, node_address: NodeAddress, request_call: RequestCall) {
         let nonce = *request_call.packet().message_nonce();
+        let timeout = self.request_timeout(&node_address.node_id, request_call.retries());
         self.active_requests_mapping
             .entry(node_address.clone())
             .or_default()
             .push(request_call);
         self.active_requests_nonce_mapping
-            .insert(nonce, node_address);
+            .insert_at(nonce, node_address, timeout);
+    }
+
+    /// The timeout of a request to a peer. With adaptive timeouts, this is the retransmission
+    /// timeout of the peer, doubled for every retry. Peers without measured round-trip times use
+    /// the configured timeout.
+    pub fn request_timeout(&self, node_id: &NodeId, retries: u8) -> Duration {
+        if !self.adaptive_timeouts {
+            return self.request_timeout;
+        }
+        let Some(rtt) = self.rtts.read().get(node_id) else {
+            return self.request_timeout;
+        };
+        let backoff = 1u32 << retries.saturating_sub(1).min(8);
+        (rtt.retransmission_timeout() * backoff).clamp(
+            MIN_ADAPTIVE_TIMEOUT,
+            self.request_timeout * MAX_ADAPTIVE_TIMEOUT_FACTOR,
+        )
+    }
+
+    /// Records the round-trip time of a request that received its first response. Requests that
+    /// were retransmitted or required a handshake are ignored, as their round-trip time is
+    /// ambiguous.
+    pub fn record_rtt(&mut self, node_id: NodeId, request_call: &RequestCall) {
+        if request_call.retries() > 1
+            || request_call.handshake_sent()
+            || request_call.initiating_session()
+        {
+            return;
+        }
+        self.rtts
+            .write()
+            .record(node_id, request_call.sent_at().elapsed());
     }
 
     /// Update the underlying packet for the request via message nonce.
@@ -67,6 +116,11 @@ impl ActiveRequests {
         }
     }
 
+    /// The addresses of all nodes with active requests.
+    pub fn node_addresses(&self) -> impl Iterator<Item = &NodeAddress> {
+        self.active_requests_mapping.keys()
+    }
+
     pub fn get(&self, node_address: &NodeAddress) -> Option<&Vec<RequestCall>> {
         self.active_requests_mapping.get(node_address)
     }
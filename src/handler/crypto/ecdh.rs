@@ -1,9 +1,11 @@
-//! Implements the static ecdh algorithm required by discv5 in terms of the `k256` library.
+//! Implements the static ecdh algorithm required by discv5 in terms of the `k256` library, and
+//! the X25519 key agreement used for Ed25519 identities in terms of the `curve25519-dalek` library.
 use super::k256::{
     self,
     ecdsa::{SigningKey, VerifyingKey},
     elliptic_curve::sec1::ToEncodedPoint,
 };
+use curve25519_dalek::montgomery::MontgomeryPoint;
 
 pub fn ecdh(public_key: &VerifyingKey, secret_key: &SigningKey) -> Vec<u8> {
     k256::PublicKey::from_affine(
@@ -21,3 +23,18 @@ pub fn ecdh(public_key: &VerifyingKey, secret_key: &SigningKey) -> Vec<u8> {
     .as_bytes()
     .to_vec()
 }
+
+/// Performs an X25519 key agreement. Returns `None` if the public key is of low order, in which
+/// case the shared secret would not depend on our secret key.
+pub fn x25519(public_key: &MontgomeryPoint, secret_key: [u8; 32]) -> Option<Vec<u8>> {
+    let secret = public_key.mul_clamped(secret_key).to_bytes();
+    if secret == [0u8; 32] {
+        return None;
+    }
+    Some(secret.to_vec())
+}
+
+/// The X25519 public key of a secret key.
+pub fn x25519_public_key(secret_key: [u8; 32]) -> [u8; 32] {
+    MontgomeryPoint::mul_base_clamped(secret_key).to_bytes()
+}
@@ -1,35 +1,31 @@
 //! Implementation for generating session keys in the Discv5 protocol.
-//! Currently, Diffie-Hellman key agreement is performed with known public key types. Session keys
-//! are then derived using the HKDF (SHA2-256) key derivation function.
+//! Diffie-Hellman key agreement is performed with the [`IdentityScheme`] of the public keys
+//! involved. Session keys are then derived using the HKDF (SHA2-256) key derivation function.
 //!
-//! There is no abstraction in this module as the specification explicitly defines a singular
-//! encryption and key-derivation algorithms. Future versions may abstract some of these to allow
-//! for different algorithms.
+//! The key agreement and id-nonce signature depend on the identity scheme of the recipient's
+//! node record, see the [`scheme`] module. The symmetric encryption and key derivation are
+//! defined by the specification and are not abstracted.
 use crate::{
     error::Error,
     node_info::NodeContact,
     packet::{ChallengeData, MessageNonce},
+    Signer,
 };
 use aes_gcm::{
     aead::{generic_array::GenericArray, Aead, KeyInit, Payload},
     Aes128Gcm,
 };
-use ecdh::ecdh;
 use enr::{
-    k256::{
-        self,
-        ecdsa::{
-            signature::{DigestSigner, DigestVerifier},
-            Signature,
-        },
-        sha2::{Digest, Sha256},
-    },
-    CombinedKey, CombinedPublicKey, NodeId,
+    k256::{self, sha2::Sha256},
+    CombinedPublicKey, EnrPublicKey, NodeId,
 };
 use hkdf::Hkdf;
-use std::convert::TryFrom;
+use zeroize::Zeroizing;
 
 mod ecdh;
+pub mod scheme;
+
+pub use scheme::{IdentityScheme, IdentitySchemes};
 
 const NODE_ID_LENGTH: usize = 32;
 const INFO_LENGTH: usize = 26 + 2 * NODE_ID_LENGTH;
@@ -41,25 +37,19 @@ type Key = [u8; KEY_LENGTH];
 
 /* Session key generation */
 
-/// Generates session and auth-response keys for a nonce and remote ENR. This currently only
-/// supports Secp256k1 signed ENR's. This returns four keys; initiator key, responder key, auth
-/// response key and the ephemeral public key.
+/// Generates session and auth-response keys for a nonce and remote ENR. The ephemeral key is
+/// generated by the identity scheme of the remote's public key. This returns the initiator key,
+/// the responder key and the ephemeral public key.
 pub(crate) fn generate_session_keys(
+    schemes: &IdentitySchemes,
     local_id: &NodeId,
     contact: &NodeContact,
     challenge_data: &ChallengeData,
 ) -> Result<(Key, Key, Vec<u8>), Error> {
-    let (secret, ephem_pk) = {
-        match contact.public_key() {
-            CombinedPublicKey::Secp256k1(remote_pk) => {
-                let ephem_sk = k256::ecdsa::SigningKey::random(&mut rand::thread_rng());
-                let secret = ecdh(&remote_pk, &ephem_sk);
-                let ephem_pk = ephem_sk.verifying_key();
-                (secret, ephem_pk.to_sec1_bytes().to_vec())
-            }
-            CombinedPublicKey::Ed25519(_) => return Err(Error::KeyTypeNotSupported("Ed25519")),
-        }
-    };
+    let remote_pk = contact.public_key();
+    let scheme = schemes.for_public_key(&remote_pk)?;
+    let (ephem_sk, ephem_pk) = scheme.generate_ephemeral_key();
+    let secret = scheme.initiator_ecdh(&remote_pk.encode(), &Zeroizing::new(ephem_sk))?;
 
     let (initiator_key, recipient_key) =
         derive_key(&secret, local_id, &contact.node_id(), challenge_data)?;
@@ -94,24 +84,16 @@ fn derive_key(
 
 /// Derives the session keys for a public key type that matches the local keypair.
 pub(crate) fn derive_keys_from_pubkey(
-    local_key: &CombinedKey,
+    schemes: &IdentitySchemes,
+    local_key: &dyn Signer,
     local_id: &NodeId,
     remote_id: &NodeId,
     challenge_data: &ChallengeData,
     ephem_pubkey: &[u8],
 ) -> Result<(Key, Key), Error> {
-    let secret = {
-        match local_key {
-            CombinedKey::Secp256k1(key) => {
-                // convert remote pubkey into secp256k1 public key
-                // the key type should match our own node record
-                let remote_pubkey = k256::ecdsa::VerifyingKey::from_sec1_bytes(ephem_pubkey)
-                    .map_err(|_| Error::InvalidRemotePublicKey)?;
-                ecdh(&remote_pubkey, key)
-            }
-            CombinedKey::Ed25519(_) => return Err(Error::KeyTypeNotSupported("Ed25519")),
-        }
-    };
+    // the ephemeral key type should match our own node record
+    let scheme = schemes.for_public_key(&local_key.public_key())?;
+    let secret = local_key.ecdh(scheme, ephem_pubkey)?;
 
     derive_key(&secret, remote_id, local_id, challenge_data)
 }
@@ -121,27 +103,21 @@ pub(crate) fn derive_keys_from_pubkey(
 /// Generates a signature of a nonce given a keypair. This prefixes the `NONCE_PREFIX` to the
 /// signature.
 pub(crate) fn sign_nonce(
-    signing_key: &CombinedKey,
+    schemes: &IdentitySchemes,
+    signing_key: &dyn Signer,
     challenge_data: &ChallengeData,
     ephem_pubkey: &[u8],
     dst_id: &NodeId,
 ) -> Result<Vec<u8>, Error> {
     let signing_message = generate_signing_nonce(challenge_data, ephem_pubkey, dst_id);
 
-    match signing_key {
-        CombinedKey::Secp256k1(key) => {
-            let message = Sha256::new().chain_update(signing_message);
-            let signature: Signature = key
-                .try_sign_digest(message)
-                .map_err(|e| Error::Error(format!("Failed to sign message: {e}")))?;
-            Ok(signature.to_vec())
-        }
-        CombinedKey::Ed25519(_) => Err(Error::KeyTypeNotSupported("Ed25519")),
-    }
+    let scheme = schemes.for_public_key(&signing_key.public_key())?;
+    signing_key.sign_id_nonce(scheme, &signing_message)
 }
 
 /// Verifies the authentication header nonce.
 pub(crate) fn verify_authentication_nonce(
+    schemes: &IdentitySchemes,
     remote_pubkey: &CombinedPublicKey,
     remote_ephem_pubkey: &[u8],
     challenge_data: &ChallengeData,
@@ -150,19 +126,9 @@ pub(crate) fn verify_authentication_nonce(
 ) -> bool {
     let signing_nonce = generate_signing_nonce(challenge_data, remote_ephem_pubkey, dst_id);
 
-    match remote_pubkey {
-        CombinedPublicKey::Secp256k1(key) => {
-            if let Ok(sig) = k256::ecdsa::Signature::try_from(sig) {
-                return key
-                    .verify_digest(Sha256::new().chain_update(signing_nonce), &sig)
-                    .is_ok();
-            }
-            false
-        }
-        CombinedPublicKey::Ed25519(_) => {
-            // key not yet supported
-            false
-        }
+    match schemes.for_public_key(remote_pubkey) {
+        Ok(scheme) => scheme.verify_id_nonce(&remote_pubkey.encode(), &signing_nonce, sig),
+        Err(_) => false,
     }
 }
 
@@ -222,8 +188,13 @@ pub(crate) fn encrypt_message(
 mod tests {
     use super::*;
     use crate::ProtocolIdentity;
+    use curve25519_dalek::montgomery::MontgomeryPoint;
+    use ecdh::ecdh;
     use enr::{CombinedKey, Enr, EnrKey};
-    use std::convert::TryInto;
+    use std::{
+        convert::{TryFrom, TryInto},
+        net::Ipv4Addr,
+    };
 
     fn hex_decode(x: &'static str) -> Vec<u8> {
         hex::decode(x).unwrap()
@@ -309,7 +280,14 @@ mod tests {
 
         let challenge_data = ChallengeData::try_from(hex::decode("000000000000000000000000000000006469736376350001010102030405060708090a0b0c00180102030405060708090a0b0c0d0e0f100000000000000000").unwrap().as_slice()).unwrap();
         let key = k256::ecdsa::SigningKey::from_slice(&local_secret_key).unwrap();
-        let sig = sign_nonce(&key.into(), &challenge_data, &ephemeral_pubkey, &dst_id).unwrap();
+        let sig = sign_nonce(
+            &IdentitySchemes::default(),
+            &CombinedKey::from(key),
+            &challenge_data,
+            &ephemeral_pubkey,
+            &dst_id,
+        )
+        .unwrap();
 
         assert_eq!(sig, expected_sig);
     }
@@ -355,12 +333,14 @@ mod tests {
         let challenge_data = ChallengeData::try_from(challenge_data.as_slice()).unwrap();
 
         let (key1, key2, pk) = generate_session_keys(
+            &IdentitySchemes::default(),
             &node1_enr.node_id(),
             &node2_enr.clone().into(),
             &challenge_data,
         )
         .unwrap();
         let (key4, key5) = derive_keys_from_pubkey(
+            &IdentitySchemes::default(),
             &node2_key,
             &node2_enr.node_id(),
             &node1_enr.node_id(),
@@ -373,6 +353,333 @@ mod tests {
         assert_eq!(key2, key5);
     }
 
+    #[test]
+    fn registered_scheme_is_used() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        /// Counts the key agreements of the v4 scheme.
+        struct CountingScheme(Arc<AtomicUsize>);
+
+        impl IdentityScheme for CountingScheme {
+            fn id(&self) -> &'static str {
+                scheme::V4_SCHEME_ID
+            }
+            fn generate_ephemeral_key(&self) -> (Vec<u8>, Vec<u8>) {
+                scheme::V4Scheme.generate_ephemeral_key()
+            }
+            fn initiator_ecdh(
+                &self,
+                static_public_key: &[u8],
+                ephemeral_secret_key: &[u8],
+            ) -> Result<Vec<u8>, Error> {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                scheme::V4Scheme.initiator_ecdh(static_public_key, ephemeral_secret_key)
+            }
+            fn recipient_ecdh(
+                &self,
+                ephemeral_public_key: &[u8],
+                static_secret_key: &[u8],
+            ) -> Result<Vec<u8>, Error> {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                scheme::V4Scheme.recipient_ecdh(ephemeral_public_key, static_secret_key)
+            }
+            fn sign_id_nonce(&self, secret_key: &[u8], input: &[u8]) -> Result<Vec<u8>, Error> {
+                scheme::V4Scheme.sign_id_nonce(secret_key, input)
+            }
+            fn verify_id_nonce(&self, public_key: &[u8], input: &[u8], signature: &[u8]) -> bool {
+                scheme::V4Scheme.verify_id_nonce(public_key, input, signature)
+            }
+        }
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut schemes = IdentitySchemes::default();
+        schemes.register(CountingScheme(count.clone())).unwrap();
+
+        let local_key = CombinedKey::generate_secp256k1();
+        let remote_key = CombinedKey::generate_secp256k1();
+        let remote_enr = Enr::builder()
+            .ip4(Ipv4Addr::LOCALHOST)
+            .udp4(9000)
+            .build(&remote_key)
+            .unwrap();
+        let local_id: NodeId = local_key.public().into();
+        let challenge_data = ChallengeData::try_from([1u8; 63].as_slice()).unwrap();
+
+        let (key1, _, pk) =
+            generate_session_keys(&schemes, &local_id, &remote_enr.into(), &challenge_data)
+                .unwrap();
+        let (key2, _) = derive_keys_from_pubkey(
+            &schemes,
+            &remote_key,
+            &remote_key.public().into(),
+            &local_id,
+            &challenge_data,
+            &pk,
+        )
+        .unwrap();
+        assert_eq!(key1, key2);
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn unknown_scheme_cannot_be_registered() {
+        /// The v4 scheme under a name node records carry no keys of.
+        struct RenamedScheme;
+
+        impl IdentityScheme for RenamedScheme {
+            fn id(&self) -> &'static str {
+                "unknown"
+            }
+            fn generate_ephemeral_key(&self) -> (Vec<u8>, Vec<u8>) {
+                scheme::V4Scheme.generate_ephemeral_key()
+            }
+            fn initiator_ecdh(
+                &self,
+                static_public_key: &[u8],
+                ephemeral_secret_key: &[u8],
+            ) -> Result<Vec<u8>, Error> {
+                scheme::V4Scheme.initiator_ecdh(static_public_key, ephemeral_secret_key)
+            }
+            fn recipient_ecdh(
+                &self,
+                ephemeral_public_key: &[u8],
+                static_secret_key: &[u8],
+            ) -> Result<Vec<u8>, Error> {
+                scheme::V4Scheme.recipient_ecdh(ephemeral_public_key, static_secret_key)
+            }
+            fn sign_id_nonce(&self, secret_key: &[u8], input: &[u8]) -> Result<Vec<u8>, Error> {
+                scheme::V4Scheme.sign_id_nonce(secret_key, input)
+            }
+            fn verify_id_nonce(&self, public_key: &[u8], input: &[u8], signature: &[u8]) -> bool {
+                scheme::V4Scheme.verify_id_nonce(public_key, input, signature)
+            }
+        }
+
+        let mut schemes = IdentitySchemes::default();
+        assert!(matches!(
+            schemes.register(RenamedScheme),
+            Err(Error::KeyTypeNotSupported("unknown"))
+        ));
+        assert!(schemes.get("unknown").is_none());
+    }
+
+    #[test]
+    fn derive_symmetric_keys_ed25519() {
+        let node1_key = CombinedKey::generate_ed25519();
+        let node2_key = CombinedKey::generate_ed25519();
+        let node2_enr = Enr::builder()
+            .ip4(Ipv4Addr::LOCALHOST)
+            .udp4(9000)
+            .build(&node2_key)
+            .unwrap();
+        let node1_id: NodeId = node1_key.public().into();
+
+        let challenge_data = ChallengeData::try_from([1u8; 63].as_slice()).unwrap();
+
+        let (key1, key2, pk) = generate_session_keys(
+            &IdentitySchemes::default(),
+            &node1_id,
+            &node2_enr.clone().into(),
+            &challenge_data,
+        )
+        .unwrap();
+        // X25519 public keys are 32 bytes
+        assert_eq!(pk.len(), 32);
+        let (key3, key4) = derive_keys_from_pubkey(
+            &IdentitySchemes::default(),
+            &node2_key,
+            &node2_enr.node_id(),
+            &node1_id,
+            &challenge_data,
+            &pk,
+        )
+        .unwrap();
+
+        assert_eq!(key1, key3);
+        assert_eq!(key2, key4);
+    }
+
+    #[test]
+    fn ed25519_ephemeral_key_is_x25519() {
+        let node1_id = NodeId::random();
+        let node2_key = CombinedKey::generate_ed25519();
+        let CombinedPublicKey::Ed25519(node2_pk) = node2_key.public() else {
+            panic!("Expected an Ed25519 key");
+        };
+        let challenge_data = ChallengeData::try_from([1u8; 63].as_slice()).unwrap();
+
+        // The initiator sends the X25519 public key of its ephemeral secret.
+        let ephem_sk: [u8; 32] = rand::random();
+        let ephem_pk = MontgomeryPoint::mul_base_clamped(ephem_sk).to_bytes();
+        let secret = ecdh::x25519(&node2_pk.to_montgomery(), ephem_sk).unwrap();
+        let expected = derive_key(
+            &secret,
+            &node1_id,
+            &node2_key.public().into(),
+            &challenge_data,
+        )
+        .unwrap();
+
+        let keys = derive_keys_from_pubkey(
+            &IdentitySchemes::default(),
+            &node2_key,
+            &node2_key.public().into(),
+            &node1_id,
+            &challenge_data,
+            &ephem_pk,
+        )
+        .unwrap();
+        assert_eq!(keys, expected);
+
+        let (ephem_sk, ephem_pk) = scheme::Ed25519Scheme.generate_ephemeral_key();
+        let ephem_sk: [u8; 32] = ephem_sk.try_into().unwrap();
+        assert_eq!(
+            ephem_pk,
+            MontgomeryPoint::mul_base_clamped(ephem_sk).to_bytes()
+        );
+    }
+
+    #[test]
+    fn sign_verify_nonce_ed25519() {
+        let key = CombinedKey::generate_ed25519();
+        let challenge_data = ChallengeData::try_from([1u8; 63].as_slice()).unwrap();
+        let ephem_pubkey = [2u8; 32];
+        let dst_id = NodeId::random();
+
+        let sig = sign_nonce(
+            &IdentitySchemes::default(),
+            &key,
+            &challenge_data,
+            &ephem_pubkey,
+            &dst_id,
+        )
+        .unwrap();
+        assert_eq!(sig.len(), 64);
+        assert!(verify_authentication_nonce(
+            &IdentitySchemes::default(),
+            &key.public(),
+            &ephem_pubkey,
+            &challenge_data,
+            &dst_id,
+            &sig
+        ));
+        // The signature covers the destination
+        assert!(!verify_authentication_nonce(
+            &IdentitySchemes::default(),
+            &key.public(),
+            &ephem_pubkey,
+            &challenge_data,
+            &NodeId::random(),
+            &sig
+        ));
+    }
+
+    #[test]
+    fn mismatched_key_types_are_rejected() {
+        let secp_key = CombinedKey::generate_secp256k1();
+        let ed_key = CombinedKey::generate_ed25519();
+        let secp_enr = Enr::builder()
+            .ip4(Ipv4Addr::LOCALHOST)
+            .udp4(9000)
+            .build(&secp_key)
+            .unwrap();
+        let ed_enr = Enr::builder()
+            .ip4(Ipv4Addr::LOCALHOST)
+            .udp4(9000)
+            .build(&ed_key)
+            .unwrap();
+        let challenge_data = ChallengeData::try_from([1u8; 63].as_slice()).unwrap();
+
+        // An ephemeral key generated for a secp256k1 record cannot be used by an Ed25519 node and
+        // vice versa.
+        let (_, _, secp_ephem) = generate_session_keys(
+            &IdentitySchemes::default(),
+            &ed_enr.node_id(),
+            &secp_enr.clone().into(),
+            &challenge_data,
+        )
+        .unwrap();
+        let (_, _, ed_ephem) = generate_session_keys(
+            &IdentitySchemes::default(),
+            &secp_enr.node_id(),
+            &ed_enr.clone().into(),
+            &challenge_data,
+        )
+        .unwrap();
+        assert!(matches!(
+            derive_keys_from_pubkey(
+                &IdentitySchemes::default(),
+                &ed_key,
+                &ed_enr.node_id(),
+                &secp_enr.node_id(),
+                &challenge_data,
+                &secp_ephem,
+            ),
+            Err(Error::InvalidRemotePublicKey)
+        ));
+        assert!(matches!(
+            derive_keys_from_pubkey(
+                &IdentitySchemes::default(),
+                &secp_key,
+                &secp_enr.node_id(),
+                &ed_enr.node_id(),
+                &challenge_data,
+                &ed_ephem,
+            ),
+            Err(Error::InvalidRemotePublicKey)
+        ));
+        // A low order point is rejected.
+        assert!(matches!(
+            derive_keys_from_pubkey(
+                &IdentitySchemes::default(),
+                &ed_key,
+                &ed_enr.node_id(),
+                &secp_enr.node_id(),
+                &challenge_data,
+                &[0u8; 32],
+            ),
+            Err(Error::InvalidRemotePublicKey)
+        ));
+
+        // A signature only verifies against the key type that produced it.
+        let dst_id = NodeId::random();
+        let secp_sig = sign_nonce(
+            &IdentitySchemes::default(),
+            &secp_key,
+            &challenge_data,
+            &ed_ephem,
+            &dst_id,
+        )
+        .unwrap();
+        let ed_sig = sign_nonce(
+            &IdentitySchemes::default(),
+            &ed_key,
+            &challenge_data,
+            &ed_ephem,
+            &dst_id,
+        )
+        .unwrap();
+        assert!(!verify_authentication_nonce(
+            &IdentitySchemes::default(),
+            &ed_key.public(),
+            &ed_ephem,
+            &challenge_data,
+            &dst_id,
+            &secp_sig
+        ));
+        assert!(!verify_authentication_nonce(
+            &IdentitySchemes::default(),
+            &secp_key.public(),
+            &ed_ephem,
+            &challenge_data,
+            &dst_id,
+            &ed_sig
+        ));
+    }
+
     #[test]
     fn encrypt_decrypt() {
         // aad
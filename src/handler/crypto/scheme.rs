@@ -0,0 +1,260 @@
+//! Identity schemes define the key agreement and id-nonce signatures of the handshake.
+//!
+//! The key agreement of a handshake is performed with the scheme of the recipient's node record:
+//! the initiator generates an ephemeral key of that scheme and agrees on a secret with the
+//! recipient's static key, and the recipient agrees on the same secret using its static secret key
+//! and the ephemeral public key. Each node signs the id-nonce with the scheme of its own record.
+//!
+//! The `v4` (secp256k1) and `ed25519` schemes are built in. Node records only carry keys of these
+//! two schemes, so registering a scheme of another name fails. Alternative implementations of the
+//! built-in schemes can replace them via
+//! [`ConfigBuilder::identity_scheme`](crate::ConfigBuilder::identity_scheme).
+use super::{
+    ecdh::{ecdh, x25519, x25519_public_key},
+    k256::{
+        self,
+        ecdsa::{
+            signature::{DigestSigner, DigestVerifier},
+            Signature,
+        },
+        sha2::{Digest, Sha256},
+    },
+};
+use crate::error::Error;
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use enr::{
+    ed25519_dalek::{self, Signer as _},
+    CombinedPublicKey,
+};
+use std::{
+    collections::HashMap,
+    convert::{TryFrom, TryInto},
+    fmt,
+    sync::Arc,
+};
+
+/// The name of the secp256k1 identity scheme.
+pub const V4_SCHEME_ID: &str = "v4";
+
+/// The name of the Ed25519 identity scheme.
+pub const ED25519_SCHEME_ID: &str = "ed25519";
+
+/// The cryptographic operations of an identity scheme used in the handshake.
+///
+/// Keys are passed in their encoded form. Public keys are encoded as in node records, secret keys
+/// as returned by [`CombinedKey::encode`](enr::CombinedKey::encode).
+pub trait IdentityScheme: Send + Sync {
+    /// The name of the scheme.
+    fn id(&self) -> &'static str;
+
+    /// Generates an ephemeral keypair, returning the secret key and the encoded public key.
+    fn generate_ephemeral_key(&self) -> (Vec<u8>, Vec<u8>);
+
+    /// Agrees on a shared secret as the initiator of a handshake, given the static public key of
+    /// the recipient as encoded in its node record and the secret key of an ephemeral key
+    /// generated by this scheme.
+    fn initiator_ecdh(
+        &self,
+        static_public_key: &[u8],
+        ephemeral_secret_key: &[u8],
+    ) -> Result<Vec<u8>, Error>;
+
+    /// Agrees on a shared secret as the recipient of a handshake, given the ephemeral public key
+    /// sent by the initiator and our static secret key. The ephemeral public key was received
+    /// from the remote and must be validated.
+    fn recipient_ecdh(
+        &self,
+        ephemeral_public_key: &[u8],
+        static_secret_key: &[u8],
+    ) -> Result<Vec<u8>, Error>;
+
+    /// Signs the id-nonce signing input with a static secret key.
+    fn sign_id_nonce(&self, secret_key: &[u8], input: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// Verifies a signature of the id-nonce signing input against a static public key.
+    fn verify_id_nonce(&self, public_key: &[u8], input: &[u8], signature: &[u8]) -> bool;
+}
+
+/// The secp256k1 identity scheme defined by the specification.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct V4Scheme;
+
+impl IdentityScheme for V4Scheme {
+    fn id(&self) -> &'static str {
+        V4_SCHEME_ID
+    }
+
+    fn generate_ephemeral_key(&self) -> (Vec<u8>, Vec<u8>) {
+        let ephem_sk = k256::ecdsa::SigningKey::random(&mut rand::thread_rng());
+        let ephem_pk = ephem_sk.verifying_key().to_sec1_bytes().to_vec();
+        (ephem_sk.to_bytes().to_vec(), ephem_pk)
+    }
+
+    fn initiator_ecdh(
+        &self,
+        static_public_key: &[u8],
+        ephemeral_secret_key: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        v4_ecdh(static_public_key, ephemeral_secret_key)
+    }
+
+    fn recipient_ecdh(
+        &self,
+        ephemeral_public_key: &[u8],
+        static_secret_key: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        v4_ecdh(ephemeral_public_key, static_secret_key)
+    }
+
+    fn sign_id_nonce(&self, secret_key: &[u8], input: &[u8]) -> Result<Vec<u8>, Error> {
+        let key =
+            k256::ecdsa::SigningKey::from_slice(secret_key).map_err(|_| Error::InvalidSecretKey)?;
+        let message = Sha256::new().chain_update(input);
+        let signature: Signature = key
+            .try_sign_digest(message)
+            .map_err(|e| Error::Error(format!("Failed to sign message: {e}")))?;
+        Ok(signature.to_vec())
+    }
+
+    fn verify_id_nonce(&self, public_key: &[u8], input: &[u8], signature: &[u8]) -> bool {
+        let (Ok(key), Ok(sig)) = (
+            k256::ecdsa::VerifyingKey::from_sec1_bytes(public_key),
+            Signature::try_from(signature),
+        ) else {
+            return false;
+        };
+        key.verify_digest(Sha256::new().chain_update(input), &sig)
+            .is_ok()
+    }
+}
+
+fn v4_ecdh(public_key: &[u8], secret_key: &[u8]) -> Result<Vec<u8>, Error> {
+    let public_key = k256::ecdsa::VerifyingKey::from_sec1_bytes(public_key)
+        .map_err(|_| Error::InvalidRemotePublicKey)?;
+    let secret_key =
+        k256::ecdsa::SigningKey::from_slice(secret_key).map_err(|_| Error::InvalidSecretKey)?;
+    Ok(ecdh(&public_key, &secret_key))
+}
+
+/// The Ed25519 identity scheme. Ephemeral keys are X25519 keys, sent as 32-byte public keys. The
+/// key agreement is X25519 against the Montgomery form of the recipient's Ed25519 key, and the
+/// id-nonce is signed with Ed25519 directly.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ed25519Scheme;
+
+impl IdentityScheme for Ed25519Scheme {
+    fn id(&self) -> &'static str {
+        ED25519_SCHEME_ID
+    }
+
+    fn generate_ephemeral_key(&self) -> (Vec<u8>, Vec<u8>) {
+        let ephem_sk: [u8; 32] = rand::random();
+        let ephem_pk = x25519_public_key(ephem_sk).to_vec();
+        (ephem_sk.to_vec(), ephem_pk)
+    }
+
+    fn initiator_ecdh(
+        &self,
+        static_public_key: &[u8],
+        ephemeral_secret_key: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        let public_key = ed25519_dalek::VerifyingKey::try_from(static_public_key)
+            .map_err(|_| Error::InvalidRemotePublicKey)?;
+        let secret_key: [u8; 32] = ephemeral_secret_key
+            .try_into()
+            .map_err(|_| Error::InvalidSecretKey)?;
+        x25519(&public_key.to_montgomery(), secret_key).ok_or(Error::InvalidRemotePublicKey)
+    }
+
+    fn recipient_ecdh(
+        &self,
+        ephemeral_public_key: &[u8],
+        static_secret_key: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        // the remote ephemeral key is an X25519 public key
+        let public_key: [u8; 32] = ephemeral_public_key
+            .try_into()
+            .map_err(|_| Error::InvalidRemotePublicKey)?;
+        let secret_key = ed25519_signing_key(static_secret_key)?;
+        x25519(&MontgomeryPoint(public_key), secret_key.to_scalar_bytes())
+            .ok_or(Error::InvalidRemotePublicKey)
+    }
+
+    fn sign_id_nonce(&self, secret_key: &[u8], input: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(ed25519_signing_key(secret_key)?.sign(input).to_vec())
+    }
+
+    fn verify_id_nonce(&self, public_key: &[u8], input: &[u8], signature: &[u8]) -> bool {
+        let (Ok(key), Ok(sig)) = (
+            ed25519_dalek::VerifyingKey::try_from(public_key),
+            ed25519_dalek::Signature::try_from(signature),
+        ) else {
+            return false;
+        };
+        key.verify_strict(input, &sig).is_ok()
+    }
+}
+
+fn ed25519_signing_key(secret_key: &[u8]) -> Result<ed25519_dalek::SigningKey, Error> {
+    let seed: &[u8; 32] = secret_key.try_into().map_err(|_| Error::InvalidSecretKey)?;
+    Ok(ed25519_dalek::SigningKey::from_bytes(seed))
+}
+
+/// The identity schemes available to the handshake, by name.
+#[derive(Clone)]
+pub struct IdentitySchemes {
+    schemes: HashMap<&'static str, Arc<dyn IdentityScheme>>,
+}
+
+impl Default for IdentitySchemes {
+    fn default() -> Self {
+        let mut schemes = IdentitySchemes {
+            schemes: HashMap::new(),
+        };
+        schemes.insert(V4Scheme);
+        schemes.insert(Ed25519Scheme);
+        schemes
+    }
+}
+
+impl fmt::Debug for IdentitySchemes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.schemes.keys()).finish()
+    }
+}
+
+impl IdentitySchemes {
+    /// Replaces the implementation of a built-in scheme, `v4` or `ed25519`.
+    ///
+    /// Node records only carry keys of the built-in schemes, so a scheme of any other name would
+    /// never be selected and is rejected with [`Error::KeyTypeNotSupported`].
+    pub fn register(&mut self, scheme: impl IdentityScheme + 'static) -> Result<(), Error> {
+        let id = scheme.id();
+        if id != V4_SCHEME_ID && id != ED25519_SCHEME_ID {
+            return Err(Error::KeyTypeNotSupported(id));
+        }
+        self.insert(scheme);
+        Ok(())
+    }
+
+    fn insert(&mut self, scheme: impl IdentityScheme + 'static) {
+        self.schemes.insert(scheme.id(), Arc::new(scheme));
+    }
+
+    /// The scheme of the given name.
+    pub fn get(&self, id: &str) -> Option<&dyn IdentityScheme> {
+        self.schemes.get(id).map(|scheme| scheme.as_ref())
+    }
+
+    /// The scheme of records signed with the given public key.
+    pub(crate) fn for_public_key(
+        &self,
+        public_key: &CombinedPublicKey,
+    ) -> Result<&dyn IdentityScheme, Error> {
+        let id = match public_key {
+            CombinedPublicKey::Secp256k1(_) => V4_SCHEME_ID,
+            CombinedPublicKey::Ed25519(_) => ED25519_SCHEME_ID,
+        };
+        self.get(id).ok_or(Error::KeyTypeNotSupported(id))
+    }
+}
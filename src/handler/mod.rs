@@ -32,25 +32,26 @@ use crate::{
     error::{Error, RequestError},
     packet::{ChallengeData, IdNonce, MessageNonce, Packet, PacketKind},
     rpc::{Message, Request, RequestBody, RequestId, Response, ResponseBody},
+    signer::rotate_local_key,
     socket,
-    socket::{FilterConfig, Socket, UnrecognizedFrame},
-    Enr, ProtocolIdentity,
+    socket::{FilterConfig, RateLimitedAction, RequestRateLimiter, Socket, UnrecognizedFrame},
+    Enr, ProtocolIdentity, Signer,
 };
 use delay_map::HashMapDelay;
-use enr::{CombinedKey, NodeId};
+use enr::{Error as EnrError, NodeId};
 use futures::prelude::*;
 use more_asserts::debug_unreachable;
 use parking_lot::RwLock;
 use smallvec::SmallVec;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     convert::TryFrom,
     default::Default,
     net::SocketAddr,
     pin::Pin,
     sync::{atomic::Ordering, Arc},
     task::{Context, Poll},
-    time::{Duration, Instant},
+    time::Duration,
 };
 use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, error, trace, warn};
@@ -58,10 +59,14 @@ use tracing::{debug, error, trace, warn};
 mod active_requests;
 mod crypto;
 mod request_call;
+mod rtt;
 mod session;
 mod tests;
 
 pub use crate::node_info::{NodeAddress, NodeContact};
+pub use crypto::{scheme, IdentityScheme, IdentitySchemes};
+pub use rtt::PeerRtt;
+pub(crate) use rtt::RttTable;
 
 use crate::metrics::METRICS;
 
@@ -74,6 +79,13 @@ use session::Session;
 // seconds).
 const BANNED_NODES_CHECK: u64 = 300; // Check every 5 minutes.
 
+// The time interval to prune the request rate limiter (in seconds).
+const REQUEST_LIMITER_PRUNE: u64 = 30;
+
+// The maximum number of messages from a node queued while the handshake establishing its session
+// is signed.
+const MAX_QUEUED_HANDSHAKE_MESSAGES: usize = 16;
+
 /// Messages sent from the application layer to `Handler`.
 #[derive(Debug, Clone, PartialEq)]
 #[allow(clippy::large_enum_variant)]
@@ -104,6 +116,27 @@ pub enum HandlerIn {
     /// The `WhoAreYouRef` is sent out in the `HandlerOut::WhoAreYou` event and should
     /// be returned here to submit the application's response.
     WhoAreYou(WhoAreYouRef, Option<Enr>),
+
+    /// Replaces the local key and signs the local ENR with it. Sessions are bound to the local
+    /// node id, so all sessions are dropped and all requests in progress fail. The result is
+    /// reported with `HandlerOut::KeyRotated`.
+    RotateKey(NewKey),
+}
+
+/// A key replacing the key of the local node. See `HandlerIn::RotateKey`.
+#[derive(Clone)]
+pub struct NewKey(pub Arc<dyn Signer>);
+
+impl std::fmt::Debug for NewKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("NewKey").field(&self.0.public_key()).finish()
+    }
+}
+
+impl PartialEq for NewKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.public_key() == other.0.public_key()
+    }
 }
 
 /// Messages sent between a node on the network and `Handler`.
@@ -143,6 +176,9 @@ pub enum HandlerOut {
     UnrecognizedFrame(UnrecognizedFrame),
     /// These sessions have expired from the cache.
     ExpiredSessions(Vec<NodeAddress>),
+    /// The local key has been replaced, in response to `HandlerIn::RotateKey`. Contains the
+    /// previous and the new node id, or the error signing the local ENR with the new key.
+    KeyRotated(Result<(NodeId, NodeId), EnrError>),
 }
 
 /// How we connected to the node.
@@ -177,6 +213,35 @@ enum HandlerReqId {
     External(RequestId),
 }
 
+/// A handshake awaiting the signature of the local key.
+enum PendingHandshake {
+    /// A response to the WHOAREYOU challenge of a remote to one of our requests.
+    Outgoing(Box<RequestCall>),
+    /// A handshake answering a challenge we sent. The message of the handshake packet, and any
+    /// messages received from the node in the meantime, are handled once the session is
+    /// established.
+    Incoming(Vec<QueuedMessage>),
+}
+
+/// A message received from a node, as passed to `Handler::handle_message`.
+struct QueuedMessage {
+    message_nonce: MessageNonce,
+    message: Vec<u8>,
+    authenticated_data: Vec<u8>,
+}
+
+/// A handshake signed by the local key, sent back to the handler loop.
+enum SignedHandshake {
+    /// The handshake packet and session of a `PendingHandshake::Outgoing`.
+    Outgoing(Result<(Packet, Session), Error>),
+    /// The session and remote ENR of a `PendingHandshake::Incoming`.
+    Incoming(Result<(Session, Enr), Error>),
+}
+
+/// A signed handshake with the node address and id of the handshake. `None` if the signer failed
+/// or timed out.
+type SignedHandshakeResult = (NodeAddress, u64, Option<SignedHandshake>);
+
 /// A request queued for sending.
 struct PendingRequest {
     contact: NodeContact,
@@ -197,15 +262,17 @@ impl From<&HandlerReqId> for RequestId {
 pub struct Handler {
     /// Configuration for the discv5 service.
     request_retries: u8,
-    /// The local node id to save unnecessary read locks on the ENR. The NodeID should not change
-    /// during the operation of the server.
+    /// The local node id to save unnecessary read locks on the ENR. The NodeID only changes when
+    /// the local key is rotated.
     node_id: NodeId,
     /// The protocol id used
     protocol_identity: ProtocolIdentity,
     /// The local ENR.
     enr: Arc<RwLock<Enr>>,
     /// The key to sign the ENR and set up encrypted communication with peers.
-    key: Arc<RwLock<CombinedKey>>,
+    key: Arc<RwLock<Arc<dyn Signer>>>,
+    /// The identity schemes used in handshakes.
+    identity_schemes: IdentitySchemes,
     /// Active requests that are awaiting a response.
     active_requests: ActiveRequests,
     /// The expected responses by SocketAddr which allows packets to pass the underlying filter.
@@ -216,6 +283,18 @@ pub struct Handler {
     active_challenges: HashMapDelay<NodeAddress, Challenge>,
     /// Established sessions with peers.
     sessions: LruTimeCache<NodeAddress, Session>,
+    /// Handshakes awaiting the signature of the local key, with the id of their signing task.
+    pending_handshakes: HashMap<NodeAddress, (u64, PendingHandshake)>,
+    /// The id of the next handshake signing task.
+    next_handshake_id: u64,
+    /// The time after which a handshake awaiting its signature fails.
+    signing_timeout: Duration,
+    /// The channel signing tasks send signed handshakes to.
+    signed_handshakes_send: mpsc::UnboundedSender<SignedHandshakeResult>,
+    /// The channel to receive signed handshakes from signing tasks.
+    signed_handshakes_recv: mpsc::UnboundedReceiver<SignedHandshakeResult>,
+    /// Rate limits decrypted requests before they are passed to the application.
+    request_rate_limiter: Option<RequestRateLimiter>,
     /// The channel to receive messages from the application layer.
     service_recv: mpsc::UnboundedReceiver<HandlerIn>,
     /// The channel to send messages to the application layer.
@@ -238,7 +317,8 @@ impl Handler {
     /// A new Session service which instantiates the UDP socket send/recv tasks.
     pub async fn spawn(
         enr: Arc<RwLock<Enr>>,
-        key: Arc<RwLock<CombinedKey>>,
+        key: Arc<RwLock<Arc<dyn Signer>>>,
+        rtts: Arc<RwLock<RttTable>>,
         config: Config,
     ) -> Result<HandlerReturn, std::io::Error> {
         let (exit_sender, exit) = oneshot::channel();
@@ -298,6 +378,8 @@ impl Handler {
         // Attempt to bind to the socket before spinning up the send/recv tasks.
         let socket = Socket::new(socket_config).await?;
 
+        let (signed_handshakes_send, signed_handshakes_recv) = mpsc::unbounded_channel();
+
         config
             .executor
             .clone()
@@ -309,7 +391,12 @@ impl Handler {
                     protocol_identity: config.protocol_identity,
                     enr,
                     key,
-                    active_requests: ActiveRequests::new(config.request_timeout),
+                    identity_schemes: config.identity_schemes.clone(),
+                    active_requests: ActiveRequests::new(
+                        config.request_timeout,
+                        rtts,
+                        config.adaptive_request_timeout,
+                    ),
                     pending_requests: HashMap::new(),
                     filter_expected_responses,
                     sessions: LruTimeCache::new(
@@ -317,6 +404,12 @@ impl Handler {
                         Some(config.session_cache_capacity),
                     ),
                     active_challenges: HashMapDelay::new(config.request_timeout),
+                    pending_handshakes: HashMap::new(),
+                    next_handshake_id: 0,
+                    signing_timeout: config.request_timeout,
+                    signed_handshakes_send,
+                    signed_handshakes_recv,
+                    request_rate_limiter: config.request_rate_limiter,
                     service_recv,
                     service_send,
                     listen_sockets,
@@ -333,6 +426,8 @@ impl Handler {
     /// The main execution loop for the handler.
     async fn start(&mut self) {
         let mut banned_nodes_check = tokio::time::interval(Duration::from_secs(BANNED_NODES_CHECK));
+        let mut request_limiter_prune =
+            tokio::time::interval(Duration::from_secs(REQUEST_LIMITER_PRUNE));
 
         loop {
             tokio::select! {
@@ -349,6 +444,7 @@ impl Handler {
                         }
                         HandlerIn::Response(dst, response) => self.send_response(dst, *response).await,
                         HandlerIn::WhoAreYou(wru_ref, enr) => self.send_challenge(wru_ref, enr).await,
+                        HandlerIn::RotateKey(NewKey(key)) => self.rotate_key(key).await,
                     }
                 }
                 Some(incoming_packet) = self.socket.recv.recv() => {
@@ -367,6 +463,9 @@ impl Handler {
                         }
                     }
                 }
+                Some((node_address, id, signed)) = self.signed_handshakes_recv.recv() => {
+                    self.handle_signed_handshake(node_address, id, signed).await;
+                }
                 Some(Ok((node_address, active_request))) = self.active_requests.next() => {
                     self.handle_request_timeout(node_address, active_request).await;
                 }
@@ -376,6 +475,11 @@ impl Handler {
                     self.send_pending_requests(&node_address).await;
                 }
                 _ = banned_nodes_check.tick() => self.unban_nodes_check(), // Unban nodes that are past the timeout
+                _ = request_limiter_prune.tick(), if self.request_rate_limiter.is_some() => {
+                    if let Some(limiter) = self.request_rate_limiter.as_mut() {
+                        limiter.prune();
+                    }
+                }
                 _ = &mut self.exit => {
                     return;
                 }
@@ -500,6 +604,7 @@ impl Handler {
         // If there is already an active challenge (WHOAREYOU sent) for this node, or if we are
         // awaiting a session with this node to be established, add the request to pending requests.
         if self.active_challenges.get(&node_address).is_some()
+            || self.pending_handshakes.contains_key(&node_address)
             || self.is_awaiting_session_to_be_established(&node_address)
         {
             trace!(%node_address, "Request queued for node");
@@ -575,6 +680,46 @@ impl Handler {
         }
     }
 
+    /// Checks a decrypted request against the request rate limiter. Requests exceeding their
+    /// quota are either dropped or answered with an empty response, depending on the configured
+    /// `RateLimitedAction`. Returns whether the request should be passed to the application.
+    async fn check_request_rate_limit(
+        &mut self,
+        node_address: &NodeAddress,
+        request: &Request,
+    ) -> bool {
+        let Some(limiter) = self.request_rate_limiter.as_mut() else {
+            return true;
+        };
+        if limiter.allows(&node_address.node_id, &request.body).is_ok() {
+            return true;
+        }
+
+        METRICS
+            .requests_rate_limited
+            .fetch_add(1, Ordering::Relaxed);
+        debug!(%node_address, %request, "Inbound request exceeds its rate limit");
+
+        let body = match (limiter.action(), &request.body) {
+            (RateLimitedAction::RespondEmpty, RequestBody::FindNode { .. }) => {
+                ResponseBody::Nodes {
+                    total: 1,
+                    nodes: Vec::new(),
+                }
+            }
+            (RateLimitedAction::RespondEmpty, RequestBody::Talk { .. }) => ResponseBody::Talk {
+                response: Vec::new(),
+            },
+            _ => return false,
+        };
+        let response = Response {
+            id: request.id.clone(),
+            body,
+        };
+        self.send_response(node_address.clone(), response).await;
+        false
+    }
+
     /// This is called in response to a `HandlerOut::WhoAreYou` event. The applications finds the
     /// highest known ENR for a node then we respond to the node with a WHOAREYOU packet.
     async fn send_challenge(&mut self, wru_ref: WhoAreYouRef, remote_enr: Option<Enr>) {
@@ -586,6 +731,11 @@ impl Handler {
             return;
         }
 
+        if self.pending_handshakes.contains_key(&node_address) {
+            debug!(%node_address, "Handshake already in progress, not sending WHOAREYOU");
+            return;
+        }
+
         // NOTE: We do not check if we have an active session here. This was checked before
         // requesting the ENR from the service. It could be the case we have established a session
         // in the meantime, we allow this challenge to establish a second session in the event this
@@ -627,7 +777,7 @@ impl Handler {
     ) {
         // Check that this challenge matches a known active request.
         // If this message passes all the requisite checks, a request call is returned.
-        let mut request_call = match self.active_requests.remove_by_nonce(&request_nonce) {
+        let request_call = match self.active_requests.remove_by_nonce(&request_nonce) {
             Some((node_address, request_call)) => {
                 // Verify that the src_addresses match
                 if node_address.socket_addr != src_address {
@@ -694,16 +844,46 @@ impl Handler {
             None
         };
 
-        // Generate a new session and authentication packet
-        let (auth_packet, mut session) = match Session::encrypt_with_header(
-            request_call.contact(),
-            self.key.clone(),
-            updated_enr,
-            &self.node_id,
-            self.protocol_identity,
-            &challenge_data,
-            &request_call.encode(),
-        ) {
+        // Generate a new session and authentication packet. The handshake is sent once it is
+        // signed.
+        let node_address = request_call.contact().node_address();
+        if self.pending_handshakes.contains_key(&node_address) {
+            // A handshake with this node is already being signed. The request is replayed once
+            // the session is established, or resent when it times out.
+            trace!(%node_address, "Handshake already in progress");
+            self.insert_active_request(request_call);
+            return;
+        }
+        let identity_schemes = self.identity_schemes.clone();
+        let contact = request_call.contact().clone();
+        let local_node_id = self.node_id;
+        let protocol_identity = self.protocol_identity;
+        let message = request_call.encode();
+        self.sign_handshake(
+            node_address,
+            PendingHandshake::Outgoing(Box::new(request_call)),
+            move |signer| {
+                SignedHandshake::Outgoing(Session::encrypt_with_header(
+                    &identity_schemes,
+                    &contact,
+                    signer,
+                    updated_enr,
+                    &local_node_id,
+                    protocol_identity,
+                    &challenge_data,
+                    &message,
+                ))
+            },
+        );
+    }
+
+    /// Sends the signed handshake responding to the WHOAREYOU challenge of a request.
+    async fn send_handshake(
+        &mut self,
+        mut request_call: RequestCall,
+        result: Result<(Packet, Session), Error>,
+    ) {
+        let (auth_packet, mut session) = match result {
             Ok(v) => v,
             Err(e) => {
                 error!(error = ?e, "Could not generate a session");
@@ -793,6 +973,8 @@ impl Handler {
         }
         self.new_session(node_address.clone(), session, Some(auth_message_nonce))
             .await;
+        // Requests queued while the handshake was signed are sent on the new session.
+        self.send_pending_requests(&node_address).await;
     }
 
     /// Verifies a Node ENR to it's observed address. If it fails, any associated session is also
@@ -843,91 +1025,187 @@ impl Handler {
         );
 
         if let Some(challenge) = self.active_challenges.remove(&node_address) {
-            match Session::establish_from_challenge(
-                self.key.clone(),
-                &self.node_id,
-                &node_address.node_id,
-                challenge,
-                id_nonce_sig,
-                ephem_pubkey,
-                enr_record,
-            ) {
-                Ok((session, enr)) => {
-                    // Remove the expected response for the challenge.
-                    self.remove_expected_response(node_address.socket_addr);
-                    // Receiving an AuthResponse must give us an up-to-date view of the node ENR.
-                    // Verify the ENR is valid
-                    if self.verify_enr(&enr, &node_address) {
-                        // Session is valid
-                        // Notify the application
-                        // The session established here are from WHOAREYOU packets that we sent.
-                        // This occurs when a node established a connection with us.
-                        if let Err(e) = self
-                            .service_send
-                            .send(HandlerOut::Established(
-                                enr,
-                                node_address.socket_addr,
-                                ConnectionDirection::Incoming,
-                            ))
-                            .await
-                        {
-                            warn!(error = %e, "Failed to inform of established session")
-                        }
-                    } else {
-                        // IP's or NodeAddress don't match.
-                        //
-                        // We still handle the request, but we do not add the ENR to our routing
-                        // table or consider the ENR valid.
-                        debug!(
-                            udp4_socket = ?enr.udp4_socket(),
-                            udp6_socket = ?enr.udp6_socket(),
-                            expected = %node_address,
-                            "Session has invalid ENR",
-                        );
-
-                        // The ENR doesn't verify. Notify application.
-                        self.notify_unverifiable_enr(
+            // The session is established once the session keys are derived with the local key.
+            let identity_schemes = self.identity_schemes.clone();
+            let local_node_id = self.node_id;
+            let remote_node_id = node_address.node_id;
+            let id_nonce_sig = id_nonce_sig.to_vec();
+            let ephem_pubkey = ephem_pubkey.to_vec();
+            self.sign_handshake(
+                node_address,
+                PendingHandshake::Incoming(vec![QueuedMessage {
+                    message_nonce,
+                    message: message.to_vec(),
+                    authenticated_data: authenticated_data.to_vec(),
+                }]),
+                move |signer| {
+                    SignedHandshake::Incoming(Session::establish_from_challenge(
+                        &identity_schemes,
+                        signer,
+                        &local_node_id,
+                        &remote_node_id,
+                        challenge,
+                        &id_nonce_sig,
+                        &ephem_pubkey,
+                        enr_record,
+                    ))
+                },
+            );
+        } else {
+            warn!(
+                node_id = %node_address.node_id, addr = %node_address.socket_addr,
+                "Received an authenticated header without a matching WHOAREYOU request",
+            );
+        }
+    }
+
+    /// Establishes the session of a signed handshake answering a challenge we sent, and handles
+    /// the message of the handshake packet and the messages queued while it was signed.
+    async fn establish_from_handshake(
+        &mut self,
+        node_address: NodeAddress,
+        messages: Vec<QueuedMessage>,
+        result: Result<(Session, Enr), Error>,
+    ) {
+        match result {
+            Ok((session, enr)) => {
+                // Remove the expected response for the challenge.
+                self.remove_expected_response(node_address.socket_addr);
+                // Receiving an AuthResponse must give us an up-to-date view of the node ENR.
+                // Verify the ENR is valid
+                if self.verify_enr(&enr, &node_address) {
+                    // Session is valid
+                    // Notify the application
+                    // The session established here are from WHOAREYOU packets that we sent.
+                    // This occurs when a node established a connection with us.
+                    if let Err(e) = self
+                        .service_send
+                        .send(HandlerOut::Established(
                             enr,
                             node_address.socket_addr,
-                            node_address.node_id,
-                        )
-                        .await;
+                            ConnectionDirection::Incoming,
+                        ))
+                        .await
+                    {
+                        warn!(error = %e, "Failed to inform of established session")
                     }
+                } else {
+                    // IP's or NodeAddress don't match.
+                    //
+                    // We still handle the request, but we do not add the ENR to our routing
+                    // table or consider the ENR valid.
+                    debug!(
+                        udp4_socket = ?enr.udp4_socket(),
+                        udp6_socket = ?enr.udp6_socket(),
+                        expected = %node_address,
+                        "Session has invalid ENR",
+                    );
+
+                    // The ENR doesn't verify. Notify application.
+                    self.notify_unverifiable_enr(
+                        enr,
+                        node_address.socket_addr,
+                        node_address.node_id,
+                    )
+                    .await;
+                }
 
-                    // When (re-)establishing a session from an outgoing challenge, we do not need
-                    // to filter out this request from active requests, so we do not pass
-                    // the message nonce on to `new_session`.
-                    self.new_session(node_address.clone(), session, None).await;
+                // When (re-)establishing a session from an outgoing challenge, we do not need
+                // to filter out this request from active requests, so we do not pass
+                // the message nonce on to `new_session`.
+                self.new_session(node_address.clone(), session, None).await;
+                for message in messages {
                     self.handle_message(
                         node_address.clone(),
-                        message_nonce,
-                        message,
-                        authenticated_data,
+                        message.message_nonce,
+                        &message.message,
+                        &message.authenticated_data,
                     )
                     .await;
                 }
-                Err(Error::InvalidChallengeSignature(challenge)) => {
-                    warn!(
-                        %node_address,
-                        "Authentication header contained invalid signature. Ignoring packet from node",
-                    );
-                    // insert back the challenge
-                    self.active_challenges.insert(node_address, *challenge);
-                }
-                Err(e) => {
-                    warn!(
-                        error = ?e,
-                        "Invalid Authentication header. Dropping session",
-                    );
-                    self.fail_session(&node_address, RequestError::InvalidRemotePacket, true)
-                        .await;
-                }
+                // Requests queued while the handshake was signed are sent on the new session.
+                self.send_pending_requests(&node_address).await;
+            }
+            Err(Error::InvalidChallengeSignature(challenge)) => {
+                warn!(
+                    %node_address,
+                    "Authentication header contained invalid signature. Ignoring packet from node",
+                );
+                // insert back the challenge
+                self.active_challenges.insert(node_address, *challenge);
+            }
+            Err(e) => {
+                warn!(
+                    error = ?e,
+                    "Invalid Authentication header. Dropping session",
+                );
+                self.fail_session(&node_address, RequestError::InvalidRemotePacket, true)
+                    .await;
+            }
+        }
+    }
+
+    /// Signs a handshake with the local key. The signer may block, so the handshake is signed on
+    /// a blocking thread without holding the lock on the key, and the result is sent back to the
+    /// handler loop. Until then the handshake is kept in `pending_handshakes`.
+    fn sign_handshake(
+        &mut self,
+        node_address: NodeAddress,
+        handshake: PendingHandshake,
+        sign: impl FnOnce(&dyn Signer) -> SignedHandshake + Send + 'static,
+    ) {
+        let id = self.next_handshake_id;
+        self.next_handshake_id = self.next_handshake_id.wrapping_add(1);
+        self.pending_handshakes
+            .insert(node_address.clone(), (id, handshake));
+
+        let signer = self.key.read().clone();
+        let timeout = self.signing_timeout;
+        let signed_handshakes = self.signed_handshakes_send.clone();
+        tokio::spawn(async move {
+            let task = tokio::task::spawn_blocking(move || sign(signer.as_ref()));
+            let signed = tokio::time::timeout(timeout, task)
+                .await
+                .ok()
+                .and_then(Result::ok);
+            // The handler may have shut down in the meantime.
+            let _ = signed_handshakes.send((node_address, id, signed));
+        });
+    }
+
+    /// Continues a handshake once it is signed. Handshakes that are no longer pending, because
+    /// the local key was rotated in the meantime, are dropped.
+    async fn handle_signed_handshake(
+        &mut self,
+        node_address: NodeAddress,
+        id: u64,
+        signed: Option<SignedHandshake>,
+    ) {
+        let handshake = match self.pending_handshakes.remove(&node_address) {
+            Some((pending_id, handshake)) if pending_id == id => handshake,
+            Some(other) => {
+                self.pending_handshakes.insert(node_address, other);
+                return;
+            }
+            None => return,
+        };
+        let failed = || Error::Custom("The signer failed or timed out");
+        match handshake {
+            PendingHandshake::Outgoing(request_call) => {
+                let result = match signed {
+                    Some(SignedHandshake::Outgoing(result)) => result,
+                    _ => Err(failed()),
+                };
+                self.send_handshake(*request_call, result).await;
+            }
+            PendingHandshake::Incoming(messages) => {
+                let result = match signed {
+                    Some(SignedHandshake::Incoming(result)) => result,
+                    _ => Err(failed()),
+                };
+                self.establish_from_handshake(node_address, messages, result)
+                    .await;
             }
-        } else {
-            warn!(
-                node_id = %node_address.node_id, addr = %node_address.socket_addr,
-                "Received an authenticated header without a matching WHOAREYOU request",
-            );
         }
     }
 
@@ -1039,6 +1317,23 @@ impl Handler {
         message: &[u8],
         authenticated_data: &[u8],
     ) {
+        // Messages sent on a session we are establishing are handled once it is established.
+        if let Some((_, PendingHandshake::Incoming(messages))) =
+            self.pending_handshakes.get_mut(&node_address)
+        {
+            if messages.len() < MAX_QUEUED_HANDSHAKE_MESSAGES {
+                trace!(%node_address, "Queued message until the session is established");
+                messages.push(QueuedMessage {
+                    message_nonce,
+                    message: message.to_vec(),
+                    authenticated_data: authenticated_data.to_vec(),
+                });
+            } else {
+                debug!(%node_address, "Dropped message, too many queued on the handshake");
+            }
+            return;
+        }
+
         // check if we have an available session
         if let Some(session) = self.sessions.get_mut(&node_address) {
             // attempt to decrypt and process the message.
@@ -1086,6 +1381,9 @@ impl Handler {
             // Remove any associated request from pending_request
             match message {
                 Message::Request(request) => {
+                    if !self.check_request_rate_limit(&node_address, &request).await {
+                        return;
+                    }
                     // report the request to the application
                     if let Err(e) = self
                         .service_send
@@ -1173,6 +1471,11 @@ impl Handler {
             .active_requests
             .remove_request(&node_address, &response.id)
         {
+            // Measure the round trip on the first response to the request
+            if request_call.remaining_responses_mut().is_none() {
+                self.active_requests
+                    .record_rtt(node_address.node_id, &request_call);
+            }
             // The response matches a request
             // Check to see if this is a Nodes response, in which case we may require to wait for
             // extra responses
@@ -1372,16 +1675,51 @@ impl Handler {
         }
     }
 
+    /// Drops all sessions, handshakes and requests after the local key was rotated.
+    /// Replaces the local key, ENR and node id in one step and drops all sessions, which are bound
+    /// to the previous node id.
+    async fn rotate_key(&mut self, new_key: Arc<dyn Signer>) {
+        let old_node_id = self.node_id;
+        let new_node_id = match rotate_local_key(&self.enr, &self.key, new_key) {
+            Ok(enr) => enr.node_id(),
+            Err(e) => {
+                self.service_send
+                    .send(HandlerOut::KeyRotated(Err(e)))
+                    .await
+                    .unwrap_or_else(|e| warn!(error = %e, "Error with sending channel"));
+                return;
+            }
+        };
+        self.node_id = new_node_id;
+        self.socket.set_local_node_id(self.node_id);
+        // Handshakes being signed with the previous key are dropped.
+        for (_, handshake) in std::mem::take(&mut self.pending_handshakes).into_values() {
+            if let PendingHandshake::Outgoing(request_call) = handshake {
+                self.fail_request(*request_call, RequestError::KeyRotated, true)
+                    .await;
+            }
+        }
+        let node_addresses: HashSet<NodeAddress> = self
+            .sessions
+            .keys()
+            .chain(self.pending_requests.keys())
+            .chain(self.active_requests.node_addresses())
+            .cloned()
+            .collect();
+        for node_address in node_addresses {
+            self.fail_session(&node_address, RequestError::KeyRotated, true)
+                .await;
+        }
+        self.active_challenges.clear();
+        self.service_send
+            .send(HandlerOut::KeyRotated(Ok((old_node_id, new_node_id))))
+            .await
+            .unwrap_or_else(|e| warn!(error = %e, "Error with sending channel"));
+    }
+
     /// Check if any banned nodes have served their time and unban them.
     fn unban_nodes_check(&self) {
-        PERMIT_BAN_LIST
-            .write()
-            .ban_ips
-            .retain(|_, time| time.is_none() || Some(Instant::now()) < *time);
-        PERMIT_BAN_LIST
-            .write()
-            .ban_nodes
-            .retain(|_, time| time.is_none() || Some(Instant::now()) < *time);
+        PERMIT_BAN_LIST.write().remove_expired();
     }
 
     /// Removes expired sessions and report them back to the service.
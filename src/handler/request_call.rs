@@ -5,6 +5,7 @@ use crate::{
 };
 
 use super::HandlerReqId;
+use std::time::Instant;
 
 /// A request to a node that we are waiting for a response.
 #[derive(Debug)]
@@ -26,6 +27,8 @@ pub(super) struct RequestCall {
     /// Signifies if we are initiating the session with a random packet. This is only used to
     /// determine the connection direction of the session.
     initiating_session: bool,
+    /// The time the current packet of this call was sent.
+    sent_at: Instant,
 }
 
 impl RequestCall {
@@ -45,6 +48,7 @@ impl RequestCall {
             retries: 1,
             remaining_responses: None,
             initiating_session,
+            sent_at: Instant::now(),
         }
     }
 
@@ -88,6 +92,12 @@ impl RequestCall {
     /// Increments the number of retries for this call
     pub fn increment_retries(&mut self) {
         self.retries += 1;
+        self.sent_at = Instant::now();
+    }
+
+    /// The time the current packet of this call was sent.
+    pub fn sent_at(&self) -> Instant {
+        self.sent_at
     }
 
     /// Returns whether the handshake has been sent for this call or not.
@@ -113,6 +123,7 @@ impl RequestCall {
     /// Updates the underlying packet for the call.
     pub fn update_packet(&mut self, packet: Packet) {
         self.packet = packet;
+        self.sent_at = Instant::now();
     }
 
     /// Gets a mutable reference to the remaining repsonses.
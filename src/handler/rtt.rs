@@ -0,0 +1,143 @@
+//! Round-trip time estimation per peer, used to derive adaptive request timeouts.
+//!
+//! Samples are taken from requests that are answered without a retransmission or a handshake,
+//! following Karn's algorithm. The smoothed round-trip time and its variation are calculated as
+//! described in RFC 6298.
+use enr::NodeId;
+use hashlink::LruCache;
+use std::time::Duration;
+
+/// The timeout of a request to a peer with measured round-trip times is never lower than this.
+pub(crate) const MIN_ADAPTIVE_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// The timeout of a request is never larger than this factor times the configured
+/// `request_timeout`.
+pub(crate) const MAX_ADAPTIVE_TIMEOUT_FACTOR: u32 = 4;
+
+/// Round-trip time statistics of a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerRtt {
+    /// The smoothed round-trip time.
+    pub srtt: Duration,
+    /// The variation of the round-trip time.
+    pub rttvar: Duration,
+    /// The number of round trips measured.
+    pub samples: u64,
+}
+
+impl PeerRtt {
+    fn new(sample: Duration) -> Self {
+        PeerRtt {
+            srtt: sample,
+            rttvar: sample / 2,
+            samples: 1,
+        }
+    }
+
+    fn update(&mut self, sample: Duration) {
+        let deviation = self.srtt.abs_diff(sample);
+        self.rttvar = (self.rttvar * 3 + deviation) / 4;
+        self.srtt = (self.srtt * 7 + sample) / 8;
+        self.samples += 1;
+    }
+
+    /// The retransmission timeout derived from the measured round-trip times, before any bounds
+    /// are applied.
+    pub fn retransmission_timeout(&self) -> Duration {
+        self.srtt + self.rttvar * 4
+    }
+}
+
+/// The round-trip time statistics of recently contacted peers.
+pub struct RttTable {
+    peers: LruCache<NodeId, PeerRtt>,
+}
+
+impl RttTable {
+    pub fn new(capacity: usize) -> Self {
+        RttTable {
+            peers: LruCache::new(capacity),
+        }
+    }
+
+    /// Records a round-trip time sample for a peer.
+    pub fn record(&mut self, node_id: NodeId, sample: Duration) {
+        match self.peers.get_mut(&node_id) {
+            Some(rtt) => rtt.update(sample),
+            None => {
+                self.peers.insert(node_id, PeerRtt::new(sample));
+            }
+        }
+    }
+
+    /// The round-trip time statistics of a peer, if any were measured.
+    pub fn get(&self, node_id: &NodeId) -> Option<PeerRtt> {
+        self.peers.peek(node_id).copied()
+    }
+
+    /// The time a query waits for a response of a peer before moving on to other peers. This is
+    /// twice the retransmission timeout of the peer, leaving room for one retransmission, bounded
+    /// like request timeouts. Peers without measured round-trip times return `None`.
+    pub(crate) fn query_peer_timeout(
+        &self,
+        node_id: &NodeId,
+        query_peer_timeout: Duration,
+    ) -> Option<Duration> {
+        self.get(node_id).map(|rtt| {
+            (rtt.retransmission_timeout() * 2).clamp(
+                MIN_ADAPTIVE_TIMEOUT,
+                query_peer_timeout * MAX_ADAPTIVE_TIMEOUT_FACTOR,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smoothed_rtt() {
+        let mut table = RttTable::new(10);
+        let node_id = NodeId::random();
+        assert!(table.get(&node_id).is_none());
+
+        table.record(node_id, Duration::from_millis(100));
+        let rtt = table.get(&node_id).unwrap();
+        assert_eq!(rtt.srtt, Duration::from_millis(100));
+        assert_eq!(rtt.rttvar, Duration::from_millis(50));
+        assert_eq!(rtt.retransmission_timeout(), Duration::from_millis(300));
+
+        table.record(node_id, Duration::from_millis(180));
+        let rtt = table.get(&node_id).unwrap();
+        assert_eq!(rtt.srtt, Duration::from_millis(110));
+        assert_eq!(
+            rtt.rttvar,
+            Duration::from_millis(57) + Duration::from_micros(500)
+        );
+        assert_eq!(rtt.samples, 2);
+    }
+
+    #[test]
+    fn test_query_peer_timeout() {
+        let mut table = RttTable::new(10);
+        let fast = NodeId::random();
+        let slow = NodeId::random();
+        let query_peer_timeout = Duration::from_secs(2);
+        assert!(table
+            .query_peer_timeout(&fast, query_peer_timeout)
+            .is_none());
+
+        table.record(fast, Duration::from_millis(100));
+        assert_eq!(
+            table.query_peer_timeout(&fast, query_peer_timeout),
+            Some(Duration::from_millis(600))
+        );
+
+        table.record(slow, Duration::from_secs(5));
+        assert_eq!(
+            table.query_peer_timeout(&slow, query_peer_timeout),
+            Some(query_peer_timeout * MAX_ADAPTIVE_TIMEOUT_FACTOR)
+        );
+    }
+}
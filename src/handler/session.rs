@@ -1,10 +1,11 @@
 use super::*;
 use crate::{
+    handler::crypto::IdentitySchemes,
     node_info::NodeContact,
     packet::{ChallengeData, Packet, PacketHeader, PacketKind, MESSAGE_NONCE_LENGTH},
     ProtocolIdentity,
 };
-use enr::{CombinedKey, NodeId};
+use enr::NodeId;
 use zeroize::Zeroize;
 
 #[derive(Zeroize, PartialEq)]
@@ -133,8 +134,10 @@ impl Session {
     /// Generates session keys from an authentication header. If the IP of the ENR does not match the
     /// source IP address, we consider this session untrusted. The output returns a boolean which
     /// specifies if the Session is trusted or not.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn establish_from_challenge(
-        local_key: Arc<RwLock<CombinedKey>>,
+        identity_schemes: &IdentitySchemes,
+        local_key: &dyn Signer,
         local_id: &NodeId,
         remote_id: &NodeId,
         challenge: Challenge,
@@ -169,6 +172,7 @@ impl Session {
 
         // verify the auth header nonce
         if !crypto::verify_authentication_nonce(
+            identity_schemes,
             &remote_public_key,
             ephem_pubkey,
             &challenge.data,
@@ -183,7 +187,8 @@ impl Session {
 
         // generate session keys
         let (decryption_key, encryption_key) = crypto::derive_keys_from_pubkey(
-            &local_key.read(),
+            identity_schemes,
+            local_key,
             local_id,
             remote_id,
             &challenge.data,
@@ -214,9 +219,11 @@ impl Session {
     }
 
     /// Encrypts a message and produces an AuthMessage.
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn encrypt_with_header(
+        identity_schemes: &IdentitySchemes,
         remote_contact: &NodeContact,
-        local_key: Arc<RwLock<CombinedKey>>,
+        local_key: &dyn Signer,
         updated_enr: Option<Enr>,
         local_node_id: &NodeId,
         protocol_identity: ProtocolIdentity,
@@ -224,8 +231,12 @@ impl Session {
         message: &[u8],
     ) -> Result<(Packet, Session), Error> {
         // generate the session keys
-        let (encryption_key, decryption_key, ephem_pubkey) =
-            crypto::generate_session_keys(local_node_id, remote_contact, challenge_data)?;
+        let (encryption_key, decryption_key, ephem_pubkey) = crypto::generate_session_keys(
+            identity_schemes,
+            local_node_id,
+            remote_contact,
+            challenge_data,
+        )?;
 
         let keys = Keys {
             encryption_key,
@@ -234,7 +245,8 @@ impl Session {
 
         // construct the nonce signature
         let sig = crypto::sign_nonce(
-            &local_key.read(),
+            identity_schemes,
+            local_key,
             challenge_data,
             &ephem_pubkey,
             &remote_contact.node_id(),
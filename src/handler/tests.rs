@@ -16,6 +16,7 @@ use std::{
 
 use crate::{handler::HandlerOut::RequestFailed, RequestError::SelfRequest};
 use active_requests::ActiveRequests;
+use enr::CombinedKey;
 use std::time::Duration;
 use tokio::time::sleep;
 
@@ -65,18 +66,30 @@ async fn build_handler(
     let (handler_send, service_recv) = mpsc::unbounded_channel();
     let (service_send, handler_recv) = mpsc::channel(50);
     let (exit_sender, exit) = oneshot::channel();
+    let (signed_handshakes_send, signed_handshakes_recv) = mpsc::unbounded_channel();
 
     let handler = Handler {
         request_retries: config.request_retries,
         node_id,
         protocol_identity: Default::default(),
         enr: Arc::new(RwLock::new(enr)),
-        key: Arc::new(RwLock::new(key)),
-        active_requests: ActiveRequests::new(config.request_timeout),
+        key: Arc::new(RwLock::new(Arc::new(key))),
+        identity_schemes: Default::default(),
+        active_requests: ActiveRequests::new(
+            config.request_timeout,
+            Arc::new(RwLock::new(RttTable::new(100))),
+            false,
+        ),
         pending_requests: HashMap::new(),
         filter_expected_responses,
         sessions: LruTimeCache::new(config.session_timeout, Some(config.session_cache_capacity)),
         active_challenges: HashMapDelay::new(config.request_timeout),
+        pending_handshakes: HashMap::new(),
+        next_handshake_id: 0,
+        signing_timeout: config.request_timeout,
+        signed_handshakes_send,
+        signed_handshakes_recv,
+        request_rate_limiter: None,
         service_recv,
         service_send,
         listen_sockets,
@@ -122,10 +135,14 @@ async fn simple_session_message() {
     let sender_config = ConfigBuilder::new(sender_listen_config)
         .enable_packet_filter()
         .build();
-    let (_exit_send, sender_send, _sender_recv) =
-        Handler::spawn(arc_rw!(sender_enr.clone()), arc_rw!(key1), sender_config)
-            .await
-            .unwrap();
+    let (_exit_send, sender_send, _sender_recv) = Handler::spawn(
+        arc_rw!(sender_enr.clone()),
+        arc_rw!(Arc::new(key1)),
+        arc_rw!(RttTable::new(100)),
+        sender_config,
+    )
+    .await
+    .unwrap();
 
     let receiver_listen_config = ListenConfig::Ipv4 {
         ip: receiver_enr.ip4().unwrap(),
@@ -136,7 +153,8 @@ async fn simple_session_message() {
         .build();
     let (_exit_recv, recv_send, mut receiver_recv) = Handler::spawn(
         arc_rw!(receiver_enr.clone()),
-        arc_rw!(key2),
+        arc_rw!(Arc::new(key2)),
+        arc_rw!(RttTable::new(100)),
         receiver_config,
     )
     .await
@@ -348,7 +366,7 @@ fn create_req_call(node: &Enr) -> (RequestCall, NodeAddress) {
 #[tokio::test]
 async fn test_active_requests_insert() {
     const EXPIRY: Duration = Duration::from_secs(5);
-    let mut active_requests = ActiveRequests::new(EXPIRY);
+    let mut active_requests = ActiveRequests::new(EXPIRY, arc_rw!(RttTable::new(100)), false);
 
     let node_1 = create_node();
     let node_2 = create_node();
@@ -365,10 +383,49 @@ async fn test_active_requests_insert() {
     active_requests.check_invariant();
 }
 
+#[tokio::test]
+async fn test_active_requests_adaptive_timeout() {
+    const EXPIRY: Duration = Duration::from_secs(1);
+    let rtts = arc_rw!(RttTable::new(100));
+    let active_requests = ActiveRequests::new(EXPIRY, rtts.clone(), true);
+
+    let fast_node = NodeId::random();
+    let slow_node = NodeId::random();
+    rtts.write().record(fast_node, Duration::from_millis(50));
+    rtts.write().record(slow_node, Duration::from_secs(1));
+
+    // Peers without measurements use the configured timeout
+    assert_eq!(
+        active_requests.request_timeout(&NodeId::random(), 1),
+        EXPIRY
+    );
+    // The retransmission timeout is bounded and backs off on retries
+    assert_eq!(
+        active_requests.request_timeout(&fast_node, 1),
+        Duration::from_millis(200)
+    );
+    assert_eq!(
+        active_requests.request_timeout(&fast_node, 3),
+        Duration::from_millis(600)
+    );
+    assert_eq!(
+        active_requests.request_timeout(&slow_node, 1),
+        Duration::from_secs(3)
+    );
+    assert_eq!(
+        active_requests.request_timeout(&slow_node, 2),
+        Duration::from_secs(4)
+    );
+
+    // Without adaptive timeouts, the configured timeout is used for all peers
+    let active_requests = ActiveRequests::new(EXPIRY, rtts, false);
+    assert_eq!(active_requests.request_timeout(&fast_node, 1), EXPIRY);
+}
+
 #[tokio::test]
 async fn test_active_requests_remove_requests() {
     const EXPIRY: Duration = Duration::from_secs(5);
-    let mut active_requests = ActiveRequests::new(EXPIRY);
+    let mut active_requests = ActiveRequests::new(EXPIRY, arc_rw!(RttTable::new(100)), false);
 
     let node_1 = create_node();
     let node_2 = create_node();
@@ -391,7 +448,7 @@ async fn test_active_requests_remove_requests() {
 #[tokio::test]
 async fn test_active_requests_remove_request() {
     const EXPIRY: Duration = Duration::from_secs(5);
-    let mut active_requests = ActiveRequests::new(EXPIRY);
+    let mut active_requests = ActiveRequests::new(EXPIRY, arc_rw!(RttTable::new(100)), false);
 
     let node_1 = create_node();
     let node_2 = create_node();
@@ -435,7 +492,7 @@ async fn test_active_requests_remove_request() {
 #[tokio::test]
 async fn test_active_requests_remove_by_nonce() {
     const EXPIRY: Duration = Duration::from_secs(5);
-    let mut active_requests = ActiveRequests::new(EXPIRY);
+    let mut active_requests = ActiveRequests::new(EXPIRY, arc_rw!(RttTable::new(100)), false);
 
     let node_1 = create_node();
     let node_2 = create_node();
@@ -467,7 +524,7 @@ async fn test_active_requests_remove_by_nonce() {
 #[tokio::test]
 async fn test_active_requests_update_packet() {
     const EXPIRY: Duration = Duration::from_secs(5);
-    let mut active_requests = ActiveRequests::new(EXPIRY);
+    let mut active_requests = ActiveRequests::new(EXPIRY, arc_rw!(RttTable::new(100)), false);
 
     let node_1 = create_node();
     let node_2 = create_node();
@@ -511,9 +568,14 @@ async fn test_self_request_ipv4() {
         .enable_packet_filter()
         .build();
 
-    let (_exit_send, send, mut recv) = Handler::spawn(arc_rw!(enr.clone()), arc_rw!(key), config)
-        .await
-        .unwrap();
+    let (_exit_send, send, mut recv) = Handler::spawn(
+        arc_rw!(enr.clone()),
+        arc_rw!(Arc::new(key)),
+        arc_rw!(RttTable::new(100)),
+        config,
+    )
+    .await
+    .unwrap();
 
     // self request (IPv4)
     let _ = send.send(HandlerIn::Request(
@@ -550,9 +612,14 @@ async fn test_self_request_ipv6() {
         .enable_packet_filter()
         .build();
 
-    let (_exit_send, send, mut recv) = Handler::spawn(arc_rw!(enr.clone()), arc_rw!(key), config)
-        .await
-        .unwrap();
+    let (_exit_send, send, mut recv) = Handler::spawn(
+        arc_rw!(enr.clone()),
+        arc_rw!(Arc::new(key)),
+        arc_rw!(RttTable::new(100)),
+        config,
+    )
+    .await
+    .unwrap();
 
     // self request (IPv6)
     let _ = send.send(HandlerIn::Request(
@@ -982,3 +1049,145 @@ async fn test_send_pending_request() {
         }
     }
 }
+
+/// A signer that signs id-nonces only once released.
+struct BlockingSigner {
+    key: CombinedKey,
+    signing: mpsc::UnboundedSender<()>,
+    release: std::sync::Mutex<std::sync::mpsc::Receiver<()>>,
+}
+
+impl Signer for BlockingSigner {
+    fn public_key(&self) -> enr::CombinedPublicKey {
+        Signer::public_key(&self.key)
+    }
+
+    fn sign_enr(&self, content: &[u8]) -> Result<Vec<u8>, Error> {
+        self.key.sign_enr(content)
+    }
+
+    fn sign_id_nonce(&self, scheme: &dyn IdentityScheme, input: &[u8]) -> Result<Vec<u8>, Error> {
+        let _ = self.signing.send(());
+        let _ = self.release.lock().unwrap().recv();
+        self.key.sign_id_nonce(scheme, input)
+    }
+
+    fn ecdh(&self, scheme: &dyn IdentityScheme, ephem_pubkey: &[u8]) -> Result<Vec<u8>, Error> {
+        self.key.ecdh(scheme, ephem_pubkey)
+    }
+}
+
+#[tokio::test]
+// Tests that the handler keeps handling requests while a handshake is being signed
+async fn slow_signer_does_not_stall_handler() {
+    init();
+
+    let sender_port = 5010;
+    let receiver_port = 5011;
+    let ip = "127.0.0.1".parse().unwrap();
+
+    let key1 = CombinedKey::generate_secp256k1();
+    let key2 = CombinedKey::generate_secp256k1();
+
+    let sender_enr = Enr::builder()
+        .ip4(ip)
+        .udp4(sender_port)
+        .build(&key1)
+        .unwrap();
+    let receiver_enr = Enr::builder()
+        .ip4(ip)
+        .udp4(receiver_port)
+        .build(&key2)
+        .unwrap();
+
+    let (signing_send, mut signing_recv) = mpsc::unbounded_channel();
+    let (release_send, release_recv) = std::sync::mpsc::channel();
+    let signer = BlockingSigner {
+        key: key1,
+        signing: signing_send,
+        release: std::sync::Mutex::new(release_recv),
+    };
+
+    let sender_listen_config = ListenConfig::Ipv4 {
+        ip: sender_enr.ip4().unwrap(),
+        port: sender_enr.udp4().unwrap(),
+    };
+    let sender_config = ConfigBuilder::new(sender_listen_config).build();
+    let (_exit_send, sender_send, mut sender_recv) = Handler::spawn(
+        arc_rw!(sender_enr.clone()),
+        arc_rw!(Arc::new(signer)),
+        arc_rw!(RttTable::new(100)),
+        sender_config,
+    )
+    .await
+    .unwrap();
+
+    let receiver_listen_config = ListenConfig::Ipv4 {
+        ip: receiver_enr.ip4().unwrap(),
+        port: receiver_enr.udp4().unwrap(),
+    };
+    let receiver_config = ConfigBuilder::new(receiver_listen_config).build();
+    let (_exit_recv, recv_send, mut receiver_recv) = Handler::spawn(
+        arc_rw!(receiver_enr.clone()),
+        arc_rw!(Arc::new(key2)),
+        arc_rw!(RttTable::new(100)),
+        receiver_config,
+    )
+    .await
+    .unwrap();
+
+    let send_message = Box::new(Request {
+        id: RequestId(vec![1]),
+        body: RequestBody::Ping { enr_seq: 1 },
+    });
+    let _ = sender_send.send(HandlerIn::Request(
+        receiver_enr.into(),
+        send_message.clone(),
+    ));
+
+    let known_sender_enr = sender_enr.clone();
+    let receiver = async move {
+        loop {
+            match receiver_recv.recv().await {
+                Some(HandlerOut::WhoAreYou(wru_ref)) => {
+                    let _ = recv_send.send(HandlerIn::WhoAreYou(
+                        wru_ref,
+                        Some(known_sender_enr.clone()),
+                    ));
+                }
+                Some(HandlerOut::Request(_, request)) => {
+                    assert_eq!(request, send_message);
+                    return;
+                }
+                _ => {}
+            }
+        }
+    };
+
+    let sender = async move {
+        // Wait for the handshake to be signed.
+        signing_recv.recv().await.unwrap();
+        // A request to ourselves fails straight away, even though the signer blocks.
+        let _ = sender_send.send(HandlerIn::Request(
+            sender_enr.into(),
+            Box::new(Request {
+                id: RequestId(vec![2]),
+                body: RequestBody::Ping { enr_seq: 1 },
+            }),
+        ));
+        loop {
+            if let Some(RequestFailed(id, SelfRequest)) = sender_recv.recv().await {
+                assert_eq!(id, RequestId(vec![2]));
+                break;
+            }
+        }
+        release_send.send(()).unwrap();
+    };
+
+    tokio::select! {
+        _ = async { tokio::join!(receiver, sender) } => {}
+        _ = sleep(Duration::from_secs(2)) => {
+            panic!("Test timed out");
+        }
+    }
+}
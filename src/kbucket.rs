@@ -514,6 +514,37 @@ where
         })
     }
 
+    /// Changes the local key of the table, re-inserting all entries into the buckets of their
+    /// distance to the new key. Connections are tied to the local key, so all entries are
+    /// re-inserted as disconnected. Pending entries are dropped.
+    ///
+    /// Returns the keys of the entries that could not be re-inserted, for example because their
+    /// new bucket is full.
+    pub fn set_local_key(&mut self, local_key: Key<TNodeId>) -> Vec<Key<TNodeId>> {
+        let nodes: Vec<_> = self
+            .buckets
+            .iter_mut()
+            .flat_map(|bucket| bucket.take_nodes())
+            .collect();
+        self.local_key = local_key;
+        self.applied_pending.clear();
+
+        let mut dropped = Vec::new();
+        for node in nodes {
+            let status = NodeStatus {
+                state: ConnectionState::Disconnected,
+                direction: node.status.direction,
+            };
+            if !matches!(
+                self.insert_or_update(&node.key, node.value, status),
+                InsertResult::Inserted
+            ) {
+                dropped.push(node.key);
+            }
+        }
+        dropped
+    }
+
     /// Returns an iterator over all the buckets in the routing table
     pub fn buckets_iter(&self) -> impl Iterator<Item = &KBucket<TNodeId, TVal>> {
         self.buckets.iter()
@@ -932,6 +963,38 @@ mod tests {
         }
     }
 
+    #[test]
+    fn set_local_key_rebuckets_entries() {
+        let mut table = KBucketsTable::<_, ()>::new(
+            Key::from(NodeId::random()),
+            Duration::from_secs(5),
+            MAX_NODES_PER_BUCKET,
+            None,
+            None,
+        );
+        let mut keys = Vec::new();
+        while keys.len() < 50 {
+            let key = Key::from(NodeId::random());
+            if let Entry::Absent(e) = table.entry(&key) {
+                if let BucketInsertResult::Inserted = e.insert((), connected_state()) {
+                    keys.push(key);
+                }
+            }
+        }
+
+        let new_local_key = Key::from(NodeId::random());
+        let dropped = table.set_local_key(new_local_key.clone());
+        assert_eq!(table.iter().count() + dropped.len(), keys.len());
+
+        for (index, bucket) in table.buckets.iter().enumerate() {
+            for node in bucket.iter() {
+                let distance = new_local_key.distance(&node.key);
+                assert_eq!(BucketIndex::new(&distance).unwrap().get(), index);
+                assert_eq!(node.status, disconnected_state());
+            }
+        }
+    }
+
     #[test]
     fn closest() {
         let local_key = Key::from(NodeId::random());
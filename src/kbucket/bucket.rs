@@ -618,6 +618,13 @@ where
         }
     }
 
+    /// Removes all nodes from the bucket, returning them. The pending node is dropped.
+    pub fn take_nodes(&mut self) -> impl Iterator<Item = Node<TNodeId, TVal>> {
+        self.first_connected_pos = None;
+        self.pending = None;
+        std::mem::take(&mut self.nodes).into_iter()
+    }
+
     /// Gets the number of entries currently in the bucket.
     pub fn num_entries(&self) -> usize {
         self.nodes.len()
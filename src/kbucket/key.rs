@@ -100,6 +100,32 @@ impl<T> Key<T> {
     }
 }
 
+impl Key<NodeId> {
+    /// Generates a random key that lies at exactly the given log-2 distance from this key.
+    ///
+    /// Returns `None` if the distance is not in the range 1-256.
+    pub fn random_at_log2_distance(&self, distance: u64) -> Option<Key<NodeId>> {
+        if distance == 0 || distance > 256 {
+            return None;
+        }
+
+        // The most significant bit of the XOR distance must be set and all bits above it cleared.
+        let leading_zeros = (256 - distance) as usize;
+        let byte_index = leading_zeros / 8;
+        let bit = 1u8 << (7 - (leading_zeros % 8));
+
+        let mut xor_distance: [u8; 32] = rand::random();
+        xor_distance[..byte_index].fill(0);
+        xor_distance[byte_index] = (xor_distance[byte_index] & (bit - 1)) | bit;
+
+        let mut raw = [0u8; 32];
+        for (i, byte) in raw.iter_mut().enumerate() {
+            *byte = self.hash[i] ^ xor_distance[i];
+        }
+        Some(Key::from(NodeId::new(&raw)))
+    }
+}
+
 impl From<NodeId> for Key<NodeId> {
     fn from(node_id: NodeId) -> Self {
         Key {
@@ -156,6 +182,20 @@ mod tests {
         quickcheck(prop as fn(_, _, _) -> _)
     }
 
+    #[test]
+    fn random_at_log2_distance() {
+        fn prop(a: Key<NodeId>, distance: u8) -> bool {
+            let distance = u64::from(distance) + 1;
+            let b = a.random_at_log2_distance(distance).unwrap();
+            a.log2_distance(&b) == Some(distance)
+        }
+        quickcheck(prop as fn(_, _) -> _);
+
+        let a = Key::from(NodeId::random());
+        assert!(a.random_at_log2_distance(0).is_none());
+        assert!(a.random_at_log2_distance(257).is_none());
+    }
+
     #[test]
     fn unidirectionality() {
         fn prop(a: Key<NodeId>, b: Key<NodeId>) -> bool {
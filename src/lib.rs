@@ -81,7 +81,7 @@
 //!    let mut discv5: Discv5 = Discv5::new(enr, enr_key, config).unwrap();
 //!
 //!    // In order to bootstrap the routing table an external ENR should be added
-//!    // This can be done via add_enr. I.e.:
+//!    // This can be done via add_enr or by configuring bootnodes. I.e.:
 //!    // discv5.add_enr(<ENR>)
 //!
 //!    // start the discv5 server
@@ -94,10 +94,15 @@
 //!    });
 //! ```
 
+mod bootnode;
 mod config;
 mod discv5;
+pub mod distance_strategy;
+pub mod enr_store;
+mod enr_update;
 mod error;
 mod executor;
+mod external_address;
 pub mod handler;
 mod ipmode;
 pub mod kbucket;
@@ -105,10 +110,12 @@ mod lru_time_cache;
 pub mod metrics;
 mod node_info;
 pub mod packet;
+pub mod peer_stats;
 pub mod permit_ban;
 mod query_pool;
 pub mod rpc;
 pub mod service;
+pub mod signer;
 pub mod socket;
 
 #[macro_use]
@@ -116,16 +123,27 @@ extern crate lazy_static;
 
 pub type Enr = enr::Enr<enr::CombinedKey>;
 
-pub use crate::discv5::{Discv5, Event};
+pub use crate::discv5::{Discv5, Event, VerifiedEnr};
+pub use bootnode::Bootnode;
 pub use config::{Config, ConfigBuilder};
+pub use distance_strategy::DistanceStrategy;
+pub use enr_store::{EnrStore, FileEnrStore};
+pub use enr_update::{EnrUpdate, Transport};
 pub use error::{Error, QueryError, RequestError, ResponseError};
 pub use executor::{Executor, TokioExecutor};
+pub use external_address::ExternalAddressMode;
+pub use handler::{IdentityScheme, IdentitySchemes, PeerRtt};
 pub use ipmode::IpMode;
 pub use kbucket::{ConnectionDirection, ConnectionState, Key};
 pub use packet::ProtocolIdentity;
-pub use permit_ban::PermitBanList;
-pub use service::TalkRequest;
-pub use socket::{ListenConfig, RateLimiter, RateLimiterBuilder};
+pub use peer_stats::{BanPolicy, PeerStats};
+pub use permit_ban::{BanEntry, BanSource, IpSubnet, PermitBanList, SubnetTrie};
+pub use service::{ProbeResult, ProbeStep, TalkRequest};
+pub use signer::Signer;
+pub use socket::{
+    ListenConfig, RateLimitedAction, RateLimiter, RateLimiterBuilder, RequestRateLimiter,
+    RequestRateLimiterBuilder,
+};
 // Re-export the ENR crate
 pub use enr;
 
@@ -57,7 +57,6 @@ impl<K: Clone + Eq + Hash, V> LruTimeCache<K, V> {
 
     /// Returns a reference to the value with the given `key`, if present and not expired, without
     /// updating the timestamp.
-    #[allow(dead_code)]
     pub fn peek(&self, key: &K) -> Option<&V> {
         if let Some((value, time)) = self.map.get(key) {
             return if *time + self.ttl >= Instant::now() {
@@ -81,6 +80,11 @@ impl<K: Clone + Eq + Hash, V> LruTimeCache<K, V> {
         self.map.remove(key).map(|v| v.0)
     }
 
+    /// Returns the keys of all items in the cache, including expired ones.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.map.keys()
+    }
+
     /// Removes expired items from the cache.
     pub fn remove_expired_values(&mut self) -> Vec<K> {
         let mut expired_elements = Vec::new();
@@ -12,6 +12,10 @@ pub struct InternalMetrics {
     pub moving_window: u64,
     /// The number of unsolicited requests received per moving window.
     pub unsolicited_requests_per_window: AtomicUsize,
+    /// The number of decrypted inbound requests that exceeded their rate limit.
+    pub requests_rate_limited: AtomicUsize,
+    /// The number of NODES responses truncated by the byte budget for unverified addresses.
+    pub amplification_limited_responses: AtomicUsize,
     /// The number of bytes sent.
     pub bytes_sent: AtomicUsize,
     /// The number of bytes received.
@@ -28,6 +32,8 @@ impl Default for InternalMetrics {
             moving_window: 5,
             active_sessions: AtomicUsize::new(0),
             unsolicited_requests_per_window: AtomicUsize::new(0),
+            requests_rate_limited: AtomicUsize::new(0),
+            amplification_limited_responses: AtomicUsize::new(0),
             bytes_sent: AtomicUsize::new(0),
             bytes_recv: AtomicUsize::new(0),
             ipv4_contactable: AtomicBool::new(false),
@@ -57,6 +63,10 @@ pub struct Metrics {
     pub active_sessions: usize,
     /// The number of unsolicited requests received per second (averaged over a moving window).
     pub unsolicited_requests_per_second: f64,
+    /// The number of decrypted inbound requests that exceeded their rate limit.
+    pub requests_rate_limited: usize,
+    /// The number of NODES responses truncated by the byte budget for unverified addresses.
+    pub amplification_limited_responses: usize,
     /// The number of bytes sent.
     pub bytes_sent: usize,
     /// The number of bytes received.
@@ -75,6 +85,12 @@ impl From<&METRICS> for Metrics {
                 .unsolicited_requests_per_window
                 .load(Ordering::Relaxed) as f64
                 / internal_metrics.moving_window as f64,
+            requests_rate_limited: internal_metrics
+                .requests_rate_limited
+                .load(Ordering::Relaxed),
+            amplification_limited_responses: internal_metrics
+                .amplification_limited_responses
+                .load(Ordering::Relaxed),
             bytes_sent: internal_metrics.bytes_sent.load(Ordering::Relaxed),
             bytes_recv: internal_metrics.bytes_recv.load(Ordering::Relaxed),
             ipv4_contactable: internal_metrics.ipv4_contactable.load(Ordering::Relaxed),
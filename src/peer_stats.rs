@@ -0,0 +1,206 @@
+//! Statistics about the behaviour of peers.
+//!
+//! The service records the requests exchanged with each peer and the ways in which a peer
+//! misbehaved. Misbehaviour accumulates a penalty, which decreases with every valid response. If a
+//! [`BanPolicy`] is configured, peers whose penalty reaches the policy's threshold are banned via
+//! the [`crate::PermitBanList`].
+use crate::handler::PeerRtt;
+use enr::NodeId;
+use hashlink::LruCache;
+use std::time::Instant;
+
+/// Statistics about a single peer.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerStats {
+    /// The number of requests we sent to the peer.
+    pub requests_sent: u64,
+    /// The number of requests the peer sent to us.
+    pub requests_received: u64,
+    /// The number of responses we received from the peer.
+    pub responses_received: u64,
+    /// The number of our requests to the peer that timed out.
+    pub timeouts: u64,
+    /// The number of responses that did not match the request or contained unsolicited data.
+    pub invalid_responses: u64,
+    /// The number of invalid ENRs the peer provided.
+    pub malformed_enrs: u64,
+    /// The number of sessions with the peer that failed to be established.
+    pub handshake_failures: u64,
+    /// The last time we received a message from the peer.
+    pub last_seen: Option<Instant>,
+    /// The measured round-trip times to the peer.
+    pub rtt: Option<PeerRtt>,
+    /// The current penalty of the peer. See [`BanPolicy`].
+    pub penalty: u64,
+}
+
+/// Automatically bans peers that misbehave.
+///
+/// Each kind of misbehaviour adds a penalty to a peer, and every valid response from the peer
+/// reduces its penalty by one. Once the penalty of a peer reaches the `threshold`, the peer is
+/// banned for the configured `ban_duration` and its penalty is reset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BanPolicy {
+    /// The penalty at which a peer is banned.
+    pub threshold: u64,
+    /// The penalty of a request timing out.
+    pub timeout_penalty: u64,
+    /// The penalty of an invalid response.
+    pub invalid_response_penalty: u64,
+    /// The penalty of an invalid ENR.
+    pub malformed_enr_penalty: u64,
+    /// The penalty of a failed handshake.
+    pub handshake_failure_penalty: u64,
+}
+
+impl Default for BanPolicy {
+    fn default() -> Self {
+        BanPolicy {
+            threshold: 100,
+            timeout_penalty: 1,
+            invalid_response_penalty: 20,
+            malformed_enr_penalty: 20,
+            handshake_failure_penalty: 10,
+        }
+    }
+}
+
+/// An interaction with a peer that is recorded in its statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PeerEvent {
+    RequestSent,
+    RequestReceived,
+    ResponseReceived,
+    Timeout,
+    InvalidResponse,
+    MalformedEnr,
+    HandshakeFailure,
+}
+
+/// The statistics of the most recently seen peers.
+pub struct PeerStatsStore {
+    peers: LruCache<NodeId, PeerStats>,
+}
+
+impl PeerStatsStore {
+    pub fn new(capacity: usize) -> Self {
+        PeerStatsStore {
+            peers: LruCache::new(capacity),
+        }
+    }
+
+    /// Records an event for a peer. Returns `true` if the peer should be banned according to the
+    /// given policy, in which case the penalty of the peer is reset.
+    pub(crate) fn record(
+        &mut self,
+        node_id: NodeId,
+        event: PeerEvent,
+        policy: Option<&BanPolicy>,
+    ) -> bool {
+        if !self.peers.contains_key(&node_id) {
+            self.peers.insert(node_id, PeerStats::default());
+        }
+        let stats = self
+            .peers
+            .get_mut(&node_id)
+            .expect("The stats were just inserted");
+
+        let penalty = match event {
+            PeerEvent::RequestSent => {
+                stats.requests_sent += 1;
+                return false;
+            }
+            PeerEvent::RequestReceived => {
+                stats.requests_received += 1;
+                stats.last_seen = Some(Instant::now());
+                return false;
+            }
+            PeerEvent::ResponseReceived => {
+                stats.responses_received += 1;
+                stats.last_seen = Some(Instant::now());
+                stats.penalty = stats.penalty.saturating_sub(1);
+                return false;
+            }
+            PeerEvent::Timeout => {
+                stats.timeouts += 1;
+                policy.map(|policy| policy.timeout_penalty)
+            }
+            PeerEvent::InvalidResponse => {
+                stats.invalid_responses += 1;
+                policy.map(|policy| policy.invalid_response_penalty)
+            }
+            PeerEvent::MalformedEnr => {
+                stats.malformed_enrs += 1;
+                policy.map(|policy| policy.malformed_enr_penalty)
+            }
+            PeerEvent::HandshakeFailure => {
+                stats.handshake_failures += 1;
+                policy.map(|policy| policy.handshake_failure_penalty)
+            }
+        };
+
+        let (Some(penalty), Some(policy)) = (penalty, policy) else {
+            return false;
+        };
+        stats.penalty = stats.penalty.saturating_add(penalty);
+        if stats.penalty >= policy.threshold {
+            stats.penalty = 0;
+            return true;
+        }
+        false
+    }
+
+    /// The statistics of a peer, if any were recorded.
+    pub fn get(&self, node_id: &NodeId) -> Option<PeerStats> {
+        self.peers.peek(node_id).cloned()
+    }
+
+    /// Iterates over the statistics of all tracked peers.
+    pub fn iter(&self) -> impl Iterator<Item = (&NodeId, &PeerStats)> {
+        self.peers.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_penalties_trigger_ban() {
+        let mut store = PeerStatsStore::new(10);
+        let node_id = NodeId::random();
+        let policy = BanPolicy {
+            threshold: 45,
+            ..Default::default()
+        };
+
+        store.record(node_id, PeerEvent::RequestSent, Some(&policy));
+        assert!(!store.record(node_id, PeerEvent::InvalidResponse, Some(&policy)));
+        assert!(!store.record(node_id, PeerEvent::MalformedEnr, Some(&policy)));
+        // Valid responses reduce the penalty
+        assert!(!store.record(node_id, PeerEvent::ResponseReceived, Some(&policy)));
+        assert_eq!(store.get(&node_id).unwrap().penalty, 39);
+        assert!(store.record(node_id, PeerEvent::HandshakeFailure, Some(&policy)));
+
+        let stats = store.get(&node_id).unwrap();
+        assert_eq!(stats.requests_sent, 1);
+        assert_eq!(stats.responses_received, 1);
+        assert_eq!(stats.invalid_responses, 1);
+        assert_eq!(stats.malformed_enrs, 1);
+        assert_eq!(stats.handshake_failures, 1);
+        assert!(stats.last_seen.is_some());
+        assert_eq!(stats.penalty, 0);
+    }
+
+    #[test]
+    fn test_no_ban_without_policy() {
+        let mut store = PeerStatsStore::new(10);
+        let node_id = NodeId::random();
+        for _ in 0..100 {
+            assert!(!store.record(node_id, PeerEvent::InvalidResponse, None));
+        }
+        let stats = store.get(&node_id).unwrap();
+        assert_eq!(stats.invalid_responses, 100);
+        assert_eq!(stats.penalty, 0);
+    }
+}
@@ -1,9 +1,37 @@
+//! The list of IPs and nodes that bypass the packet filter or whose packets are dropped.
+//!
+//! Bans carry the [`BanSource`] that issued them and a human-readable reason. The list can be
+//! exported to a file and imported again, for example to persist bans across restarts. As an
+//! [`Instant`] is meaningless outside of the running process, expiries are written as seconds
+//! since the UNIX epoch.
+//!
+//! The file contains one entry per line:
+//!
+//! ```text
+//! ban-ip <ip> <expiry> <source> <reason>
+//! ban-subnet <ip>/<prefix length> <expiry> <source> <reason>
+//! ban-node <hex node id> <expiry> <source> <reason>
+//! permit-ip <ip>
+//! permit-subnet <ip>/<prefix length>
+//! permit-node <hex node id>
+//! ```
+//!
+//! The expiry is `-` for permanent bans. Empty lines and lines starting with `#` are ignored.
+mod subnet_trie;
+
+pub use subnet_trie::SubnetTrie;
+
 use crate::node_info::NodeAddress;
 use enr::NodeId;
 use std::{
     collections::{HashMap, HashSet},
+    convert::TryInto,
+    fmt,
+    io::{Error, ErrorKind},
     net::IpAddr,
-    time::Instant,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 #[derive(Debug, Clone, Default)]
@@ -11,17 +39,398 @@ pub struct PermitBanList {
     /// A set of IPs which pass all filters.
     pub permit_ips: HashSet<IpAddr>,
     /// A set of IPs whose packets get dropped instantly.
-    pub ban_ips: HashMap<IpAddr, Option<Instant>>,
+    pub ban_ips: HashMap<IpAddr, BanEntry>,
+    /// A set of subnets whose IPs pass all filters.
+    pub permit_subnets: SubnetTrie<()>,
+    /// A set of subnets whose packets get dropped instantly.
+    pub ban_subnets: SubnetTrie<BanEntry>,
     /// A set of NodeIds which pass all filters.
     pub permit_nodes: HashSet<NodeId>,
     /// A set of NodeIds whose packets get dropped instantly.
-    pub ban_nodes: HashMap<NodeId, Option<Instant>>,
+    pub ban_nodes: HashMap<NodeId, BanEntry>,
+}
+
+/// The component that issued a ban.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BanSource {
+    /// The ban was requested by the application.
+    Manual,
+    /// The packet filter banned an IP or node for exceeding its limits.
+    Filter,
+    /// The service banned a node for misbehaving.
+    Reputation,
+}
+
+impl fmt::Display for BanSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BanSource::Manual => write!(f, "manual"),
+            BanSource::Filter => write!(f, "filter"),
+            BanSource::Reputation => write!(f, "reputation"),
+        }
+    }
+}
+
+impl FromStr for BanSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "manual" => Ok(BanSource::Manual),
+            "filter" => Ok(BanSource::Filter),
+            "reputation" => Ok(BanSource::Reputation),
+            _ => Err(format!("Unknown ban source: {s}")),
+        }
+    }
+}
+
+/// A ban of an IP, subnet or node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BanEntry {
+    /// The time at which the ban is lifted. `None` for a permanent ban.
+    pub expiry: Option<Instant>,
+    /// The component that issued the ban.
+    pub source: BanSource,
+    /// Why the ban was issued.
+    pub reason: String,
+}
+
+impl BanEntry {
+    pub fn new(source: BanSource, reason: impl Into<String>, expiry: Option<Instant>) -> Self {
+        BanEntry {
+            expiry,
+            source,
+            reason: reason.into(),
+        }
+    }
+
+    /// Whether the ban is still in effect at the given time.
+    pub fn is_active(&self, now: Instant) -> bool {
+        self.expiry.is_none_or(|expiry| now < expiry)
+    }
+}
+
+/// A range of IPv4 or IPv6 addresses sharing a common prefix, such as `10.0.0.0/8`.
+///
+/// The bits of the address beyond the prefix are always zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpSubnet {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpSubnet {
+    /// Creates the subnet of the given prefix length containing `addr`. Returns `None` if the
+    /// prefix length exceeds the number of bits of the address.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Option<Self> {
+        let addr = match addr {
+            IpAddr::V4(ip) => {
+                if prefix_len > 32 {
+                    return None;
+                }
+                let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+                IpAddr::V4((u32::from(ip) & mask).into())
+            }
+            IpAddr::V6(ip) => {
+                if prefix_len > 128 {
+                    return None;
+                }
+                let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+                IpAddr::V6((u128::from(ip) & mask).into())
+            }
+        };
+        Some(IpSubnet { addr, prefix_len })
+    }
+
+    /// The first address of the subnet.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// The number of leading bits shared by all addresses of the subnet.
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Whether the address is part of the subnet.
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        IpSubnet::new(*ip, self.prefix_len).is_some_and(|subnet| subnet == *self)
+    }
+}
+
+impl fmt::Display for IpSubnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl FromStr for IpSubnet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix_len) = s
+            .split_once('/')
+            .ok_or_else(|| format!("Missing prefix length in subnet: {s}"))?;
+        let addr = addr
+            .parse()
+            .map_err(|e| format!("Invalid subnet address {addr}: {e}"))?;
+        let prefix_len = prefix_len
+            .parse()
+            .map_err(|e| format!("Invalid prefix length {prefix_len}: {e}"))?;
+        IpSubnet::new(addr, prefix_len).ok_or_else(|| format!("Prefix length too long: {s}"))
+    }
 }
 
 impl PermitBanList {
-    pub fn ban(&mut self, node_address: NodeAddress, time_to_unban: Option<Instant>) {
+    /// Bans both the IP and the node id of a node.
+    pub fn ban(&mut self, node_address: NodeAddress, entry: BanEntry) {
         self.ban_ips
-            .insert(node_address.socket_addr.ip(), time_to_unban);
-        self.ban_nodes.insert(node_address.node_id, time_to_unban);
+            .insert(node_address.socket_addr.ip(), entry.clone());
+        self.ban_nodes.insert(node_address.node_id, entry);
+    }
+
+    /// Whether the IP is permitted, either directly or as part of a subnet.
+    pub fn is_ip_permitted(&self, ip: &IpAddr) -> bool {
+        self.permit_ips.contains(ip) || self.permit_subnets.contains_ip(ip)
+    }
+
+    /// Whether the IP is banned, either directly or as part of a subnet.
+    pub fn is_ip_banned(&self, ip: &IpAddr) -> bool {
+        self.ban_ips.contains_key(ip) || self.ban_subnets.contains_ip(ip)
+    }
+
+    /// Removes all bans that have expired.
+    pub fn remove_expired(&mut self) {
+        let now = Instant::now();
+        self.ban_ips.retain(|_, entry| entry.is_active(now));
+        self.ban_subnets.retain(|_, entry| entry.is_active(now));
+        self.ban_nodes.retain(|_, entry| entry.is_active(now));
+    }
+
+    /// Writes the list to a file. Expired bans are omitted.
+    pub fn export(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        std::fs::write(path, self.serialize())
+    }
+
+    /// Reads a list previously written with [`PermitBanList::export`]. Bans that expired in the
+    /// meantime are dropped.
+    pub fn import(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::deserialize(&contents)
+    }
+
+    fn serialize(&self) -> String {
+        let now = Instant::now();
+        let wall_clock_now = SystemTime::now();
+        let ban_line = |kind: &str, key: String, entry: &BanEntry| {
+            let expiry = match entry.expiry {
+                Some(expiry) => {
+                    let expiry = wall_clock_now + expiry.saturating_duration_since(now);
+                    expiry
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs()
+                        .to_string()
+                }
+                None => "-".into(),
+            };
+            format!(
+                "{kind} {key} {expiry} {} {}\n",
+                entry.source,
+                entry.reason.replace('\n', " ")
+            )
+        };
+
+        let mut contents = String::new();
+        for ip in &self.permit_ips {
+            contents.push_str(&format!("permit-ip {ip}\n"));
+        }
+        for (subnet, _) in self.permit_subnets.iter() {
+            contents.push_str(&format!("permit-subnet {subnet}\n"));
+        }
+        for node_id in &self.permit_nodes {
+            contents.push_str(&format!("permit-node {}\n", hex::encode(node_id.raw())));
+        }
+        for (ip, entry) in self.ban_ips.iter().filter(|(_, e)| e.is_active(now)) {
+            contents.push_str(&ban_line("ban-ip", ip.to_string(), entry));
+        }
+        for (subnet, entry) in self.ban_subnets.iter().filter(|(_, e)| e.is_active(now)) {
+            contents.push_str(&ban_line("ban-subnet", subnet.to_string(), entry));
+        }
+        for (node_id, entry) in self.ban_nodes.iter().filter(|(_, e)| e.is_active(now)) {
+            contents.push_str(&ban_line("ban-node", hex::encode(node_id.raw()), entry));
+        }
+        contents
+    }
+
+    fn deserialize(contents: &str) -> Result<Self, Error> {
+        let now = Instant::now();
+        let wall_clock_now = SystemTime::now();
+        let mut list = PermitBanList::default();
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid =
+                |e: String| Error::new(ErrorKind::InvalidData, format!("Line {}: {e}", index + 1));
+
+            let mut fields = line.splitn(5, ' ');
+            let kind = fields.next().unwrap_or_default();
+            let key = fields
+                .next()
+                .ok_or_else(|| invalid("Missing entry".into()))?;
+
+            let entry = if kind.starts_with("ban-") {
+                let expiry = match fields.next() {
+                    Some("-") => None,
+                    Some(secs) => {
+                        let secs = secs
+                            .parse()
+                            .map_err(|e| invalid(format!("Invalid expiry {secs}: {e}")))?;
+                        let expiry = UNIX_EPOCH + Duration::from_secs(secs);
+                        match expiry.duration_since(wall_clock_now) {
+                            Ok(remaining) => Some(now + remaining),
+                            // The ban has expired.
+                            Err(_) => continue,
+                        }
+                    }
+                    None => return Err(invalid("Missing expiry".into())),
+                };
+                let source = fields
+                    .next()
+                    .ok_or_else(|| invalid("Missing ban source".into()))?
+                    .parse()
+                    .map_err(invalid)?;
+                let reason = fields.next().unwrap_or_default();
+                Some(BanEntry::new(source, reason, expiry))
+            } else {
+                None
+            };
+
+            match (kind, entry) {
+                ("permit-ip", _) => {
+                    list.permit_ips.insert(parse_ip(key).map_err(invalid)?);
+                }
+                ("permit-subnet", _) => {
+                    list.permit_subnets
+                        .insert(key.parse().map_err(invalid)?, ());
+                }
+                ("permit-node", _) => {
+                    list.permit_nodes
+                        .insert(parse_node_id(key).map_err(invalid)?);
+                }
+                ("ban-ip", Some(entry)) => {
+                    list.ban_ips.insert(parse_ip(key).map_err(invalid)?, entry);
+                }
+                ("ban-subnet", Some(entry)) => {
+                    list.ban_subnets
+                        .insert(key.parse().map_err(invalid)?, entry);
+                }
+                ("ban-node", Some(entry)) => {
+                    list.ban_nodes
+                        .insert(parse_node_id(key).map_err(invalid)?, entry);
+                }
+                _ => return Err(invalid(format!("Unknown entry kind: {kind}"))),
+            }
+        }
+        Ok(list)
+    }
+}
+
+fn parse_ip(s: &str) -> Result<IpAddr, String> {
+    s.parse().map_err(|e| format!("Invalid IP {s}: {e}"))
+}
+
+fn parse_node_id(s: &str) -> Result<NodeId, String> {
+    let bytes: [u8; 32] = hex::decode(s)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("Invalid node id: {s}"))?;
+    Ok(NodeId::new(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subnet_contains() {
+        let subnet: IpSubnet = "10.1.2.3/16".parse().unwrap();
+        assert_eq!(subnet.to_string(), "10.1.0.0/16");
+        assert!(subnet.contains(&"10.1.200.1".parse().unwrap()));
+        assert!(!subnet.contains(&"10.2.0.1".parse().unwrap()));
+        assert!(!subnet.contains(&"::ffff:10.1.0.1".parse().unwrap()));
+
+        let subnet: IpSubnet = "2001:db8::/32".parse().unwrap();
+        assert!(subnet.contains(&"2001:db8:1::1".parse().unwrap()));
+        assert!(!subnet.contains(&"2001:db9::1".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<IpSubnet>().is_err());
+        assert!("0.0.0.0/0"
+            .parse::<IpSubnet>()
+            .unwrap()
+            .contains(&"1.2.3.4".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_export_import() {
+        let now = Instant::now();
+        let mut list = PermitBanList::default();
+        let node_id = NodeId::random();
+        let expired_node_id = NodeId::random();
+        let ip: IpAddr = "192.168.0.1".parse().unwrap();
+        let subnet: IpSubnet = "10.0.0.0/8".parse().unwrap();
+        let permit_subnet: IpSubnet = "fd00::/8".parse().unwrap();
+
+        list.permit_ips.insert("127.0.0.1".parse().unwrap());
+        list.permit_subnets.insert(permit_subnet, ());
+        list.ban_ips.insert(
+            ip,
+            BanEntry::new(
+                BanSource::Filter,
+                "excessive requests",
+                Some(now + Duration::from_secs(3600)),
+            ),
+        );
+        list.ban_subnets.insert(
+            subnet,
+            BanEntry::new(BanSource::Manual, "hostile subnet", None),
+        );
+        list.ban_nodes.insert(
+            node_id,
+            BanEntry::new(BanSource::Reputation, "invalid responses", None),
+        );
+        list.ban_nodes.insert(
+            expired_node_id,
+            BanEntry::new(BanSource::Manual, "expired", Some(now)),
+        );
+
+        let imported = PermitBanList::deserialize(&list.serialize()).unwrap();
+        assert_eq!(imported.permit_ips, list.permit_ips);
+        assert_eq!(imported.permit_subnets, list.permit_subnets);
+        assert_eq!(imported.ban_subnets, list.ban_subnets);
+        assert!(imported.is_ip_banned(&"10.20.30.40".parse().unwrap()));
+        assert!(imported.is_ip_permitted(&"fd12::1".parse().unwrap()));
+
+        let entry = &imported.ban_ips[&ip];
+        assert_eq!(entry.source, BanSource::Filter);
+        assert_eq!(entry.reason, "excessive requests");
+        // Expiries are stored with a precision of a second.
+        let expiry = entry.expiry.unwrap();
+        assert!(expiry > now + Duration::from_secs(3598));
+        assert!(expiry <= Instant::now() + Duration::from_secs(3600));
+
+        assert_eq!(imported.ban_nodes.len(), 1);
+        assert_eq!(imported.ban_nodes[&node_id], list.ban_nodes[&node_id]);
+    }
+
+    #[test]
+    fn test_import_invalid_entry() {
+        assert!(PermitBanList::deserialize("# comment\n\npermit-ip 127.0.0.1\n").is_ok());
+        assert!(PermitBanList::deserialize("ban-ip 127.0.0.1 - unknown reason\n").is_err());
+        assert!(PermitBanList::deserialize("ban-node 1234 - manual reason\n").is_err());
+        assert!(PermitBanList::deserialize("allow-ip 127.0.0.1\n").is_err());
     }
 }
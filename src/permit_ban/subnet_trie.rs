@@ -0,0 +1,323 @@
+//! A binary prefix trie mapping IP subnets to values.
+//!
+//! Looking up the subnets containing an IP walks at most one node per bit of the address,
+//! independent of the number of subnets stored.
+use super::IpSubnet;
+use std::net::IpAddr;
+
+/// A map from IPv4 and IPv6 subnets to values, supporting efficient lookups of the subnets that
+/// contain an IP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubnetTrie<V> {
+    v4: TrieNode<V>,
+    v6: TrieNode<V>,
+    len: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TrieNode<V> {
+    value: Option<V>,
+    children: [Option<Box<TrieNode<V>>>; 2],
+}
+
+impl<V> Default for TrieNode<V> {
+    fn default() -> Self {
+        TrieNode {
+            value: None,
+            children: [None, None],
+        }
+    }
+}
+
+impl<V> TrieNode<V> {
+    fn is_empty(&self) -> bool {
+        self.value.is_none() && self.children.iter().all(Option::is_none)
+    }
+}
+
+impl<V> Default for SubnetTrie<V> {
+    fn default() -> Self {
+        SubnetTrie {
+            v4: TrieNode::default(),
+            v6: TrieNode::default(),
+            len: 0,
+        }
+    }
+}
+
+/// The bits of an address, left-aligned, and the number of bits of the address.
+fn address_bits(ip: &IpAddr) -> (u128, u8) {
+    match ip {
+        IpAddr::V4(ip) => ((u32::from(*ip) as u128) << 96, 32),
+        IpAddr::V6(ip) => (u128::from(*ip), 128),
+    }
+}
+
+/// The bit of the key at the given depth of the trie.
+fn bit(key: u128, depth: u8) -> usize {
+    ((key >> (127 - depth)) & 1) as usize
+}
+
+impl<V> SubnetTrie<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of subnets in the trie.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the trie contains no subnets.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn root(&self, ip: &IpAddr) -> &TrieNode<V> {
+        match ip {
+            IpAddr::V4(_) => &self.v4,
+            IpAddr::V6(_) => &self.v6,
+        }
+    }
+
+    fn root_mut(&mut self, ip: &IpAddr) -> &mut TrieNode<V> {
+        match ip {
+            IpAddr::V4(_) => &mut self.v4,
+            IpAddr::V6(_) => &mut self.v6,
+        }
+    }
+
+    /// Inserts a subnet, returning the previous value of the subnet.
+    pub fn insert(&mut self, subnet: IpSubnet, value: V) -> Option<V> {
+        let (key, _) = address_bits(&subnet.addr());
+        let mut node = self.root_mut(&subnet.addr());
+        for depth in 0..subnet.prefix_len() {
+            node = node.children[bit(key, depth)].get_or_insert_with(Default::default);
+        }
+        let previous = node.value.replace(value);
+        if previous.is_none() {
+            self.len += 1;
+        }
+        previous
+    }
+
+    /// Returns the value of exactly this subnet.
+    pub fn get(&self, subnet: &IpSubnet) -> Option<&V> {
+        let (key, _) = address_bits(&subnet.addr());
+        let mut node = self.root(&subnet.addr());
+        for depth in 0..subnet.prefix_len() {
+            node = node.children[bit(key, depth)].as_deref()?;
+        }
+        node.value.as_ref()
+    }
+
+    /// Removes exactly this subnet, returning its value.
+    pub fn remove(&mut self, subnet: &IpSubnet) -> Option<V> {
+        fn remove_at<V>(node: &mut TrieNode<V>, key: u128, depth: u8, len: u8) -> Option<V> {
+            if depth == len {
+                return node.value.take();
+            }
+            let child_slot = &mut node.children[bit(key, depth)];
+            let child = child_slot.as_deref_mut()?;
+            let value = remove_at(child, key, depth + 1, len);
+            if child.is_empty() {
+                *child_slot = None;
+            }
+            value
+        }
+
+        let (key, _) = address_bits(&subnet.addr());
+        let value = remove_at(self.root_mut(&subnet.addr()), key, 0, subnet.prefix_len());
+        if value.is_some() {
+            self.len -= 1;
+        }
+        value
+    }
+
+    /// Returns the most specific subnet containing the IP, along with its value.
+    pub fn longest_match(&self, ip: &IpAddr) -> Option<(IpSubnet, &V)> {
+        let (key, bits) = address_bits(ip);
+        let mut node = self.root(ip);
+        let mut longest = node.value.as_ref().map(|value| (0, value));
+        for depth in 0..bits {
+            match node.children[bit(key, depth)].as_deref() {
+                Some(child) => node = child,
+                None => break,
+            }
+            if let Some(value) = node.value.as_ref() {
+                longest = Some((depth + 1, value));
+            }
+        }
+        longest.map(|(prefix_len, value)| {
+            let subnet = IpSubnet::new(*ip, prefix_len).expect("The prefix is within the address");
+            (subnet, value)
+        })
+    }
+
+    /// Whether any subnet in the trie contains the IP.
+    pub fn contains_ip(&self, ip: &IpAddr) -> bool {
+        self.longest_match(ip).is_some()
+    }
+
+    /// Iterates over all subnets and their values.
+    pub fn iter(&self) -> impl Iterator<Item = (IpSubnet, &V)> {
+        fn collect<'a, V>(
+            node: &'a TrieNode<V>,
+            base: IpAddr,
+            key: u128,
+            depth: u8,
+            entries: &mut Vec<(IpSubnet, &'a V)>,
+        ) {
+            if let Some(value) = node.value.as_ref() {
+                let ip = key_to_ip(base, key);
+                let subnet = IpSubnet::new(ip, depth).expect("The depth is within the address");
+                entries.push((subnet, value));
+            }
+            for (bit, child) in node.children.iter().enumerate() {
+                if let Some(child) = child {
+                    let key = key | ((bit as u128) << (127 - depth));
+                    collect(child, base, key, depth + 1, entries);
+                }
+            }
+        }
+
+        let mut entries = Vec::with_capacity(self.len);
+        collect(&self.v4, IpAddr::from([0u8; 4]), 0, 0, &mut entries);
+        collect(&self.v6, IpAddr::from([0u8; 16]), 0, 0, &mut entries);
+        entries.into_iter()
+    }
+
+    /// Retains only the subnets for which the predicate returns `true`.
+    pub fn retain(&mut self, mut f: impl FnMut(&IpSubnet, &mut V) -> bool) {
+        fn retain_at<V>(
+            node: &mut TrieNode<V>,
+            base: IpAddr,
+            key: u128,
+            depth: u8,
+            removed: &mut usize,
+            f: &mut impl FnMut(&IpSubnet, &mut V) -> bool,
+        ) {
+            if let Some(value) = node.value.as_mut() {
+                let ip = key_to_ip(base, key);
+                let subnet = IpSubnet::new(ip, depth).expect("The depth is within the address");
+                if !f(&subnet, value) {
+                    node.value = None;
+                    *removed += 1;
+                }
+            }
+            for (bit, child_slot) in node.children.iter_mut().enumerate() {
+                if let Some(child) = child_slot.as_deref_mut() {
+                    let key = key | ((bit as u128) << (127 - depth));
+                    retain_at(child, base, key, depth + 1, removed, f);
+                    if child.is_empty() {
+                        *child_slot = None;
+                    }
+                }
+            }
+        }
+
+        let mut removed = 0;
+        retain_at(
+            &mut self.v4,
+            IpAddr::from([0u8; 4]),
+            0,
+            0,
+            &mut removed,
+            &mut f,
+        );
+        retain_at(
+            &mut self.v6,
+            IpAddr::from([0u8; 16]),
+            0,
+            0,
+            &mut removed,
+            &mut f,
+        );
+        self.len -= removed;
+    }
+}
+
+/// Converts left-aligned address bits back into an address of the same family as `base`.
+fn key_to_ip(base: IpAddr, key: u128) -> IpAddr {
+    match base {
+        IpAddr::V4(_) => IpAddr::V4(((key >> 96) as u32).into()),
+        IpAddr::V6(_) => IpAddr::V6(key.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subnet(s: &str) -> IpSubnet {
+        s.parse().unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_longest_match() {
+        let mut trie = SubnetTrie::new();
+        assert!(trie.insert(subnet("10.0.0.0/8"), 1).is_none());
+        assert!(trie.insert(subnet("10.1.0.0/16"), 2).is_none());
+        assert!(trie.insert(subnet("2001:db8::/32"), 3).is_none());
+        assert_eq!(trie.insert(subnet("10.1.0.0/16"), 4), Some(2));
+        assert_eq!(trie.len(), 3);
+
+        assert_eq!(
+            trie.longest_match(&ip("10.1.2.3")),
+            Some((subnet("10.1.0.0/16"), &4))
+        );
+        assert_eq!(
+            trie.longest_match(&ip("10.2.0.1")),
+            Some((subnet("10.0.0.0/8"), &1))
+        );
+        assert_eq!(
+            trie.longest_match(&ip("2001:db8::1")),
+            Some((subnet("2001:db8::/32"), &3))
+        );
+        assert!(!trie.contains_ip(&ip("11.0.0.1")));
+        // IPv4 subnets never match IPv6 addresses.
+        assert!(!trie.contains_ip(&ip("::ffff:10.1.2.3")));
+        assert!(!trie.contains_ip(&ip("a00::")));
+    }
+
+    #[test]
+    fn test_single_addresses_and_default_route() {
+        let mut trie = SubnetTrie::new();
+        trie.insert(subnet("192.168.0.1/32"), ());
+        assert!(trie.contains_ip(&ip("192.168.0.1")));
+        assert!(!trie.contains_ip(&ip("192.168.0.2")));
+
+        trie.insert(subnet("::/0"), ());
+        assert!(trie.contains_ip(&ip("fe80::1")));
+        assert!(!trie.contains_ip(&ip("1.1.1.1")));
+    }
+
+    #[test]
+    fn test_remove_and_retain() {
+        let mut trie = SubnetTrie::new();
+        trie.insert(subnet("10.0.0.0/8"), 1);
+        trie.insert(subnet("10.1.0.0/16"), 2);
+        trie.insert(subnet("fd00::/8"), 3);
+
+        assert!(trie.remove(&subnet("10.2.0.0/16")).is_none());
+        assert_eq!(trie.remove(&subnet("10.0.0.0/8")), Some(1));
+        assert!(!trie.contains_ip(&ip("10.2.0.1")));
+        assert!(trie.contains_ip(&ip("10.1.0.1")));
+
+        trie.retain(|_, value| *value != 2);
+        assert_eq!(trie.len(), 1);
+        assert_eq!(
+            trie.iter().collect::<Vec<_>>(),
+            vec![(subnet("fd00::/8"), &3)]
+        );
+
+        // The structure of the trie only depends on its contents.
+        let mut other = SubnetTrie::new();
+        other.insert(subnet("fd00::/8"), 3);
+        assert_eq!(trie, other);
+    }
+}
@@ -129,7 +129,13 @@ where
     }
 
     /// Polls the pool to advance the queries.
-    pub fn poll(&mut self) -> QueryPoolState<'_, TTarget, TNodeId, TResult> {
+    ///
+    /// `peer_timeout` returns the time to wait for a contacted peer before it is considered
+    /// unresponsive, or `None` to use the configured `query_peer_timeout`.
+    pub fn poll(
+        &mut self,
+        peer_timeout: &dyn Fn(&TNodeId) -> Option<Duration>,
+    ) -> QueryPoolState<'_, TTarget, TNodeId, TResult> {
         let now = Instant::now();
         let mut finished = None;
         let mut waiting = None;
@@ -137,7 +143,7 @@ where
 
         for (&query_id, query) in self.queries.iter_mut() {
             query.started = query.started.or(Some(now));
-            match query.next(now) {
+            match query.next(now, peer_timeout) {
                 QueryState::Finished => {
                     finished = Some(query_id);
                     break;
@@ -254,10 +260,14 @@ where
     }
 
     /// Advances the state of the underlying peer iterator.
-    fn next(&mut self, now: Instant) -> QueryState<TNodeId> {
+    fn next(
+        &mut self,
+        now: Instant,
+        peer_timeout: &dyn Fn(&TNodeId) -> Option<Duration>,
+    ) -> QueryState<TNodeId> {
         match &mut self.peer_iter {
-            QueryPeerIter::FindNode(iter) => iter.next(now),
-            QueryPeerIter::Predicate(iter) => iter.next(now),
+            QueryPeerIter::FindNode(iter) => iter.next(now, peer_timeout),
+            QueryPeerIter::Predicate(iter) => iter.next(now, peer_timeout),
         }
     }
 
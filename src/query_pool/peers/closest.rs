@@ -240,8 +240,14 @@ where
 
     /// Advances the state of the query, potentially getting a new peer to contact.
     ///
+    /// `peer_timeout` overrides the configured peer timeout for individual peers.
+    ///
     /// See [`QueryState`].
-    pub fn next(&mut self, now: Instant) -> QueryState<TNodeId> {
+    pub fn next(
+        &mut self,
+        now: Instant,
+        peer_timeout: &dyn Fn(&TNodeId) -> Option<Duration>,
+    ) -> QueryState<TNodeId> {
         if let QueryProgress::Finished = self.progress {
             return QueryState::Finished;
         }
@@ -261,8 +267,9 @@ where
                 QueryPeerState::NotContacted => {
                     // This peer is waiting to be reiterated.
                     if !at_capacity {
-                        let timeout = now + self.config.peer_timeout;
-                        peer.state = QueryPeerState::Waiting(timeout);
+                        let timeout =
+                            peer_timeout(peer.key.preimage()).unwrap_or(self.config.peer_timeout);
+                        peer.state = QueryPeerState::Waiting(now + timeout);
                         self.num_waiting += 1;
                         let peer = peer.key.preimage().clone();
                         return QueryState::Waiting(Some(peer));
@@ -537,7 +544,7 @@ mod tests {
 
                 // Advance the query for maximum parallelism.
                 for k in expected.iter() {
-                    match query.next(now) {
+                    match query.next(now, &|_| None) {
                         QueryState::Finished => break 'finished,
                         QueryState::Waiting(Some(p)) => assert_eq!(&p, k.preimage()),
                         QueryState::Waiting(None) => panic!("Expected another peer."),
@@ -549,7 +556,7 @@ mod tests {
 
                 // Check the bounded parallelism.
                 if query.at_capacity() {
-                    assert_eq!(query.next(now), QueryState::WaitingAtCapacity)
+                    assert_eq!(query.next(now, &|_| None), QueryState::WaitingAtCapacity)
                 }
 
                 // Report results back to the query with a random number of "closer"
@@ -575,7 +582,7 @@ mod tests {
             }
 
             // The query must be finished.
-            assert_eq!(query.next(now), QueryState::Finished);
+            assert_eq!(query.next(now, &|_| None), QueryState::Finished);
             assert_eq!(query.progress, QueryProgress::Finished);
 
             // Determine if all peers have been contacted by the query. This _must_ be
@@ -619,7 +626,7 @@ mod tests {
             let closer: Vec<NodeId> = random_nodes(1).collect();
 
             // A first peer reports a "closer" peer.
-            let peer1 = if let QueryState::Waiting(Some(p)) = query.next(now) {
+            let peer1 = if let QueryState::Waiting(Some(p)) = query.next(now, &|_| None) {
                 p
             } else {
                 panic!("No peer.");
@@ -629,7 +636,7 @@ mod tests {
             query.on_success(&peer1, closer.clone());
 
             // If there is a second peer, let it also report the same "closer" peer.
-            match query.next(now) {
+            match query.next(now, &|_| None) {
                 QueryState::Waiting(Some(p)) => {
                     let peer2 = p;
                     query.on_success(&peer2, closer.clone())
@@ -665,7 +672,7 @@ mod tests {
                 .clone()
                 .into_preimage();
             // Poll the query for the first peer to be in progress.
-            match query.next(now) {
+            match query.next(now, &|_| None) {
                 QueryState::Waiting(Some(id)) => assert_eq!(id, peer),
                 _ => panic!(),
             }
@@ -674,7 +681,7 @@ mod tests {
             now += query.config.peer_timeout;
 
             // Advancing the query again should mark the first peer as unresponsive.
-            let _ = query.next(now);
+            let _ = query.next(now, &|_| None);
             match &query.closest_peers.values().next().unwrap() {
                 QueryPeer {
                     key,
@@ -704,4 +711,27 @@ mod tests {
 
         QuickCheck::new().tests(10).quickcheck(prop as fn(_) -> _)
     }
+
+    #[test]
+    fn peer_timeout_override() {
+        fn prop(mut query: TestQuery) -> bool {
+            let mut now = Instant::now();
+            let peer_timeout = Duration::from_millis(100);
+            assert!(peer_timeout < query.config.peer_timeout);
+            match query.next(now, &|_| Some(peer_timeout)) {
+                QueryState::Waiting(Some(_)) => {}
+                _ => panic!(),
+            }
+
+            // The first peer is unresponsive after the overridden timeout.
+            now += peer_timeout;
+            let _ = query.next(now, &|_| Some(peer_timeout));
+            matches!(
+                query.closest_peers.values().next().unwrap().state,
+                QueryPeerState::Unresponsive
+            )
+        }
+
+        QuickCheck::new().tests(10).quickcheck(prop as fn(_) -> _)
+    }
 }
@@ -225,8 +225,14 @@ where
 
     /// Advances the state of the query, potentially getting a new peer to contact.
     ///
+    /// `peer_timeout` overrides the configured peer timeout for individual peers.
+    ///
     /// See [`QueryState`].
-    pub fn next(&mut self, now: Instant) -> QueryState<TNodeId> {
+    pub fn next(
+        &mut self,
+        now: Instant,
+        peer_timeout: &dyn Fn(&TNodeId) -> Option<Duration>,
+    ) -> QueryState<TNodeId> {
         if let QueryProgress::Finished = self.progress {
             return QueryState::Finished;
         }
@@ -246,8 +252,9 @@ where
                 QueryPeerState::NotContacted => {
                     // This peer is waiting to be reiterated.
                     if !at_capacity {
-                        let timeout = now + self.config.peer_timeout;
-                        peer.state = QueryPeerState::Waiting(timeout);
+                        let timeout =
+                            peer_timeout(peer.key.preimage()).unwrap_or(self.config.peer_timeout);
+                        peer.state = QueryPeerState::Waiting(now + timeout);
                         self.num_waiting += 1;
                         let return_peer = peer.key.preimage().clone();
                         return QueryState::Waiting(Some(return_peer));
@@ -9,64 +9,84 @@
 //! such should have an address to connect to. Untrusted `PeerId`'s can be obtained from the
 //! `Service::Discovered` event, which is fired as peers get discovered.
 //!
-//! Note that although the ENR crate does support Ed25519 keys, these are currently not
-//! supported as the ECDH procedure isn't specified in the specification. Therefore, only
-//! secp256k1 keys are supported currently.
+//! Both secp256k1 and Ed25519 keys are supported. The key agreement of a handshake is performed
+//! with the identity scheme of the recipient, so nodes of either key type can establish sessions
+//! with each other.
 
 use self::{
+    amplification::AmplificationLimit,
+    enr_propagation::{EnrPropagation, PropagationEvent},
     ip_vote::IpVote,
-    query_info::{QueryInfo, QueryType},
+    outbound_scheduler::{OutboundScheduler, Released, Scheduled},
+    query_info::{QueryCallback, QueryInfo, QueryType},
+    table_refresh::{RefreshEvent, TableRefresh},
 };
 use crate::{
+    enr_store::EnrWriter,
     error::{RequestError, ResponseError},
-    handler::{Handler, HandlerIn, HandlerOut},
+    external_address,
+    handler::{Handler, HandlerIn, HandlerOut, NewKey, RttTable},
     kbucket::{
         self, ConnectionDirection, ConnectionState, FailureReason, InsertResult, KBucketsTable,
         NodeStatus, UpdateResult, MAX_NODES_PER_BUCKET,
     },
+    lru_time_cache::LruTimeCache,
     node_info::{NodeAddress, NodeContact, NonContactable},
     packet::MAX_PACKET_SIZE,
+    peer_stats::{PeerEvent, PeerStatsStore},
+    permit_ban::{BanEntry, BanSource},
     query_pool::{
         FindNodeQueryConfig, PredicateQueryConfig, QueryId, QueryPool, QueryPoolState, TargetKey,
     },
-    rpc, Config, Enr, Event, IpMode,
+    rpc,
+    signer::{swap_local_enr, update_enr},
+    Config, Enr, EnrUpdate, Event, IpMode, Signer,
 };
 use connectivity_state::{
     ConnectivityState, TimerFailure, DURATION_UNTIL_NEXT_CONNECTIVITY_ATTEMPT,
 };
 use delay_map::HashSetDelay;
-use enr::{CombinedKey, NodeId};
+pub use dial_back::DIAL_BACK_PROTOCOL;
+use dial_back::{dial_back, dial_back_contact, MAX_CONCURRENT_DIAL_BACKS};
+use enr::{CombinedKey, Error as EnrError, NodeId};
 use fnv::FnvHashMap;
 use futures::prelude::*;
 use more_asserts::debug_unreachable;
 use parking_lot::RwLock;
+use rand::seq::SliceRandom;
 use rpc::*;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     convert::TryInto,
     net::{IpAddr, SocketAddr},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
     task::Poll,
-    time::Instant,
+    time::{Duration, Instant},
 };
 use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, error, info, trace, warn};
 
+mod amplification;
 mod connectivity_state;
+mod dial_back;
+mod enr_propagation;
 mod ip_vote;
+mod outbound_scheduler;
 mod query_info;
+mod table_refresh;
 mod test;
 
-/// The number of distances (buckets) we simultaneously request from each peer.
-/// NOTE: This must not be larger than 127.
-pub(crate) const DISTANCES_TO_REQUEST_PER_PEER: usize = 3;
-
-/// Currently, a maximum of `DISTANCES_TO_REQUEST_PER_PEER * BUCKET_SIZE` peers
-/// can be returned. Datagrams have a max size of 1280 and ENR's have a max size
-/// of 300 bytes. Bucket sizes should be 16. Therefore, to return all required peers
-/// there should be no more than `5 * DISTANCES_TO_REQUEST_PER_PEER` responses.
-pub(crate) const MAX_NODES_RESPONSES: usize =
-    (MAX_NODES_PER_BUCKET / 4 + 1) * DISTANCES_TO_REQUEST_PER_PEER;
+/// The maximum number of NODES responses accepted for a FINDNODE request of the given number of
+/// distances. A maximum of `BUCKET_SIZE` peers can be returned per distance. Datagrams have a max
+/// size of 1280 and ENR's have a max size of 300 bytes. Bucket sizes should be 16. Therefore, to
+/// return all required peers there should be no more than 5 responses per distance. See
+/// [`crate::distance_strategy`] for the distances that are requested.
+pub(crate) fn max_nodes_responses(distances: usize) -> usize {
+    (MAX_NODES_PER_BUCKET / 4 + 1) * distances.max(1)
+}
 
 /// Request type for Protocols using `TalkReq` message.
 ///
@@ -148,6 +168,9 @@ pub enum ServiceRequest {
     /// - A Predicate Query - Searches for peers closest to a random target that match a specified
     ///   predicate.
     StartQuery(QueryKind, oneshot::Sender<Vec<Enr>>),
+    /// A request to start a FindNode query that returns the found ENRs along with the peers that
+    /// reported each of them.
+    StartReportedQuery(NodeId, oneshot::Sender<Vec<(Enr, Vec<NodeId>)>>),
     /// Send a FINDNODE request for nodes that fall within the given set of distances,
     /// to the designated peer and wait for a response.
     FindNodeDesignated(
@@ -164,6 +187,16 @@ pub enum ServiceRequest {
     ),
     /// The PING discv5 RPC function.
     Ping(Enr, Option<oneshot::Sender<Result<Pong, RequestError>>>),
+    /// Returns the most recent ENR known for a node, from the routing table, active sessions or
+    /// ongoing queries.
+    FindEnr(NodeId, oneshot::Sender<Option<Enr>>),
+    /// Establishes a session with a node and PINGs it, without inserting it into the routing
+    /// table.
+    Probe(Enr, oneshot::Sender<ProbeResult>),
+    /// Replaces the local key, returning the new node id once the handler has switched to it.
+    RotateKey(Arc<dyn Signer>, oneshot::Sender<Result<NodeId, EnrError>>),
+    /// The local ENR has been updated by the application.
+    LocalEnrUpdated,
     /// Sets up an event stream where the discv5 server will return various events such as
     /// discovered nodes as it traverses the DHT.
     RequestEventStream(oneshot::Sender<mpsc::Receiver<Event>>),
@@ -177,7 +210,9 @@ pub struct Service {
     /// The local ENR of the server.
     local_enr: Arc<RwLock<Enr>>,
     /// The key associated with the local ENR.
-    enr_key: Arc<RwLock<CombinedKey>>,
+    enr_key: Arc<RwLock<Arc<dyn Signer>>>,
+    /// Writes updates of the local ENR to the configured ENR store.
+    enr_writer: Option<EnrWriter>,
     /// Storage of the ENR record for each node.
     kbuckets: Arc<RwLock<KBucketsTable<NodeId, Enr>>>,
     /// All the iterative queries we are currently performing.
@@ -209,6 +244,30 @@ pub struct Service {
     /// contactable or not. This decides if we should update our ENR or set it to None, if we are
     /// not contactable.
     connectivity_state: ConnectivityState,
+    /// The ENRs of nodes we have established sessions with, and who initiated the session. This
+    /// mirrors the session cache of the handler and allows resolving ENRs of nodes that are not in
+    /// the routing table.
+    session_enrs: LruTimeCache<NodeId, (Enr, ConnectionDirection)>,
+    /// The number of dial-backs we are performing for other nodes.
+    dial_backs_in_progress: Arc<AtomicUsize>,
+    /// Schedules the automatic lookups that keep the routing table populated.
+    table_refresh: TableRefresh,
+    /// Pings peers after updates of the local ENR.
+    enr_propagation: EnrPropagation,
+    /// The interval at which we check whether the routing table needs to be re-seeded from the
+    /// bootnodes.
+    bootnode_check: tokio::time::Interval,
+    /// Throttles the RPC requests we send to peers.
+    outbound: OutboundScheduler<ActiveRequest>,
+    /// Limits the responses sent to addresses that have not answered our PINGs.
+    amplification: AmplificationLimit,
+    /// Statistics about the behaviour of peers.
+    peer_stats: Arc<RwLock<PeerStatsStore>>,
+    /// The round-trip times measured by the handler, used for adaptive query peer timeouts.
+    rtts: Arc<RwLock<RttTable>>,
+    /// The callbacks of key rotations awaiting completion by the handler, in the order they were
+    /// sent to the handler.
+    key_rotations: VecDeque<oneshot::Sender<Result<NodeId, EnrError>>>,
 }
 
 /// Active RPC request awaiting a response from the handler.
@@ -233,6 +292,31 @@ pub struct Pong {
     pub port: u16,
 }
 
+/// The outcome of probing whether a node is reachable.
+#[derive(Debug)]
+pub struct ProbeResult {
+    /// Whether a session with the node is established.
+    pub session_established: bool,
+    /// Our external socket as observed by the node, if it answered the PING.
+    pub observed_addr: Option<SocketAddr>,
+    /// The time it took to establish the session, if required, and receive the PONG.
+    pub rtt: Option<Duration>,
+    /// The step of the probe that failed and the error it failed with. This is `None` if the
+    /// node answered the PING.
+    pub failure: Option<(ProbeStep, RequestError)>,
+}
+
+/// The steps performed when probing a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeStep {
+    /// Determining the address to contact the node at from its ENR.
+    Contact,
+    /// Establishing a session with the node.
+    Handshake,
+    /// Sending a PING and awaiting the PONG.
+    Ping,
+}
+
 /// The kinds of responses we can send back to the discv5 layer.
 pub enum CallbackResponse {
     /// A response to a requested Nodes.
@@ -241,6 +325,11 @@ pub enum CallbackResponse {
     Talk(oneshot::Sender<Result<Vec<u8>, RequestError>>),
     /// A response from a Pong request
     Pong(oneshot::Sender<Result<Pong, RequestError>>),
+    /// The result of a probe, along with the time the PING was scheduled and whether a session
+    /// with the node is established.
+    Probe(oneshot::Sender<ProbeResult>, Instant, bool),
+    /// A request asking a peer to dial back our advertised address.
+    DialBack,
 }
 
 /// For multiple responses to a FindNodes request, this keeps track of the request count
@@ -269,8 +358,11 @@ impl Service {
     /// mechanism.
     pub async fn spawn(
         local_enr: Arc<RwLock<Enr>>,
-        enr_key: Arc<RwLock<CombinedKey>>,
+        enr_key: Arc<RwLock<Arc<dyn Signer>>>,
+        enr_writer: Option<EnrWriter>,
         kbuckets: Arc<RwLock<KBucketsTable<NodeId, Enr>>>,
+        rtts: Arc<RwLock<RttTable>>,
+        peer_stats: Arc<RwLock<PeerStatsStore>>,
         config: Config,
     ) -> Result<(oneshot::Sender<()>, mpsc::Sender<ServiceRequest>), std::io::Error> {
         // process behaviour-level configuration parameters
@@ -278,6 +370,7 @@ impl Service {
             Some(IpVote::new(
                 config.enr_peer_update_min,
                 config.vote_duration,
+                config.vote_ipv6_subnet_prefix,
             ))
         } else {
             None
@@ -286,14 +379,29 @@ impl Service {
         let ip_mode = IpMode::new_from_listen_config(&config.listen_config);
 
         // build the session service
-        let (handler_exit, handler_send, handler_recv) =
-            Handler::spawn(local_enr.clone(), enr_key.clone(), config.clone()).await?;
+        let (handler_exit, handler_send, handler_recv) = Handler::spawn(
+            local_enr.clone(),
+            enr_key.clone(),
+            rtts.clone(),
+            config.clone(),
+        )
+        .await?;
 
         // create the required channels
         let (discv5_send, discv5_recv) = mpsc::channel(30);
         let (exit_send, exit) = oneshot::channel();
 
-        let connectivity_state = ConnectivityState::new(config.auto_nat_listen_duration);
+        let connectivity_state = ConnectivityState::new(
+            config.auto_nat_listen_duration,
+            config.ipv4_external_address,
+            config.ipv6_external_address,
+        );
+        let table_refresh = TableRefresh::new(config.table_refresh_interval);
+        let enr_propagation = EnrPropagation::new(
+            config.enr_update_propagation_window,
+            config.request_timeout,
+            config.enr_update_propagation_min_interval,
+        );
 
         config
             .executor
@@ -303,6 +411,7 @@ impl Service {
                 let mut service = Service {
                     local_enr,
                     enr_key,
+                    enr_writer,
                     kbuckets,
                     queries: QueryPool::new(config.query_timeout),
                     active_requests: Default::default(),
@@ -318,9 +427,35 @@ impl Service {
                     config: config.clone(),
                     ip_mode,
                     connectivity_state,
+                    session_enrs: LruTimeCache::new(
+                        config.session_timeout,
+                        Some(config.session_cache_capacity),
+                    ),
+                    dial_backs_in_progress: Arc::new(AtomicUsize::new(0)),
+                    table_refresh,
+                    enr_propagation,
+                    outbound: OutboundScheduler::new(
+                        config.max_in_flight_requests_per_peer,
+                        config.outbound_requests_per_second,
+                        config.outbound_queue_timeout,
+                        config.max_queued_outbound_requests,
+                    ),
+                    amplification: AmplificationLimit::new(
+                        config.unverified_response_byte_budget,
+                        config.session_timeout,
+                        config.session_cache_capacity,
+                    ),
+                    peer_stats,
+                    rtts,
+                    key_rotations: VecDeque::new(),
+                    bootnode_check: tokio::time::interval_at(
+                        tokio::time::Instant::now() + config.bootnode_reseed_interval,
+                        config.bootnode_reseed_interval,
+                    ),
                 };
 
                 info!(mode = ?service.ip_mode, "Discv5 Service started");
+                service.contact_bootnodes();
                 service.start().await;
             }));
 
@@ -343,13 +478,16 @@ impl Service {
                         ServiceRequest::StartQuery(query, callback) => {
                             match query {
                                 QueryKind::FindNode { target_node } => {
-                                    self.start_findnode_query(target_node, callback);
+                                    self.start_findnode_query(target_node, QueryCallback::Enrs(callback));
                                 }
                                 QueryKind::Predicate { target_node, target_peer_no, predicate } => {
-                                    self.start_predicate_query(target_node, target_peer_no, predicate, callback);
+                                    self.start_predicate_query(target_node, target_peer_no, predicate, QueryCallback::Enrs(callback));
                                 }
                             }
                         }
+                        ServiceRequest::StartReportedQuery(target_node, callback) => {
+                            self.start_findnode_query(target_node, QueryCallback::Reported(callback));
+                        }
                         ServiceRequest::FindNodeDesignated(node_contact, distance, callback) => {
                             self.request_find_node_designated_peer(node_contact, distance, Some(callback));
                         }
@@ -359,6 +497,27 @@ impl Service {
                         ServiceRequest::Ping(enr, callback) => {
                             self.send_ping(enr, callback);
                         }
+                        ServiceRequest::FindEnr(node_id, callback) => {
+                            let session_enr = self.session_enrs.peek(&node_id).map(|(enr, _)| enr.clone());
+                            let enr = match (self.find_enr(&node_id), session_enr) {
+                                (Some(enr), Some(session_enr)) if session_enr.seq() > enr.seq() => Some(session_enr),
+                                (enr, session_enr) => enr.or(session_enr),
+                            };
+                            if callback.send(enr).is_err() {
+                                error!("Failed to return the requested ENR");
+                            }
+                        }
+                        ServiceRequest::Probe(enr, callback) => {
+                            self.probe(enr, callback);
+                        }
+                        ServiceRequest::LocalEnrUpdated => self.propagate_enr_update(),
+                        ServiceRequest::RotateKey(new_key, callback) => {
+                            if let Err(e) = self.handler_send.send(HandlerIn::RotateKey(NewKey(new_key))) {
+                                warn!(error = %e, "Failed to send the new key to the handler");
+                            } else {
+                                self.key_rotations.push_back(callback);
+                            }
+                        }
                         ServiceRequest::RequestEventStream(callback) => {
                             // the channel size needs to be large to handle many discovered peers
                             // if we are reporting them on the event stream.
@@ -374,6 +533,7 @@ impl Service {
                 Some(event) = self.handler_recv.recv() => {
                     match event {
                         HandlerOut::Established(enr, socket_addr, direction) => {
+                            self.session_enrs.insert(enr.node_id(), (enr.clone(), direction));
                             self.inject_session_established(enr.clone(), &socket_addr, direction);
                             self.send_event(Event::SessionEstablished(enr, socket_addr));
                         }
@@ -420,14 +580,18 @@ impl Service {
                             self.send_event(Event::UnrecognizedFrame(frame));
                         }
                         HandlerOut::ExpiredSessions(expired_sessions) => {
+                            for node_address in expired_sessions.iter() {
+                                self.session_enrs.remove(&node_address.node_id);
+                            }
                             self.send_event(Event::SessionsExpired(expired_sessions));
                         }
+                        HandlerOut::KeyRotated(result) => self.key_rotated(result),
                     }
                 }
                 event = Service::bucket_maintenance_poll(&self.kbuckets) => {
                     self.send_event(event);
                 }
-                query_event = Service::query_event_poll(&mut self.queries) => {
+                query_event = Service::query_event_poll(&mut self.queries, &self.rtts, &self.config) => {
                     match query_event {
                         QueryEvent::Waiting(query_id, node_id, request_body) => {
                             self.send_rpc_query(query_id, node_id, request_body);
@@ -440,18 +604,19 @@ impl Service {
                             // obtain the ENR's for the resulting nodes
                             let mut found_enrs = Vec::new();
                             for node_id in result.closest_peers {
+                                let reporters = result.target.reporters.remove(&node_id).unwrap_or_default();
                                 if let Some(position) = result.target.untrusted_enrs.iter().position(|enr| enr.node_id() == node_id) {
                                     let enr = result.target.untrusted_enrs.swap_remove(position);
-                                    found_enrs.push(enr);
+                                    found_enrs.push((enr, reporters));
                                 } else if let Some(enr) = self.find_enr(&node_id) {
                                     // look up from the routing table
-                                    found_enrs.push(enr);
+                                    found_enrs.push((enr, reporters));
                                 }
                                 else {
                                     warn!("ENR not present in queries results");
                                 }
                             }
-                            if result.target.callback.send(found_enrs).is_err() {
+                            if !result.target.callback.send(found_enrs) {
                                 warn!(query_id = *id, "Callback dropped for query. Results dropped");
                             }
                         }
@@ -478,7 +643,7 @@ impl Service {
                             // We have not received enough incoming connections in the required
                             // time. Remove our ENR advertisement.
                             info!(ip_version="v4", next_attempt_in=%DURATION_UNTIL_NEXT_CONNECTIVITY_ATTEMPT.as_secs(), "UDP Socket removed from ENR");
-                            if let Err(error) = self.local_enr.write().remove_udp_socket(&self.enr_key.read()) {
+                            if let Err(error) = self.apply_enr_update(|enr, key| enr.remove_udp_socket(key)) {
                                 error!(?error, "Failed to update the ENR");
                                 false
                             } else {
@@ -490,7 +655,7 @@ impl Service {
                             // We have not received enough incoming connections in the required
                             // time. Remove our ENR advertisement.
                             info!(ip_version="v6", next_attempt_in=%DURATION_UNTIL_NEXT_CONNECTIVITY_ATTEMPT.as_secs(), "UDP Socket removed from ENR");
-                            if let Err(error) = self.local_enr.write().remove_udp6_socket(&self.enr_key.read()) {
+                            if let Err(error) = self.apply_enr_update(|enr, key| enr.remove_udp6_socket(key)) {
                                 error!(?error, "Failed to update the ENR");
                                 false
                             } else {
@@ -499,21 +664,60 @@ impl Service {
                             }
                         }
                     };
-                    if updated_enr {
+                    if updated_enr && !self.enr_propagation.is_enabled() {
                         // Inform our known peers of our updated ENR
                         self.ping_connected_peers();
                     }
                 }
+                _ = self.bootnode_check.tick(), if !self.config.bootnodes.is_empty() => {
+                    let table_size = self.kbuckets.read().iter_ref().count();
+                    if table_size < self.config.bootnode_reseed_threshold {
+                        info!(table_size, "Routing table below threshold, re-seeding from bootnodes");
+                        self.contact_bootnodes();
+                    }
+                }
+                (_, released) = self.outbound.next_request() => {
+                    match released {
+                        Released::Ready(active_request) => self.dispatch_rpc_request(active_request),
+                        Released::Expired(active_request) => {
+                            debug!(node = %active_request.contact, request = %active_request.request_body, "Queued RPC expired before it was sent");
+                            self.report_request_failure(None, active_request, RequestError::Timeout);
+                        }
+                    }
+                }
+                refresh_event = self.table_refresh.poll() => {
+                    match refresh_event {
+                        RefreshEvent::Ready => self.refresh_table(),
+                        RefreshEvent::Completed { distance, found } => {
+                            debug!(distance, found = found.len(), "Routing table refresh completed");
+                            self.send_event(Event::TableRefreshed { distance, found: found.len() });
+                        }
+                    }
+                }
+                propagation_event = self.enr_propagation.poll() => {
+                    match propagation_event {
+                        PropagationEvent::Ping(node_id) => {
+                            if let Some((enr, _)) = self.session_enrs.get(&node_id).cloned() {
+                                self.send_ping(enr, None);
+                            }
+                        }
+                        PropagationEvent::Completed { seq, pinged, acknowledged } => {
+                            debug!(seq, pinged, acknowledged, "ENR update propagated");
+                            self.send_event(Event::EnrUpdatePropagated { seq, pinged, acknowledged });
+                        }
+                    }
+                }
             }
         }
     }
 
     /// Internal function that starts a query.
-    fn start_findnode_query(&mut self, target_node: NodeId, callback: oneshot::Sender<Vec<Enr>>) {
+    fn start_findnode_query(&mut self, target_node: NodeId, callback: QueryCallback) {
         let mut target = QueryInfo {
             query_type: QueryType::FindNode(target_node),
             untrusted_enrs: Default::default(),
-            distances_to_request: DISTANCES_TO_REQUEST_PER_PEER,
+            reporters: Default::default(),
+            distance_strategy: self.config.distance_strategy.clone(),
             callback,
         };
 
@@ -531,7 +735,7 @@ impl Service {
 
         if known_closest_peers.is_empty() {
             warn!("No known_closest_peers found. Return empty result without sending query.");
-            if target.callback.send(vec![]).is_err() {
+            if !target.callback.send(vec![]) {
                 warn!("Failed to callback");
             }
         } else {
@@ -541,18 +745,46 @@ impl Service {
         }
     }
 
+    /// Starts a lookup towards a random target in the most under-filled bucket of the routing
+    /// table, if the table requires a refresh.
+    fn refresh_table(&mut self) {
+        let bucket_sizes = self
+            .kbuckets
+            .read()
+            .buckets_iter()
+            .map(|bucket| bucket.num_entries())
+            .collect::<Vec<_>>();
+
+        let Some(distance) = self.table_refresh.next_distance(&bucket_sizes) else {
+            trace!("Routing table does not require a refresh");
+            return;
+        };
+
+        let local_key = kbucket::Key::from(self.local_enr.read().node_id());
+        let Some(target) = local_key.random_at_log2_distance(distance) else {
+            debug_unreachable!("Refresh distance is always within 1-256");
+            return;
+        };
+
+        debug!(distance, "Refreshing routing table");
+        let (callback, result) = oneshot::channel();
+        self.start_findnode_query(target.into_preimage(), QueryCallback::Enrs(callback));
+        self.table_refresh.lookup_started(distance, result);
+    }
+
     /// Internal function that starts a query.
     fn start_predicate_query(
         &mut self,
         target_node: NodeId,
         num_nodes: usize,
         predicate: Box<dyn Fn(&Enr) -> bool + Send>,
-        callback: oneshot::Sender<Vec<Enr>>,
+        callback: QueryCallback,
     ) {
         let mut target = QueryInfo {
             query_type: QueryType::FindNode(target_node),
             untrusted_enrs: Default::default(),
-            distances_to_request: DISTANCES_TO_REQUEST_PER_PEER,
+            reporters: Default::default(),
+            distance_strategy: self.config.distance_strategy.clone(),
             callback,
         };
 
@@ -575,7 +807,7 @@ impl Service {
 
         if known_closest_peers.is_empty() {
             warn!("No known_closest_peers found. Return empty result without sending query.");
-            if target.callback.send(vec![]).is_err() {
+            if !target.callback.send(vec![]) {
                 warn!("Failed to callback");
             }
         } else {
@@ -610,9 +842,13 @@ impl Service {
     /// Processes an RPC request from a peer. Requests respond to the received socket address,
     /// rather than the IP of the known ENR.
     fn handle_rpc_request(&mut self, node_address: NodeAddress, req: Request) {
+        self.record_peer_event(&node_address, PeerEvent::RequestReceived);
         let id = req.id;
         match req.body {
             RequestBody::FindNode { distances } => {
+                if distances == [0] {
+                    self.enr_propagation.enr_requested(&node_address.node_id);
+                }
                 self.send_nodes_response(node_address, id, distances);
             }
             RequestBody::Ping { enr_seq } => {
@@ -667,6 +903,11 @@ impl Service {
                     warn!(%src, "The src port number should be non zero");
                 }
             }
+            RequestBody::Talk { protocol, request }
+                if self.config.serve_dial_back && protocol == DIAL_BACK_PROTOCOL =>
+            {
+                self.serve_dial_back(node_address, id, request);
+            }
             RequestBody::Talk { protocol, request } => {
                 let req = TalkRequest {
                     id,
@@ -690,6 +931,8 @@ impl Service {
             warn!(%id, "Received an RPC response which doesn't match a request");
             return;
         };
+        self.outbound
+            .request_completed(&active_request.contact.node_id());
 
         debug!(
             response = %response.body,
@@ -715,27 +958,30 @@ impl Service {
                 %node_address,
                 "Node gave an incorrect response type. Ignoring response"
             );
+            self.record_peer_event(&node_address, PeerEvent::InvalidResponse);
             return;
         }
+        self.record_peer_event(&node_address, PeerEvent::ResponseReceived);
 
         let node_id = node_address.node_id;
 
         match response.body {
             ResponseBody::Nodes { total, mut nodes } => {
-                if total > MAX_NODES_RESPONSES as u64 {
-                    warn!(
-                        total,
-                        "NodesResponse has a total larger than {}, nodes will be truncated",
-                        MAX_NODES_RESPONSES
-                    );
-                }
-
                 // These are sanitized and ordered
                 let distances_requested = match &active_request.request_body {
                     RequestBody::FindNode { distances } => distances,
                     _ => unreachable!(),
                 };
 
+                let max_responses = max_nodes_responses(distances_requested.len());
+                if total > max_responses as u64 {
+                    warn!(
+                        total,
+                        "NodesResponse has a total larger than {}, nodes will be truncated",
+                        max_responses
+                    );
+                }
+
                 if let Some(CallbackResponse::Nodes(callback)) = active_request.callback.take() {
                     if let Err(e) = callback.send(Ok(nodes)) {
                         warn!(error = ?e, "Failed to send response in callback")
@@ -755,8 +1001,16 @@ impl Service {
                             %node_address,
                             "Peer returned more than one ENR for itself. Blacklisting",
                         );
+                        self.record_peer_event(&node_address, PeerEvent::MalformedEnr);
                         let ban_timeout = self.config.ban_duration.map(|v| Instant::now() + v);
-                        PERMIT_BAN_LIST.write().ban(node_address, ban_timeout);
+                        PERMIT_BAN_LIST.write().ban(
+                            node_address,
+                            BanEntry::new(
+                                BanSource::Reputation,
+                                "Returned more than one ENR for itself",
+                                ban_timeout,
+                            ),
+                        );
                         nodes.retain(|enr| peer_key.log2_distance(&enr.node_id().into()).is_none());
                     }
                 } else {
@@ -773,8 +1027,16 @@ impl Service {
                         let node_id = active_request.contact.node_id();
                         let addr = active_request.contact.socket_addr();
                         warn!(%node_id, %addr, "ENRs received of unsolicited distances. Blacklisting");
+                        self.record_peer_event(&node_address, PeerEvent::InvalidResponse);
                         let ban_timeout = self.config.ban_duration.map(|v| Instant::now() + v);
-                        PERMIT_BAN_LIST.write().ban(node_address, ban_timeout);
+                        PERMIT_BAN_LIST.write().ban(
+                            node_address,
+                            BanEntry::new(
+                                BanSource::Reputation,
+                                "Returned ENRs of unsolicited distances",
+                                ban_timeout,
+                            ),
+                        );
                     }
                 }
 
@@ -793,13 +1055,14 @@ impl Service {
                     // rpc messages.
                     if current_response.received_nodes.len() < self.config.max_nodes_response
                         && (current_response.count as u64) < total
-                        && current_response.count < MAX_NODES_RESPONSES
+                        && current_response.count < max_responses
                     {
                         current_response.count += 1;
 
                         current_response.received_nodes.append(&mut nodes);
                         self.active_nodes_responses
                             .insert(id.clone(), current_response);
+                        self.outbound.request_sent(node_id);
                         self.active_requests.insert(id, active_request);
                         return;
                     }
@@ -823,26 +1086,52 @@ impl Service {
                 // ensure any mapping is removed in this rare case
                 self.active_nodes_responses.remove(&id);
 
+                if active_request.query_id.is_some() {
+                    if let RequestBody::FindNode { distances } = &active_request.request_body {
+                        self.config
+                            .distance_strategy
+                            .on_nodes_response(distances, nodes.len());
+                    }
+                }
+
                 self.discovered(&node_id, nodes, active_request.query_id);
             }
             ResponseBody::Pong { enr_seq, ip, port } => {
+                // The peer answered our PING, so its address is reachable
+                self.amplification.verify(node_address.socket_addr);
+
                 // Send the response to the user, if they are who asked
-                if let Some(CallbackResponse::Pong(callback)) = active_request.callback {
-                    let response = Pong {
-                        enr_seq,
-                        ip,
-                        port: port.get(),
-                    };
-                    if let Err(e) = callback.send(Ok(response)) {
-                        warn!(error = ?e, "Failed to send callback response")
-                    };
-                    return;
+                match active_request.callback.take() {
+                    Some(CallbackResponse::Pong(callback)) => {
+                        let response = Pong {
+                            enr_seq,
+                            ip,
+                            port: port.get(),
+                        };
+                        if let Err(e) = callback.send(Ok(response)) {
+                            warn!(error = ?e, "Failed to send callback response")
+                        };
+                        return;
+                    }
+                    Some(CallbackResponse::Probe(callback, sent_at, _)) => {
+                        let result = ProbeResult {
+                            session_established: true,
+                            observed_addr: Some(SocketAddr::new(ip, port.get())),
+                            rtt: Some(sent_at.elapsed()),
+                            failure: None,
+                        };
+                        if let Err(e) = callback.send(result) {
+                            warn!(error = ?e, "Failed to send probe result")
+                        };
+                        return;
+                    }
+                    _ => {}
                 }
 
                 let socket = SocketAddr::new(ip, port.get());
                 // Register the vote, this counts towards potentially updating the ENR for external
                 // advertisement
-                self.handle_ip_vote_from_pong(node_id, socket);
+                self.handle_ip_vote_from_pong(node_id, node_address.socket_addr.ip(), socket);
 
                 // check if we need to request a new ENR
                 if let Some(enr) = self.find_enr(&node_id) {
@@ -872,6 +1161,9 @@ impl Service {
                             warn!(error = ?e, "Failed to send callback response")
                         };
                     }
+                    Some(CallbackResponse::DialBack) => {
+                        debug!(%node_address, reached = response == [1], "Dial-back result");
+                    }
                     _ => error!("Invalid callback for response"),
                 }
             }
@@ -881,7 +1173,7 @@ impl Service {
     // We have received a PONG which informs us for our external socket. This function decides
     // how we should handle this vote and whether or not to update our ENR. This is done on a
     // majority-based voting system, see `IpVote` for more details.
-    fn handle_ip_vote_from_pong(&mut self, node_id: NodeId, socket: SocketAddr) {
+    fn handle_ip_vote_from_pong(&mut self, node_id: NodeId, voter: IpAddr, socket: SocketAddr) {
         // Check that we are in a state to handle any IP votes
         if !self.connectivity_state.should_count_ip_vote(&socket) {
             return;
@@ -892,16 +1184,14 @@ impl Service {
             return;
         }
 
-        // Only count votes that are from peers we have contacted.
-        let key: kbucket::Key<NodeId> = node_id.into();
-        let is_connected_and_outgoing = matches!(
-                        self.kbuckets.write().entry(&key),
-                        kbucket::Entry::Present(_, status)
-                            if status.is_connected() && !status.is_incoming());
-
-        // Check to make sure this is an outgoing peer vote, otherwise if we need the vote due to a
-        // lack of majority, we accept it.
-        if !(is_connected_and_outgoing | self.require_more_ip_votes(socket.is_ipv6())) {
+        // Only count votes from peers we initiated the session with. Peers contacting us choose
+        // their node ids and addresses, and could otherwise flood us with votes.
+        let is_outgoing = matches!(
+            self.session_enrs.peek(&node_id),
+            Some((_, ConnectionDirection::Outgoing))
+        );
+        if !is_outgoing {
+            trace!(%node_id, "Ignoring IP vote of a peer that initiated the session");
             return;
         }
 
@@ -909,7 +1199,7 @@ impl Service {
             SocketAddr::V4(_) => {
                 let local_ip4_socket = self.local_enr.read().udp4_socket();
                 if let Some(ip_votes) = self.ip_votes.as_mut() {
-                    ip_votes.insert(node_id, socket);
+                    ip_votes.insert(voter, socket);
                     let maybe_ip4_majority = ip_votes.majority().0;
 
                     let new_ip4 = maybe_ip4_majority.and_then(|majority| {
@@ -923,16 +1213,14 @@ impl Service {
                     // If we have a new ipv4 majority
                     if let Some(new_ip4) = new_ip4 {
                         let new_ip4: SocketAddr = new_ip4.into();
-                        let result = self
-                            .local_enr
-                            .write()
-                            .set_udp_socket(new_ip4, &self.enr_key.read());
+                        let result = self.set_voted_socket(new_ip4);
                         match result {
                             Ok(_) => {
                                 // Inform the connectivity state that we have updated our IP advertisement
                                 self.connectivity_state.enr_socket_update(&new_ip4);
                                 info!(ip_version="v4", %new_ip4, "Local UDP socket updated");
                                 self.send_event(Event::SocketUpdated(new_ip4));
+                                self.request_dial_back(&new_ip4);
                             }
                             Err(e) => {
                                 warn!(ip = %new_ip4, error = ?e, "Failed to update local UDP socket.");
@@ -944,7 +1232,7 @@ impl Service {
             SocketAddr::V6(_) => {
                 let local_ip6_socket = self.local_enr.read().udp6_socket();
                 if let Some(ip_votes) = self.ip_votes.as_mut() {
-                    ip_votes.insert(node_id, socket);
+                    ip_votes.insert(voter, socket);
                     let maybe_ip6_majority = ip_votes.majority().1;
 
                     let new_ip6 = maybe_ip6_majority.and_then(|majority| {
@@ -957,16 +1245,14 @@ impl Service {
                     // Check if our advertised IPV6 address needs to be updated.
                     if let Some(new_ip6) = new_ip6 {
                         let new_ip6: SocketAddr = new_ip6.into();
-                        let result = self
-                            .local_enr
-                            .write()
-                            .set_udp_socket(new_ip6, &self.enr_key.read());
+                        let result = self.set_voted_socket(new_ip6);
                         match result {
                             Ok(_) => {
                                 // Inform the connectivity state that we have updated our IP advertisement
                                 self.connectivity_state.enr_socket_update(&new_ip6);
                                 info!(ip_version="v6", %new_ip6, "Local UDP socket updated");
                                 self.send_event(Event::SocketUpdated(new_ip6));
+                                self.request_dial_back(&new_ip6);
                             }
                             Err(e) => {
                                 warn!(ip6 = %new_ip6, error = ?e, "Failed to update local UDP ip6 socket.");
@@ -1003,6 +1289,39 @@ impl Service {
         }
     }
 
+    /// Probes a node by establishing a session and sending a PING. The node is not inserted into
+    /// the routing table.
+    fn probe(&mut self, enr: Enr, callback: oneshot::Sender<ProbeResult>) {
+        let contact = match NodeContact::try_from_enr(enr, self.ip_mode) {
+            Ok(contact) => contact,
+            Err(NonContactable { .. }) => {
+                let result = ProbeResult {
+                    session_established: false,
+                    observed_addr: None,
+                    rtt: None,
+                    failure: Some((
+                        ProbeStep::Contact,
+                        RequestError::InvalidEnr("ENR is not contactable"),
+                    )),
+                };
+                callback
+                    .send(result)
+                    .unwrap_or_else(|_| debug!("Couldn't send probe result to user"));
+                return;
+            }
+        };
+
+        let active_request = ActiveRequest {
+            contact,
+            request_body: RequestBody::Ping {
+                enr_seq: self.local_enr.read().seq(),
+            },
+            query_id: None,
+            callback: Some(CallbackResponse::Probe(callback, Instant::now(), false)),
+        };
+        self.send_rpc_request(active_request);
+    }
+
     /// Ping all peers that are connected in the routing table.
     fn ping_connected_peers(&mut self) {
         // maintain the ping interval
@@ -1025,6 +1344,44 @@ impl Service {
         }
     }
 
+    /// Contacts all configured bootnodes. Bootnodes with a known ENR are re-added to the routing
+    /// table if absent and pinged, the others have their ENR requested. In both cases, a
+    /// successful response establishes a session which inserts the bootnode in the routing table.
+    fn contact_bootnodes(&mut self) {
+        for bootnode in self.config.bootnodes.clone() {
+            let contact = match bootnode.contact(self.ip_mode) {
+                Ok(contact) => contact,
+                Err(NonContactable { enr }) => {
+                    warn!(%enr, "Bootnode is not contactable with the configured IpMode");
+                    continue;
+                }
+            };
+
+            let Some(enr) = contact.enr() else {
+                debug!(%contact, "Requesting the ENR of a bootnode");
+                self.request_find_node_designated_peer(contact, vec![0], None);
+                continue;
+            };
+
+            let key = kbucket::Key::from(enr.node_id());
+            let is_absent = matches!(self.kbuckets.write().entry(&key), kbucket::Entry::Absent(_));
+            if is_absent && (self.config.table_filter)(&enr) {
+                let status = NodeStatus {
+                    state: ConnectionState::Disconnected,
+                    direction: ConnectionDirection::Outgoing,
+                };
+                if let InsertResult::Failed(reason) =
+                    self.kbuckets
+                        .write()
+                        .insert_or_update(&key, enr.clone(), status)
+                {
+                    debug!(node_id = %enr.node_id(), ?reason, "Failed to add bootnode to routing table");
+                }
+            }
+            self.send_ping(enr, None);
+        }
+    }
+
     /// Request an external node's ENR.
     fn request_find_node_designated_peer(
         &mut self,
@@ -1061,6 +1418,79 @@ impl Service {
         self.send_rpc_request(active_request);
     }
 
+    /// Asks connected peers to dial back our updated ENR socket, to quickly confirm that it is
+    /// reachable. The dial-backs count as incoming connections in the `ConnectivityState`.
+    fn request_dial_back(&mut self, socket: &SocketAddr) {
+        let Some(peers) = self.config.dial_back_peers else {
+            return;
+        };
+        let request = alloy_rlp::encode(&*self.local_enr.read());
+
+        let mut candidates = self
+            .kbuckets
+            .write()
+            .iter()
+            .filter(|entry| entry.status.is_connected() && !entry.status.is_incoming())
+            .filter_map(|entry| {
+                NodeContact::try_from_enr(entry.node.value.clone(), self.ip_mode).ok()
+            })
+            .filter(|contact| contact.socket_addr().is_ipv4() == socket.is_ipv4())
+            .collect::<Vec<_>>();
+        candidates.shuffle(&mut rand::thread_rng());
+
+        for contact in candidates.into_iter().take(peers) {
+            debug!(%contact, %socket, "Requesting dial-back");
+            let active_request = ActiveRequest {
+                contact,
+                request_body: RequestBody::Talk {
+                    protocol: DIAL_BACK_PROTOCOL.to_vec(),
+                    request: request.clone(),
+                },
+                query_id: None,
+                callback: Some(CallbackResponse::DialBack),
+            };
+            self.send_rpc_request(active_request);
+        }
+    }
+
+    /// Dials back a node that requested it, answering with whether the node was reachable.
+    fn serve_dial_back(&mut self, node_address: NodeAddress, id: RequestId, request: Vec<u8>) {
+        let handler_send = self.handler_send.clone();
+        let respond = move |node_address: NodeAddress, reached: bool| {
+            let response = Response {
+                id,
+                body: ResponseBody::Talk {
+                    response: vec![reached as u8],
+                },
+            };
+            if let Err(e) = handler_send.send(HandlerIn::Response(node_address, Box::new(response)))
+            {
+                warn!(error = %e, "Failed to send dial-back response");
+            }
+        };
+
+        let contact = match dial_back_contact(&node_address, &request, self.ip_mode) {
+            Some(contact)
+                if self.dial_backs_in_progress.load(Ordering::Relaxed)
+                    < MAX_CONCURRENT_DIAL_BACKS =>
+            {
+                contact
+            }
+            _ => return respond(node_address, false),
+        };
+
+        debug!(%contact, "Dialing back");
+        self.dial_backs_in_progress.fetch_add(1, Ordering::Relaxed);
+        let dial_backs_in_progress = self.dial_backs_in_progress.clone();
+        let config = self.config.clone();
+        let executor = self.config.executor.clone().expect("Executor must exist");
+        executor.spawn(Box::pin(async move {
+            let reached = dial_back(contact, config).await;
+            dial_backs_in_progress.fetch_sub(1, Ordering::Relaxed);
+            respond(node_address, reached);
+        }));
+    }
+
     /// Sends a NODES response, given a list of found ENR's. This function splits the nodes up
     /// into multiple responses to ensure the response stays below the maximum packet size.
     fn send_nodes_response(
@@ -1097,8 +1527,64 @@ impl Service {
             }
         }
 
+        // build the NODES response
+        let mut to_send_nodes: Vec<Vec<Enr>> = Vec::new();
+        let mut packet_sizes: Vec<usize> = Vec::new();
+        for enr in nodes_to_send.into_iter() {
+            let entry_size = alloy_rlp::encode(&enr).len();
+            // Responses assume that a session is established. Thus, on top of the encoded
+            // ENR's the packet should be a regular message. A regular message has an IV (16
+            // bytes), and a header of 55 bytes. The find-nodes RPC requires 16 bytes for the ID and the
+            // `total` field. Also there is a 16 byte HMAC for encryption and an extra byte for
+            // RLP encoding.
+            //
+            // We could also be responding via an authheader which can take up to 282 bytes in its
+            // header.
+            // As most messages will be normal messages we will try and pack as many ENR's we
+            // can in and drop the response packet if a user requests an auth message of a very
+            // packed response.
+            //
+            // The estimated total overhead for a regular message is therefore 104 bytes.
+            match (to_send_nodes.last_mut(), packet_sizes.last_mut()) {
+                (Some(nodes), Some(total_size))
+                    if entry_size + *total_size < MAX_PACKET_SIZE - 104 =>
+                {
+                    *total_size += entry_size;
+                    trace!(
+                        %enr,
+                        entry_size,
+                        total_size,
+                        "Adding ENR",
+                    );
+                    nodes.push(enr);
+                }
+                _ => {
+                    packet_sizes.push(entry_size);
+                    to_send_nodes.push(vec![enr]);
+                }
+            }
+        }
+
+        // Limit the responses to addresses that have not proven to be reachable
+        let packet_sizes = packet_sizes
+            .iter()
+            .map(|size| size + 104)
+            .collect::<Vec<_>>();
+        let allowed = self
+            .amplification
+            .allowed_packets(node_address.socket_addr, &packet_sizes);
+        if allowed < to_send_nodes.len() {
+            debug!(
+                to = %node_address,
+                allowed,
+                packets = to_send_nodes.len(),
+                "Truncating FINDNODES response to unverified address",
+            );
+            to_send_nodes.truncate(allowed);
+        }
+
         // if there are no nodes, send an empty response
-        if nodes_to_send.is_empty() {
+        if to_send_nodes.is_empty() {
             let response = Response {
                 id: rpc_id,
                 body: ResponseBody::Nodes {
@@ -1116,66 +1602,25 @@ impl Service {
             {
                 warn!(error = %e, "Failed to send empty FINDNODES response")
             }
-        } else {
-            // build the NODES response
-            let mut to_send_nodes: Vec<Vec<Enr>> = Vec::new();
-            let mut total_size = 0;
-            let mut rpc_index = 0;
-            to_send_nodes.push(Vec::new());
-            for enr in nodes_to_send.into_iter() {
-                let entry_size = alloy_rlp::encode(&enr).len();
-                // Responses assume that a session is established. Thus, on top of the encoded
-                // ENR's the packet should be a regular message. A regular message has an IV (16
-                // bytes), and a header of 55 bytes. The find-nodes RPC requires 16 bytes for the ID and the
-                // `total` field. Also there is a 16 byte HMAC for encryption and an extra byte for
-                // RLP encoding.
-                //
-                // We could also be responding via an authheader which can take up to 282 bytes in its
-                // header.
-                // As most messages will be normal messages we will try and pack as many ENR's we
-                // can in and drop the response packet if a user requests an auth message of a very
-                // packed response.
-                //
-                // The estimated total overhead for a regular message is therefore 104 bytes.
-                if entry_size + total_size < MAX_PACKET_SIZE - 104 {
-                    total_size += entry_size;
-                    trace!(
-                        %enr,
-                        entry_size,
-                        total_size,
-                        "Adding ENR",
-                    );
-                    to_send_nodes[rpc_index].push(enr);
-                } else {
-                    total_size = entry_size;
-                    to_send_nodes.push(vec![enr]);
-                    rpc_index += 1;
-                }
-            }
-
-            let responses: Vec<Response> = to_send_nodes
-                .into_iter()
-                .map(|nodes| Response {
-                    id: rpc_id.clone(),
-                    body: ResponseBody::Nodes {
-                        total: (rpc_index + 1) as u64,
-                        nodes,
-                    },
-                })
-                .collect();
+            return;
+        }
 
-            for response in responses {
-                trace!(
-                    to = %node_address,
-                    %response,
-                    "Sending FINDNODES response",
-                );
-                if let Err(e) = self.handler_send.send(HandlerIn::Response(
-                    node_address.clone(),
-                    Box::new(response),
-                )) {
-                    warn!(error = %e, "Failed to send FINDNODES response")
-                }
+        let total = to_send_nodes.len() as u64;
+        for nodes in to_send_nodes {
+            let response = Response {
+                id: rpc_id.clone(),
+                body: ResponseBody::Nodes { total, nodes },
+            };
+            trace!(
+                to = %node_address,
+                %response,
+                "Sending FINDNODES response",
+            );
+            if let Err(e) = self.handler_send.send(HandlerIn::Response(
+                node_address.clone(),
+                Box::new(response),
+            )) {
+                warn!(error = %e, "Failed to send FINDNODES response")
             }
         }
     }
@@ -1222,6 +1667,23 @@ impl Service {
 
     /// Sends generic RPC requests. Each request gets added to known outputs, awaiting a response.
     fn send_rpc_request(&mut self, active_request: ActiveRequest) {
+        let node_id = active_request.contact.node_id();
+        match self.outbound.schedule(node_id, active_request) {
+            Scheduled::Ready(active_request) => self.dispatch_rpc_request(active_request),
+            Scheduled::Queued => trace!(
+                node = %node_id,
+                queued = self.outbound.queued(),
+                "Outbound request limit reached, queuing RPC"
+            ),
+            Scheduled::Rejected(active_request) => {
+                warn!(node = %node_id, "Outbound queue full, dropping RPC");
+                self.report_request_failure(None, active_request, RequestError::OutboundQueueFull);
+            }
+        }
+    }
+
+    /// Sends an RPC request to the handler, bypassing the outbound scheduler.
+    fn dispatch_rpc_request(&mut self, mut active_request: ActiveRequest) {
         // Generate a random rpc_id which is matched per node id
         let id = RequestId::random();
         let request: Request = Request {
@@ -1236,10 +1698,150 @@ impl Service {
             .send(HandlerIn::Request(contact, Box::new(request)))
             .is_ok()
         {
+            self.outbound.request_sent(active_request.contact.node_id());
+            self.record_peer_event(
+                &active_request.contact.node_address(),
+                PeerEvent::RequestSent,
+            );
+            // A probe sent over an existing session does not perform a handshake.
+            if let Some(CallbackResponse::Probe(_, _, session_established)) =
+                active_request.callback.as_mut()
+            {
+                *session_established = self
+                    .session_enrs
+                    .peek(&active_request.contact.node_id())
+                    .is_some();
+            }
             self.active_requests.insert(id, active_request);
         }
     }
 
+    /// Records an interaction with a peer in its statistics, banning the peer if its penalty
+    /// crosses the threshold of the configured `BanPolicy`.
+    fn record_peer_event(&mut self, node_address: &NodeAddress, event: PeerEvent) {
+        let ban = self.peer_stats.write().record(
+            node_address.node_id,
+            event,
+            self.config.ban_policy.as_ref(),
+        );
+        if ban {
+            warn!(%node_address, ?event, "Peer penalty crossed the ban threshold. Blacklisting");
+            let ban_timeout = self.config.ban_duration.map(|v| Instant::now() + v);
+            PERMIT_BAN_LIST.write().ban(
+                node_address.clone(),
+                BanEntry::new(
+                    BanSource::Reputation,
+                    format!("Penalty threshold crossed after {event:?}"),
+                    ban_timeout,
+                ),
+            );
+        }
+    }
+
+    /// Applies an update to the local ENR, signs it and writes it to the ENR store.
+    fn apply_enr_update<T>(
+        &mut self,
+        mut update: impl FnMut(&mut Enr, &CombinedKey) -> Result<T, EnrError>,
+    ) -> Result<T, EnrError> {
+        let (updated, result) = swap_local_enr(&self.local_enr, &self.enr_key, |enr, signer| {
+            let mut updated = enr.clone();
+            let result = update_enr(&mut updated, signer, &mut update)?;
+            Ok(Some((updated, result)))
+        })?
+        .expect("updates always produce a record");
+        self.store_local_enr(updated);
+        self.propagate_enr_update();
+        Ok(result)
+    }
+
+    /// Sets the external socket agreed on by peers as the UDP socket of the local ENR, and
+    /// advertises the configured `enr_update_transports` at its IP address.
+    fn set_voted_socket(&mut self, socket: SocketAddr) -> Result<(), EnrError> {
+        let transports = &self.config.enr_update_transports;
+        let share_udp_port = self.config.enr_update_share_udp_port;
+        let Some((updated, ())) = swap_local_enr(&self.local_enr, &self.enr_key, |enr, signer| {
+            let mut update = EnrUpdate::default();
+            external_address::add_external_socket(
+                &mut update,
+                enr,
+                socket,
+                transports,
+                share_udp_port,
+            );
+            Ok(update.apply(enr, signer)?.map(|updated| (updated, ())))
+        })?
+        else {
+            return Ok(());
+        };
+        self.store_local_enr(updated);
+        self.propagate_enr_update();
+        Ok(())
+    }
+
+    /// Queues the updated local ENR to be written to the ENR store, if one is configured.
+    fn store_local_enr(&self, enr: Enr) {
+        if let Some(writer) = self.enr_writer.as_ref() {
+            writer.write(enr);
+        }
+    }
+
+    /// Starts pinging the peers we have a session with to inform them of the current sequence
+    /// number of the local ENR, if enabled.
+    fn propagate_enr_update(&mut self) {
+        if !self.enr_propagation.is_enabled() {
+            return;
+        }
+        let seq = self.local_enr.read().seq();
+        self.session_enrs.remove_expired_values();
+        let peers = self.session_enrs.keys().copied().collect();
+        if let Some(PropagationEvent::Completed {
+            seq,
+            pinged,
+            acknowledged,
+        }) = self.enr_propagation.start(seq, peers)
+        {
+            debug!(
+                seq,
+                pinged, acknowledged, "ENR update propagation interrupted"
+            );
+            self.send_event(Event::EnrUpdatePropagated {
+                seq,
+                pinged,
+                acknowledged,
+            });
+        }
+    }
+
+    /// Completes a key rotation performed by the handler by rebuilding the routing table around
+    /// the new node id, and returns the new node id to the caller.
+    fn key_rotated(&mut self, result: Result<(NodeId, NodeId), EnrError>) {
+        let result = result.map(|(old_node_id, new_node_id)| {
+            let dropped = self.kbuckets.write().set_local_key(new_node_id.into());
+            if !dropped.is_empty() {
+                debug!(
+                    dropped = dropped.len(),
+                    "Nodes removed from the routing table after rotating the local key"
+                );
+            }
+            let updated = self.local_enr.read().clone();
+            self.store_local_enr(updated);
+            info!(%old_node_id, %new_node_id, "Local key rotated");
+            self.send_event(Event::KeyRotated {
+                old_node_id,
+                new_node_id,
+            });
+            new_node_id
+        });
+        match self.key_rotations.pop_front() {
+            Some(callback) => {
+                if callback.send(result).is_err() {
+                    error!("Failed to return the new node id");
+                }
+            }
+            None => debug_unreachable!("Key rotated without a pending rotation"),
+        }
+    }
+
     fn send_event(&mut self, event: Event) {
         if let Some(stream) = self.event_stream.as_mut() {
             if let Err(mpsc::error::TrySendError::Closed(_)) = stream.try_send(event) {
@@ -1326,6 +1928,14 @@ impl Service {
                     {
                         query.target_mut().untrusted_enrs.push(enr_ref.clone());
                     }
+                    let reporters = query
+                        .target_mut()
+                        .reporters
+                        .entry(enr_ref.node_id())
+                        .or_default();
+                    if !reporters.contains(source) {
+                        reporters.push(*source);
+                    }
                     peer_count += 1;
                 }
                 debug!(peer_count, ?query_id, "peers found for query id");
@@ -1487,123 +2097,214 @@ impl Service {
             self.connectivity_state.received_incoming_connection(socket);
         }
 
+        let node_id = enr.node_id();
+        let started_by_probe = self.probe_session_established(&node_id, connection_direction);
+
         // Ignore sessions with non-contactable ENRs
         if self.ip_mode.get_contactable_addr(&enr).is_none() {
             return;
         }
 
-        let node_id = enr.node_id();
-
         // We never update connection direction if a node already exists in the routing table as we
         // don't want to promote the direction from incoming to outgoing.
         let key = kbucket::Key::from(node_id);
-        let direction = match self
+        let existing_direction = match self
             .kbuckets
             .read()
             .get_bucket(&key)
             .map(|bucket| bucket.get(&key))
         {
-            Some(Some(node)) => node.status.direction,
-            _ => connection_direction,
+            Some(Some(node)) => Some(node.status.direction),
+            _ => None,
         };
 
+        // Probed nodes are not inserted into the routing table by the session of the probe.
+        if started_by_probe && existing_direction.is_none() {
+            return;
+        }
+        let direction = existing_direction.unwrap_or(connection_direction);
+
         debug!(node = %node_id, %direction, %socket, "Session established with Node");
         self.connection_updated(node_id, ConnectionStatus::Connected(enr, direction));
     }
 
+    /// Marks the probes of the node that await a session as having one. Returns whether the
+    /// session was started by a probe.
+    fn probe_session_established(
+        &mut self,
+        node_id: &NodeId,
+        direction: ConnectionDirection,
+    ) -> bool {
+        let mut awaited = false;
+        for ActiveRequest {
+            contact, callback, ..
+        } in self.active_requests.values_mut()
+        {
+            if let Some(CallbackResponse::Probe(_, _, session_established)) = callback {
+                if contact.node_id() == *node_id && !*session_established {
+                    *session_established = true;
+                    awaited = true;
+                }
+            }
+        }
+        awaited && direction == ConnectionDirection::Outgoing
+    }
+
     /// A session could not be established or an RPC request timed-out (after a few retries, if
     /// specified).
     fn rpc_failure(&mut self, id: RequestId, error: RequestError) {
         trace!(reason = ?error, %id, "RPC Error removing request.");
         if let Some(active_request) = self.active_requests.remove(&id) {
-            // If this is initiated by the user, return an error on the callback. All callbacks
-            // support a request error.
-            match active_request.callback {
-                Some(CallbackResponse::Nodes(callback)) => {
-                    callback
-                        .send(Err(error))
-                        .unwrap_or_else(|_| debug!("Couldn't send Nodes error response to user"));
-                    return;
-                }
-                Some(CallbackResponse::Talk(callback)) => {
-                    // return the error
-                    callback
-                        .send(Err(error))
-                        .unwrap_or_else(|_| debug!("Couldn't send TALK error response to user"));
-                    return;
-                }
-                Some(CallbackResponse::Pong(callback)) => {
-                    // return the error
-                    callback
-                        .send(Err(error))
-                        .unwrap_or_else(|_| debug!("Couldn't send Pong error response to user"));
-                    return;
-                }
-                None => {
-                    // no callback to send too
-                }
+            self.outbound
+                .request_completed(&active_request.contact.node_id());
+            let peer_event = match error {
+                RequestError::Timeout => Some(PeerEvent::Timeout),
+                RequestError::InvalidRemotePacket => Some(PeerEvent::HandshakeFailure),
+                RequestError::InvalidRemoteEnr => Some(PeerEvent::MalformedEnr),
+                _ => None,
+            };
+            if let Some(peer_event) = peer_event {
+                self.record_peer_event(&active_request.contact.node_address(), peer_event);
             }
-
             let node_id = active_request.contact.node_id();
-            match active_request.request_body {
-                // if a failed FindNodes request, ensure we haven't partially received packets. If
-                // so, process the partially found nodes
-                RequestBody::FindNode { ref distances } => {
-                    if let Some(nodes_response) = self.active_nodes_responses.remove(&id) {
-                        if !nodes_response.received_nodes.is_empty() {
-                            let node_id = active_request.contact.node_id();
-                            let addr = active_request.contact.socket_addr();
-                            let received = nodes_response.received_nodes.len();
-                            warn!(%node_id, %addr, %error, %received, requested_distances = ?distances, "FINDNODE request failed with partial results");
-                            // if it's a query mark it as success, to process the partial
-                            // collection of peers
-                            self.discovered(
-                                &node_id,
-                                nodes_response.received_nodes,
-                                active_request.query_id,
-                            );
-                        }
-                    } else {
-                        // there was no partially downloaded nodes inform the query of the failure
-                        // if it's part of a query
-                        if let Some(query_id) = active_request.query_id {
-                            if let Some(query) = self.queries.get_mut(query_id) {
-                                query.on_failure(&node_id);
-                            }
-                        } else {
-                            debug!(
-                                request_body = %active_request.request_body,
-                                node = %active_request.contact,
-                                "Failed RPC request",
-                            );
-                        }
+            let socket = active_request.contact.socket_addr();
+            if self.report_request_failure(Some(&id), active_request, error) {
+                return;
+            }
+
+            if self
+                .config
+                .bootnodes
+                .iter()
+                .any(|bootnode| bootnode.node_id() == node_id)
+            {
+                debug!(%node_id, %socket, "Bootnode unreachable");
+                self.send_event(Event::BootnodeUnreachable { node_id, socket });
+            }
+
+            self.connection_updated(node_id, ConnectionStatus::Disconnected);
+        }
+    }
+
+    /// Reports a failed request to its callback, or to the query it belongs to. Requests that
+    /// were queued but never sent have no id. Returns whether the failure was reported to a
+    /// callback.
+    fn report_request_failure(
+        &mut self,
+        id: Option<&RequestId>,
+        active_request: ActiveRequest,
+        error: RequestError,
+    ) -> bool {
+        // If this is initiated by the user, return an error on the callback. All callbacks
+        // support a request error.
+        match active_request.callback {
+            Some(CallbackResponse::Nodes(callback)) => {
+                callback
+                    .send(Err(error))
+                    .unwrap_or_else(|_| debug!("Couldn't send Nodes error response to user"));
+                return true;
+            }
+            Some(CallbackResponse::Talk(callback)) => {
+                // return the error
+                callback
+                    .send(Err(error))
+                    .unwrap_or_else(|_| debug!("Couldn't send TALK error response to user"));
+                return true;
+            }
+            Some(CallbackResponse::Pong(callback)) => {
+                // return the error
+                callback
+                    .send(Err(error))
+                    .unwrap_or_else(|_| debug!("Couldn't send Pong error response to user"));
+                return true;
+            }
+            Some(CallbackResponse::Probe(callback, _, session_established)) => {
+                // Without an established session, the handshake is considered to have failed.
+                let step = if session_established {
+                    ProbeStep::Ping
+                } else {
+                    ProbeStep::Handshake
+                };
+                let result = ProbeResult {
+                    session_established,
+                    observed_addr: None,
+                    rtt: None,
+                    failure: Some((step, error)),
+                };
+                callback
+                    .send(result)
+                    .unwrap_or_else(|_| debug!("Couldn't send probe result to user"));
+                return true;
+            }
+            Some(CallbackResponse::DialBack) => {
+                debug!(contact = %active_request.contact, %error, "Dial-back request failed");
+            }
+            None => {
+                // no callback to send too
+            }
+        }
+
+        let node_id = active_request.contact.node_id();
+        match active_request.request_body {
+            // if a failed FindNodes request, ensure we haven't partially received packets. If
+            // so, process the partially found nodes
+            RequestBody::FindNode { ref distances } => {
+                if let Some(nodes_response) =
+                    id.and_then(|id| self.active_nodes_responses.remove(id))
+                {
+                    if !nodes_response.received_nodes.is_empty() {
+                        let node_id = active_request.contact.node_id();
+                        let addr = active_request.contact.socket_addr();
+                        let received = nodes_response.received_nodes.len();
+                        warn!(%node_id, %addr, %error, %received, requested_distances = ?distances, "FINDNODE request failed with partial results");
+                        // if it's a query mark it as success, to process the partial
+                        // collection of peers
+                        self.discovered(
+                            &node_id,
+                            nodes_response.received_nodes,
+                            active_request.query_id,
+                        );
                     }
-                }
-                // for all other requests, if any are queries, mark them as failures.
-                _ => {
+                } else {
+                    // there was no partially downloaded nodes inform the query of the failure
+                    // if it's part of a query
                     if let Some(query_id) = active_request.query_id {
                         if let Some(query) = self.queries.get_mut(query_id) {
-                            debug!(
-                                request_body = %active_request.request_body,
-                                query_id = *query_id,
-                                node = %active_request.contact,
-                                "Failed query request",
-                            );
                             query.on_failure(&node_id);
                         }
                     } else {
                         debug!(
                             request_body = %active_request.request_body,
                             node = %active_request.contact,
-                            error = ?error,
                             "Failed RPC request",
                         );
                     }
                 }
             }
-
-            self.connection_updated(node_id, ConnectionStatus::Disconnected);
+            // for all other requests, if any are queries, mark them as failures.
+            _ => {
+                if let Some(query_id) = active_request.query_id {
+                    if let Some(query) = self.queries.get_mut(query_id) {
+                        debug!(
+                            request_body = %active_request.request_body,
+                            query_id = *query_id,
+                            node = %active_request.contact,
+                            "Failed query request",
+                        );
+                        query.on_failure(&node_id);
+                    }
+                } else {
+                    debug!(
+                        request_body = %active_request.request_body,
+                        node = %active_request.contact,
+                        error = ?error,
+                        "Failed RPC request",
+                    );
+                }
+            }
         }
+
+        false
     }
 
     /// Helper function that determines if we need more votes for a specific IP
@@ -1654,8 +2355,22 @@ impl Service {
 
     /// A future the maintains active queries. This returns completed and timed out queries, as
     /// well as queries which need to be driven further with extra requests.
-    async fn query_event_poll(queries: &mut QueryPool<QueryInfo, NodeId, Enr>) -> QueryEvent {
-        future::poll_fn(move |_cx| match queries.poll() {
+    ///
+    /// With adaptive request timeouts, peers with measured round-trip times are considered
+    /// unresponsive after a timeout derived from their round-trip times.
+    async fn query_event_poll(
+        queries: &mut QueryPool<QueryInfo, NodeId, Enr>,
+        rtts: &RwLock<RttTable>,
+        config: &Config,
+    ) -> QueryEvent {
+        let peer_timeout = |node_id: &NodeId| {
+            if !config.adaptive_request_timeout {
+                return None;
+            }
+            rtts.read()
+                .query_peer_timeout(node_id, config.query_peer_timeout)
+        };
+        future::poll_fn(move |_cx| match queries.poll(&peer_timeout) {
             QueryPoolState::Finished(query) => Poll::Ready(QueryEvent::Finished(Box::new(query))),
             QueryPoolState::Waiting(Some((query, return_peer))) => {
                 let node_id = return_peer;
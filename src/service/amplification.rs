@@ -0,0 +1,110 @@
+//! Limits the traffic we send to addresses that have not demonstrated that they are reachable.
+//!
+//! A FINDNODE request fits in a single small packet, but can be answered by many NODES packets.
+//! As the source address of a UDP packet can be spoofed, an attacker could use us to flood a
+//! victim with NODES responses. Addresses are therefore only trusted once they have answered one
+//! of our PINGs. Responses to all other addresses are limited to a byte budget per
+//! `AMPLIFICATION_WINDOW`.
+
+use crate::{lru_time_cache::LruTimeCache, metrics::METRICS};
+use std::{
+    net::SocketAddr,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+/// The time window in which the byte budget of an unverified address applies.
+pub(crate) const AMPLIFICATION_WINDOW: Duration = Duration::from_secs(60);
+
+/// The maximum number of unverified addresses for which sent bytes are tracked.
+const MAX_TRACKED_ADDRESSES: usize = 1000;
+
+pub(crate) struct AmplificationLimit {
+    /// The number of bytes that can be sent to an unverified address per window. If this is
+    /// `None`, responses are not limited.
+    budget: Option<usize>,
+    /// Addresses that have responded to our PINGs.
+    verified: LruTimeCache<SocketAddr, ()>,
+    /// The bytes sent to unverified addresses and the start of their current window.
+    spent: LruTimeCache<SocketAddr, (usize, Instant)>,
+}
+
+impl AmplificationLimit {
+    pub fn new(budget: Option<usize>, verified_ttl: Duration, capacity: usize) -> Self {
+        AmplificationLimit {
+            budget,
+            verified: LruTimeCache::new(verified_ttl, Some(capacity)),
+            spent: LruTimeCache::new(AMPLIFICATION_WINDOW, Some(MAX_TRACKED_ADDRESSES)),
+        }
+    }
+
+    /// Marks the address as reachable, lifting the byte budget.
+    pub fn verify(&mut self, socket_addr: SocketAddr) {
+        self.verified.insert(socket_addr, ());
+        self.spent.remove(&socket_addr);
+    }
+
+    /// Given the sizes of the packets of a response, returns how many of the packets can be sent
+    /// to the address. The bytes of the allowed packets are deducted from the budget.
+    pub fn allowed_packets(&mut self, socket_addr: SocketAddr, packet_sizes: &[usize]) -> usize {
+        let Some(budget) = self.budget else {
+            return packet_sizes.len();
+        };
+        if self.verified.peek(&socket_addr).is_some() {
+            return packet_sizes.len();
+        }
+
+        let now = Instant::now();
+        let (mut spent, window_start) = match self.spent.peek(&socket_addr) {
+            Some((spent, window_start)) if now < *window_start + AMPLIFICATION_WINDOW => {
+                (*spent, *window_start)
+            }
+            _ => (0, now),
+        };
+
+        let mut allowed = 0;
+        for size in packet_sizes {
+            if spent + size > budget {
+                break;
+            }
+            spent += size;
+            allowed += 1;
+        }
+        self.spent.insert(socket_addr, (spent, window_start));
+
+        if allowed < packet_sizes.len() {
+            METRICS
+                .amplification_limited_responses
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unverified_address_budget() {
+        let mut limit = AmplificationLimit::new(Some(2000), Duration::from_secs(60), 10);
+        let socket_addr: SocketAddr = "10.0.0.1:9000".parse().unwrap();
+        let other_addr: SocketAddr = "10.0.0.2:9000".parse().unwrap();
+
+        assert_eq!(limit.allowed_packets(socket_addr, &[1000, 800, 500]), 2);
+        assert_eq!(limit.allowed_packets(socket_addr, &[300]), 0);
+        assert_eq!(limit.allowed_packets(socket_addr, &[100, 100]), 2);
+        assert_eq!(limit.allowed_packets(other_addr, &[1200]), 1);
+
+        // Verified addresses are not limited.
+        limit.verify(socket_addr);
+        assert_eq!(limit.allowed_packets(socket_addr, &[1200; 10]), 10);
+    }
+
+    #[test]
+    fn test_disabled_budget() {
+        let mut limit = AmplificationLimit::new(None, Duration::from_secs(60), 10);
+        let socket_addr: SocketAddr = "10.0.0.1:9000".parse().unwrap();
+        assert_eq!(limit.allowed_packets(socket_addr, &[1200; 10]), 10);
+    }
+}
@@ -16,8 +16,11 @@
 //!    external ENR address to None and set the `next_connectivity_test` to
 //!    DURATION_UNTIL_NEXT_CONNECTIVITY_ATTEMPT in the future. This will prevent counting votes until
 //!    this time, which prevents our ENR from being updated.
+//!
+//! IP versions whose external address is pinned by an [`ExternalAddressMode`] other than `Auto`
+//! are never tested nor revoked, and their votes are not counted.
 
-use crate::metrics::METRICS;
+use crate::{metrics::METRICS, ExternalAddressMode};
 use futures::{
     future::{pending, Either},
     FutureExt,
@@ -63,10 +66,18 @@ pub(crate) struct ConnectivityState {
     ipv4_incoming_count: usize,
     /// The number of incoming ipv6 nodes we have seen during our awaiting window.
     ipv6_incoming_count: usize,
+    /// Whether the external ipv4 socket is learned from peers, rather than pinned.
+    ipv4_auto: bool,
+    /// Whether the external ipv6 socket is learned from peers, rather than pinned.
+    ipv6_auto: bool,
 }
 
 impl ConnectivityState {
-    pub fn new(duration_for_incoming_connections: Option<Duration>) -> Self {
+    pub fn new(
+        duration_for_incoming_connections: Option<Duration>,
+        ipv4_mode: ExternalAddressMode,
+        ipv6_mode: ExternalAddressMode,
+    ) -> Self {
         ConnectivityState {
             duration_for_incoming_connections,
             ipv4_incoming_wait_time: None,
@@ -75,12 +86,19 @@ impl ConnectivityState {
             ipv6_next_connectivity_test: Instant::now(),
             ipv4_incoming_count: 0,
             ipv6_incoming_count: 0,
+            ipv4_auto: ipv4_mode.is_auto(),
+            ipv6_auto: ipv6_mode.is_auto(),
         }
     }
 
     /// Checks if we are in a state to handle new IP votes. If we are waiting to do a connectivity
     /// test for this specific ip kind, this returns false.
     pub fn should_count_ip_vote(&self, socket: &SocketAddr) -> bool {
+        // The external socket of a pinned IP version is not learned from peers.
+        if !self.is_auto(socket) {
+            return false;
+        }
+
         // If this configuration is not set, we just accept all votes and disable this
         // functionality.
         if self.duration_for_incoming_connections.is_none() {
@@ -99,6 +117,9 @@ impl ConnectivityState {
     /// is not None) then we start a timer to await for any kind of incoming connection. This will
     /// verify that we are contactable. If we receive nothing in `duration_for_incoming_connections` then we consider ourselves non-contactable
     pub fn enr_socket_update(&mut self, socket: &SocketAddr) {
+        if !self.is_auto(socket) {
+            return;
+        }
         if let Some(duration_to_wait) = self.duration_for_incoming_connections {
             match socket {
                 SocketAddr::V4(_) => {
@@ -113,6 +134,14 @@ impl ConnectivityState {
         }
     }
 
+    /// Whether the external socket of the socket's IP version is learned from peers.
+    fn is_auto(&self, socket: &SocketAddr) -> bool {
+        match socket {
+            SocketAddr::V4(_) => self.ipv4_auto,
+            SocketAddr::V6(_) => self.ipv6_auto,
+        }
+    }
+
     // We have received an incoming connection. If we were awaiting for a connection, we remove the
     // expiry timer and we are done. The ENR will remain advertised and new votes will still count
     // to potentially change the IP address if a legitimate change occurs.
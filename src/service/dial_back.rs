@@ -0,0 +1,118 @@
+//! Actively tests whether our advertised address is reachable.
+//!
+//! After our ENR socket is updated, the [`ConnectivityState`](super::ConnectivityState) waits for
+//! incoming sessions to confirm that the address is reachable. Rather than waiting for other nodes
+//! to contact us, we ask a few connected peers to dial us back using a TALK request of the
+//! [`DIAL_BACK_PROTOCOL`], carrying our ENR.
+//!
+//! The peer dials back from a fresh socket with a fresh node id, so that neither an existing
+//! session nor an existing NAT mapping towards the peer can make the dial succeed. The new session
+//! is an incoming session for us and counts towards confirming our reachability. The peer answers
+//! the TALK request with a single byte, `1` if the dial-back succeeded and `0` otherwise.
+//!
+//! To prevent our node from being used to send packets to arbitrary addresses, a dial-back is only
+//! performed to the IP the request was received from.
+
+use crate::{
+    handler::{Handler, HandlerIn, HandlerOut, RttTable},
+    node_info::{NodeAddress, NodeContact},
+    rpc::{Request, RequestBody, RequestId},
+    Config, Enr, IpMode, ListenConfig,
+};
+use alloy_rlp::Decodable;
+use enr::CombinedKey;
+use parking_lot::RwLock;
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+};
+use tracing::debug;
+
+/// The TALK protocol used to request a dial-back.
+pub const DIAL_BACK_PROTOCOL: &[u8] = b"dialback";
+
+/// The maximum number of dial-backs we perform for other nodes at once.
+pub(crate) const MAX_CONCURRENT_DIAL_BACKS: usize = 4;
+
+/// Validates a dial-back request, returning the contact to dial back.
+pub(crate) fn dial_back_contact(
+    node_address: &NodeAddress,
+    request: &[u8],
+    ip_mode: IpMode,
+) -> Option<NodeContact> {
+    let enr = Enr::decode(&mut &request[..])
+        .map_err(|e| debug!(error = %e, "Invalid ENR in dial-back request"))
+        .ok()?;
+    let contact = NodeContact::try_from_enr(enr, ip_mode).ok()?;
+    if contact.node_id() != node_address.node_id {
+        debug!(node = %node_address, "Dial-back requested for a different node");
+        return None;
+    }
+    if contact.socket_addr().ip() != node_address.socket_addr.ip() {
+        debug!(node = %node_address, "Dial-back requested to a different IP");
+        return None;
+    }
+    Some(contact)
+}
+
+/// Dials the node from a fresh socket and node id and PINGs it. Returns whether the node
+/// responded.
+pub(crate) async fn dial_back(contact: NodeContact, mut config: Config) -> bool {
+    config.listen_config = match contact.socket_addr() {
+        SocketAddr::V4(_) => ListenConfig::Ipv4 {
+            ip: Ipv4Addr::UNSPECIFIED,
+            port: 0,
+        },
+        SocketAddr::V6(_) => ListenConfig::Ipv6 {
+            ip: Ipv6Addr::UNSPECIFIED,
+            port: 0,
+        },
+    };
+    // The dial-back does not need to be rate limited or filtered.
+    config.enable_packet_filter = false;
+
+    let key = CombinedKey::generate_secp256k1();
+    let enr = match Enr::builder().build(&key) {
+        Ok(enr) => enr,
+        Err(e) => {
+            debug!(error = ?e, "Failed to build ENR for dial-back");
+            return false;
+        }
+    };
+
+    let (exit, handler_send, mut handler_recv) = match Handler::spawn(
+        Arc::new(RwLock::new(enr)),
+        Arc::new(RwLock::new(Arc::new(key))),
+        Arc::new(RwLock::new(RttTable::new(1))),
+        config,
+    )
+    .await
+    {
+        Ok(handler) => handler,
+        Err(e) => {
+            debug!(error = %e, "Failed to spawn dial-back handler");
+            return false;
+        }
+    };
+
+    let request = Request {
+        id: RequestId::random(),
+        body: RequestBody::Ping { enr_seq: 0 },
+    };
+    let reached = if handler_send
+        .send(HandlerIn::Request(contact, Box::new(request)))
+        .is_ok()
+    {
+        loop {
+            match handler_recv.recv().await {
+                Some(HandlerOut::Response(..)) => break true,
+                Some(HandlerOut::RequestFailed(..)) | None => break false,
+                Some(_) => {}
+            }
+        }
+    } else {
+        false
+    };
+    let _ = exit.send(());
+    reached
+}
@@ -0,0 +1,272 @@
+//! Proactively informs peers of updates of the local ENR.
+//!
+//! Peers learn about a new sequence number of our ENR from the PINGs we send them, after which
+//! they request the updated record with a FINDNODE request at distance 0. Without propagation,
+//! this happens on the next regular PING, up to `ping_interval` later. When the sequence number of
+//! the local ENR increases, a propagation round pings every peer we have a session with. The
+//! PINGs are spread evenly over the configured window, so that a large number of sessions does
+//! not result in a burst of requests.
+//!
+//! Peers requesting our ENR during the round are counted as having acknowledged the update. The
+//! round ends a grace period after the last PING, allowing the last peers to respond. A new update
+//! during a round ends the round early and starts a new one. Rounds begin at least a minimum
+//! interval apart, so that frequent updates do not cause a PING to every peer each time. A round
+//! that has yet to begin is replaced by the next update.
+
+use enr::NodeId;
+use futures::future::pending;
+use rand::seq::SliceRandom;
+use std::{
+    collections::{HashSet, VecDeque},
+    pin::Pin,
+    time::Duration,
+};
+use tokio::time::{sleep_until, Instant, Sleep};
+
+/// The events produced by polling the [`EnrPropagation`].
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum PropagationEvent {
+    /// The peer should be pinged.
+    Ping(NodeId),
+    /// A propagation round has ended.
+    Completed {
+        /// The sequence number propagated.
+        seq: u64,
+        /// The number of peers pinged.
+        pinged: usize,
+        /// The number of peers that requested the updated ENR.
+        acknowledged: usize,
+    },
+}
+
+struct Round {
+    /// The sequence number being propagated.
+    seq: u64,
+    /// The peers that have yet to be pinged, and when.
+    scheduled: VecDeque<(Instant, NodeId)>,
+    /// The peers of the round that have been pinged.
+    pinged: HashSet<NodeId>,
+    /// The peers of the round that requested the updated ENR.
+    acknowledged: HashSet<NodeId>,
+    /// When the round ends, once all peers are pinged.
+    ends_at: Instant,
+    /// The timer until the next PING or the end of the round.
+    timer: Pin<Box<Sleep>>,
+}
+
+impl Round {
+    fn completed(self) -> PropagationEvent {
+        PropagationEvent::Completed {
+            seq: self.seq,
+            pinged: self.pinged.len(),
+            acknowledged: self.acknowledged.len(),
+        }
+    }
+}
+
+pub(crate) struct EnrPropagation {
+    /// The window over which the PINGs of a round are spread. If this is `None`, updates are not
+    /// propagated.
+    window: Option<Duration>,
+    /// The time given to peers to request our ENR after the last PING of a round.
+    grace_period: Duration,
+    /// The minimum time between the beginnings of two rounds.
+    min_interval: Duration,
+    /// When the last round began, or will begin.
+    last_begin: Option<Instant>,
+    /// The round in progress, if any.
+    round: Option<Round>,
+}
+
+impl EnrPropagation {
+    pub fn new(window: Option<Duration>, grace_period: Duration, min_interval: Duration) -> Self {
+        EnrPropagation {
+            window,
+            grace_period,
+            min_interval,
+            last_begin: None,
+            round: None,
+        }
+    }
+
+    /// Whether updates of the local ENR are propagated.
+    pub fn is_enabled(&self) -> bool {
+        self.window.is_some()
+    }
+
+    /// Starts a round propagating the sequence number to the given peers. Returns the outcome of
+    /// the round in progress, if it was interrupted.
+    pub fn start(&mut self, seq: u64, mut peers: Vec<NodeId>) -> Option<PropagationEvent> {
+        let window = self.window?;
+        if self.round.as_ref().is_some_and(|round| round.seq >= seq) {
+            return None;
+        }
+        let now = Instant::now();
+        let (begins_at, interrupted) = match self.last_begin {
+            // The previous round has yet to begin and is replaced.
+            Some(last_begin) if last_begin > now => {
+                self.round = None;
+                (last_begin, None)
+            }
+            last_begin => (
+                last_begin.map_or(now, |last_begin| (last_begin + self.min_interval).max(now)),
+                self.round.take().map(Round::completed),
+            ),
+        };
+        self.last_begin = Some(begins_at);
+
+        peers.shuffle(&mut rand::thread_rng());
+        let spacing = window / peers.len().max(1) as u32;
+        let scheduled: VecDeque<_> = peers
+            .into_iter()
+            .enumerate()
+            .map(|(index, node_id)| (begins_at + spacing * index as u32, node_id))
+            .collect();
+        let last_ping = scheduled.back().map_or(begins_at, |(at, _)| *at);
+        let ends_at = last_ping + self.grace_period;
+        let next = scheduled.front().map_or(ends_at, |(at, _)| *at);
+
+        self.round = Some(Round {
+            seq,
+            scheduled,
+            pinged: HashSet::new(),
+            acknowledged: HashSet::new(),
+            ends_at,
+            timer: Box::pin(sleep_until(next)),
+        });
+        interrupted
+    }
+
+    /// Registers a request for our ENR from a peer.
+    pub fn enr_requested(&mut self, node_id: &NodeId) {
+        if let Some(round) = self.round.as_mut() {
+            if round.pinged.contains(node_id)
+                || round.scheduled.iter().any(|(_, peer)| peer == node_id)
+            {
+                round.acknowledged.insert(*node_id);
+            }
+        }
+    }
+
+    pub async fn poll(&mut self) -> PropagationEvent {
+        let Some(round) = self.round.as_mut() else {
+            return pending().await;
+        };
+        round.timer.as_mut().await;
+
+        match round.scheduled.pop_front() {
+            Some((_, node_id)) => {
+                round.pinged.insert(node_id);
+                let next = round.scheduled.front().map_or(round.ends_at, |(at, _)| *at);
+                round.timer.as_mut().reset(next);
+                PropagationEvent::Ping(node_id)
+            }
+            None => self
+                .round
+                .take()
+                .expect("A propagation round is in progress")
+                .completed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(200);
+    const GRACE_PERIOD: Duration = Duration::from_millis(50);
+
+    #[tokio::test]
+    async fn pings_are_spread_over_the_window() {
+        let mut propagation = EnrPropagation::new(Some(WINDOW), GRACE_PERIOD, Duration::ZERO);
+        let peers: Vec<_> = (0..4).map(|_| NodeId::random()).collect();
+        let start = Instant::now();
+        assert_eq!(propagation.start(2, peers.clone()), None);
+
+        let mut pinged = Vec::new();
+        for index in 0..4 {
+            match propagation.poll().await {
+                PropagationEvent::Ping(node_id) => pinged.push(node_id),
+                event => panic!("Unexpected event {:?}", event),
+            }
+            assert!(start.elapsed() >= WINDOW / 4 * index);
+        }
+        propagation.enr_requested(&pinged[0]);
+        propagation.enr_requested(&pinged[1]);
+        // Requests of peers outside of the round are not counted.
+        propagation.enr_requested(&NodeId::random());
+
+        assert_eq!(
+            propagation.poll().await,
+            PropagationEvent::Completed {
+                seq: 2,
+                pinged: 4,
+                acknowledged: 2
+            }
+        );
+        assert!(start.elapsed() >= WINDOW / 4 * 3 + GRACE_PERIOD);
+        assert_eq!(
+            pinged.into_iter().collect::<HashSet<_>>(),
+            peers.into_iter().collect::<HashSet<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn new_update_interrupts_round() {
+        let mut propagation = EnrPropagation::new(Some(WINDOW), GRACE_PERIOD, Duration::ZERO);
+        let peers: Vec<_> = (0..4).map(|_| NodeId::random()).collect();
+        propagation.start(2, peers.clone());
+        assert!(matches!(
+            propagation.poll().await,
+            PropagationEvent::Ping(_)
+        ));
+
+        // An outdated sequence number does not restart the round.
+        assert_eq!(propagation.start(2, peers.clone()), None);
+        assert_eq!(
+            propagation.start(3, peers),
+            Some(PropagationEvent::Completed {
+                seq: 2,
+                pinged: 1,
+                acknowledged: 0
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn rounds_begin_a_minimum_interval_apart() {
+        const MIN_INTERVAL: Duration = Duration::from_millis(300);
+        let mut propagation = EnrPropagation::new(Some(WINDOW), GRACE_PERIOD, MIN_INTERVAL);
+        let start = Instant::now();
+        propagation.start(2, vec![NodeId::random()]);
+        assert!(matches!(
+            propagation.poll().await,
+            PropagationEvent::Ping(_)
+        ));
+        assert!(start.elapsed() < MIN_INTERVAL);
+
+        // The next round is delayed, and replaced by a further update before it begins.
+        assert_eq!(
+            propagation.start(3, vec![NodeId::random()]),
+            Some(PropagationEvent::Completed {
+                seq: 2,
+                pinged: 1,
+                acknowledged: 0
+            })
+        );
+        let peer = NodeId::random();
+        assert_eq!(propagation.start(4, vec![peer]), None);
+        assert_eq!(propagation.poll().await, PropagationEvent::Ping(peer));
+        assert!(start.elapsed() >= MIN_INTERVAL);
+        assert!(start.elapsed() < MIN_INTERVAL * 2);
+    }
+
+    #[test]
+    fn disabled_propagation_starts_no_round() {
+        let mut propagation = EnrPropagation::new(None, GRACE_PERIOD, Duration::ZERO);
+        assert!(!propagation.is_enabled());
+        assert_eq!(propagation.start(2, vec![NodeId::random()]), None);
+        assert!(propagation.round.is_none());
+    }
+}
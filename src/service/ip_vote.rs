@@ -17,13 +17,17 @@
 //!
 //!       The CLEAR_MAJORITY_PERCENTAGE criteria prevents us from advertising the first vote that
 //!       reaches the threshold then reverting back to an empty ENR in the case where multiple ports are being cycled.
+//!
+//! Node ids are free to generate, so a single host could cast any number of votes with as many
+//! node ids. Votes are therefore counted per subnet of the voter's IP address rather than per node
+//! id: all voters of a /24 IPv4 subnet, or of an IPv6 subnet of the configured prefix length,
+//! share a single vote, held by the most recent of them.
 
-use enr::NodeId;
 use fnv::FnvHashMap;
 use std::{
     collections::HashMap,
     hash::Hash,
-    net::{SocketAddr, SocketAddrV4, SocketAddrV6},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
     time::{Duration, Instant},
 };
 use tracing::debug;
@@ -32,20 +36,25 @@ use tracing::debug;
 /// likely candidate.
 const CLEAR_MAJORITY_PERCENTAGE: f64 = 0.3;
 
+/// The prefix length of the IPv4 subnets sharing a vote.
+const IPV4_SUBNET_PREFIX: u8 = 24;
+
 /// A collection of IP:Ports for our node reported from external peers.
 pub(crate) struct IpVote {
-    /// The current collection of IP:Port votes for ipv4.
-    ipv4_votes: HashMap<NodeId, (SocketAddrV4, Instant)>,
-    /// The current collection of IP:Port votes for ipv6.
-    ipv6_votes: HashMap<NodeId, (SocketAddrV6, Instant)>,
+    /// The current collection of IP:Port votes for ipv4, by voter subnet.
+    ipv4_votes: HashMap<IpAddr, (SocketAddrV4, Instant)>,
+    /// The current collection of IP:Port votes for ipv6, by voter subnet.
+    ipv6_votes: HashMap<IpAddr, (SocketAddrV6, Instant)>,
     /// The minimum number of votes required before an IP/PORT is accepted.
     minimum_threshold: usize,
     /// The time votes remain valid.
     vote_duration: Duration,
+    /// The prefix length of the IPv6 subnets sharing a vote.
+    ipv6_subnet_prefix: u8,
 }
 
 impl IpVote {
-    pub fn new(minimum_threshold: usize, vote_duration: Duration) -> Self {
+    pub fn new(minimum_threshold: usize, vote_duration: Duration, ipv6_subnet_prefix: u8) -> Self {
         // do not allow minimum thresholds less than 2
         if minimum_threshold < 2 {
             panic!("Setting enr_peer_update_min to a value less than 2 will cause issues with discovery with peers behind NAT");
@@ -55,10 +64,28 @@ impl IpVote {
             ipv6_votes: HashMap::new(),
             minimum_threshold,
             vote_duration,
+            ipv6_subnet_prefix: ipv6_subnet_prefix.min(128),
+        }
+    }
+
+    /// The subnet of a voter's IP address, sharing a single vote.
+    fn voter_subnet(&self, voter: IpAddr) -> IpAddr {
+        match voter.to_canonical() {
+            IpAddr::V4(ip) => {
+                let mask = u32::MAX.checked_shl(32 - IPV4_SUBNET_PREFIX as u32);
+                Ipv4Addr::from(u32::from(ip) & mask.unwrap_or(0)).into()
+            }
+            IpAddr::V6(ip) => {
+                let mask = u128::MAX.checked_shl(128 - self.ipv6_subnet_prefix as u32);
+                Ipv6Addr::from(u128::from(ip) & mask.unwrap_or(0)).into()
+            }
         }
     }
 
-    pub fn insert(&mut self, key: NodeId, socket: impl Into<SocketAddr>) {
+    /// Registers the external socket reported by a voter, replacing the previous vote of the
+    /// voter's subnet.
+    pub fn insert(&mut self, voter: IpAddr, socket: impl Into<SocketAddr>) {
+        let key = self.voter_subnet(voter);
         match socket.into() {
             SocketAddr::V4(socket) => {
                 self.ipv4_votes
@@ -93,9 +120,9 @@ impl IpVote {
     /// If the second highest candidate is within 20% of the highest, we also return None.
     /// If there are not enough votes to meet the threshold this returns None.
     fn filter_stale_find_most_frequent<K: Copy + Eq + Hash + std::fmt::Debug>(
-        votes: &HashMap<NodeId, (K, Instant)>,
+        votes: &HashMap<IpAddr, (K, Instant)>,
         minimum_threshold: usize,
-    ) -> (HashMap<NodeId, (K, Instant)>, Option<K>) {
+    ) -> (HashMap<IpAddr, (K, Instant)>, Option<K>) {
         let mut updated = HashMap::default();
         let mut counter: FnvHashMap<K, usize> = FnvHashMap::default();
         let mut max_count = 0;
@@ -103,12 +130,12 @@ impl IpVote {
         let mut max_vote = None;
         let now = Instant::now();
 
-        for (node_id, (vote, instant)) in votes {
+        for (subnet, (vote, instant)) in votes {
             // Discard stale votes
             if instant <= &now {
                 continue;
             }
-            updated.insert(*node_id, (*vote, *instant));
+            updated.insert(*subnet, (*vote, *instant));
 
             let count = counter.entry(*vote).or_default();
             *count += 1;
@@ -176,27 +203,36 @@ impl IpVote {
 
 #[cfg(test)]
 mod tests {
-    use super::{Duration, IpVote, NodeId, SocketAddrV4, CLEAR_MAJORITY_PERCENTAGE};
+    use super::{Duration, IpAddr, IpVote, Ipv4Addr, SocketAddrV4, CLEAR_MAJORITY_PERCENTAGE};
     use quickcheck::{quickcheck, Arbitrary, Gen, TestResult};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    const IPV6_SUBNET_PREFIX: u8 = 64;
+
+    /// Returns the address of a voter in a subnet of its own.
+    fn new_voter() -> IpAddr {
+        static NEXT_SUBNET: AtomicU32 = AtomicU32::new(1);
+        Ipv4Addr::from(NEXT_SUBNET.fetch_add(1, Ordering::Relaxed) << 8).into()
+    }
 
     #[test]
     fn test_three_way_vote_draw() {
-        let mut votes = IpVote::new(2, Duration::from_secs(10));
+        let mut votes = IpVote::new(2, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
 
         let socket_1 = SocketAddrV4::new("127.0.0.1".parse().unwrap(), 1);
         let socket_2 = SocketAddrV4::new("127.0.0.1".parse().unwrap(), 2);
         let socket_3 = SocketAddrV4::new("127.0.0.1".parse().unwrap(), 3);
 
         // 3 votes for each socket
-        votes.insert(NodeId::random(), socket_1);
-        votes.insert(NodeId::random(), socket_1);
-        votes.insert(NodeId::random(), socket_1);
-        votes.insert(NodeId::random(), socket_2);
-        votes.insert(NodeId::random(), socket_2);
-        votes.insert(NodeId::random(), socket_2);
-        votes.insert(NodeId::random(), socket_3);
-        votes.insert(NodeId::random(), socket_3);
-        votes.insert(NodeId::random(), socket_3);
+        votes.insert(new_voter(), socket_1);
+        votes.insert(new_voter(), socket_1);
+        votes.insert(new_voter(), socket_1);
+        votes.insert(new_voter(), socket_2);
+        votes.insert(new_voter(), socket_2);
+        votes.insert(new_voter(), socket_2);
+        votes.insert(new_voter(), socket_3);
+        votes.insert(new_voter(), socket_3);
+        votes.insert(new_voter(), socket_3);
 
         // With new logic, draw situations should return None due to competing votes
         assert!(votes.majority().0.is_none());
@@ -204,31 +240,31 @@ mod tests {
 
     #[test]
     fn test_majority_vote() {
-        let mut votes = IpVote::new(2, Duration::from_secs(10));
+        let mut votes = IpVote::new(2, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
         let socket_1 = SocketAddrV4::new("127.0.0.1".parse().unwrap(), 1);
         let socket_2 = SocketAddrV4::new("127.0.0.1".parse().unwrap(), 2);
 
         // 5 votes for socket_1, 1 vote for socket_2
         // 1 < (5 * (1-CLEAR_MAJORITY_PERCENTAGE)) = 3.5, so clear majority
         for _ in 0..5 {
-            votes.insert(NodeId::random(), socket_1);
+            votes.insert(new_voter(), socket_1);
         }
-        votes.insert(NodeId::random(), socket_2);
+        votes.insert(new_voter(), socket_2);
 
         assert_eq!(votes.majority(), (Some(socket_1), None));
     }
 
     #[test]
     fn test_below_threshold() {
-        let mut votes = IpVote::new(3, Duration::from_secs(10));
+        let mut votes = IpVote::new(3, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
         let socket_1 = SocketAddrV4::new("127.0.0.1".parse().unwrap(), 1);
         let socket_2 = SocketAddrV4::new("127.0.0.1".parse().unwrap(), 2);
         let socket_3 = SocketAddrV4::new("127.0.0.1".parse().unwrap(), 3);
 
-        votes.insert(NodeId::random(), socket_1);
-        votes.insert(NodeId::random(), socket_1);
-        votes.insert(NodeId::random(), socket_2);
-        votes.insert(NodeId::random(), socket_3);
+        votes.insert(new_voter(), socket_1);
+        votes.insert(new_voter(), socket_1);
+        votes.insert(new_voter(), socket_2);
+        votes.insert(new_voter(), socket_3);
 
         assert_eq!(votes.majority(), (None, None));
     }
@@ -246,23 +282,23 @@ mod tests {
 
         // Run multiple iterations with alternating vote insertion order
         for iteration in 0..10 {
-            let mut votes = IpVote::new(2, Duration::from_secs(10));
+            let mut votes = IpVote::new(2, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
 
             if iteration % 2 == 0 {
                 // Even iterations: port_1 votes first
                 for _ in 0..3 {
-                    votes.insert(NodeId::random(), port_1);
+                    votes.insert(new_voter(), port_1);
                 }
                 for _ in 0..3 {
-                    votes.insert(NodeId::random(), port_2);
+                    votes.insert(new_voter(), port_2);
                 }
             } else {
                 // Odd iterations: port_2 votes first
                 for _ in 0..3 {
-                    votes.insert(NodeId::random(), port_2);
+                    votes.insert(new_voter(), port_2);
                 }
                 for _ in 0..3 {
-                    votes.insert(NodeId::random(), port_1);
+                    votes.insert(new_voter(), port_1);
                 }
             }
 
@@ -287,14 +323,14 @@ mod tests {
     #[derive(Debug, Clone)]
     struct VoteData {
         port: u16,
-        node_id: NodeId,
+        voter: IpAddr,
     }
 
     impl Arbitrary for VoteData {
         fn arbitrary<G: Gen>(g: &mut G) -> VoteData {
             VoteData {
                 port: u16::arbitrary(g),
-                node_id: NodeId::random(),
+                voter: new_voter(),
             }
         }
     }
@@ -321,13 +357,13 @@ mod tests {
                 return TestResult::discard();
             }
 
-            let mut vote_system = IpVote::new(scenario.threshold, Duration::from_secs(10));
+            let mut vote_system = IpVote::new(scenario.threshold, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
             let ip = "192.168.1.1".parse().unwrap();
 
             // Add all votes
             for vote_data in &scenario.votes {
                 let socket = SocketAddrV4::new(ip, vote_data.port);
-                vote_system.insert(vote_data.node_id, socket);
+                vote_system.insert(vote_data.voter, socket);
             }
 
             // Count votes per port
@@ -351,13 +387,13 @@ mod tests {
                 return TestResult::discard();
             }
 
-            let mut vote_system = IpVote::new(scenario.threshold, Duration::from_secs(10));
+            let mut vote_system = IpVote::new(scenario.threshold, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
             let ip = "192.168.1.1".parse().unwrap();
 
             // Add votes
             for vote_data in &scenario.votes {
                 let socket = SocketAddrV4::new(ip, vote_data.port);
-                vote_system.insert(vote_data.node_id, socket);
+                vote_system.insert(vote_data.voter, socket);
             }
 
             // Count votes per port
@@ -397,16 +433,16 @@ mod tests {
 
         /// Property: Adding the same vote multiple times should be idempotent
         fn prop_same_vote_idempotent(port: u16) -> bool {
-            let mut vote_system = IpVote::new(2, Duration::from_secs(10));
+            let mut vote_system = IpVote::new(2, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
             let ip = "192.168.1.1".parse().unwrap();
             let socket = SocketAddrV4::new(ip, port);
-            let node_id = NodeId::random();
+            let voter = new_voter();
 
             // Add same vote multiple times
-            vote_system.insert(node_id, socket);
+            vote_system.insert(voter, socket);
             let result1 = vote_system.majority().0;
 
-            vote_system.insert(node_id, socket);
+            vote_system.insert(voter, socket);
             let result2 = vote_system.majority().0;
 
             result1 == result2
@@ -414,14 +450,14 @@ mod tests {
 
         /// Property: Vote count should never exceed number of unique node IDs
         fn prop_vote_count_bounded_by_nodes() -> bool {
-            let mut vote_system = IpVote::new(2, Duration::from_secs(10));
+            let mut vote_system = IpVote::new(2, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
             let ip = "192.168.1.1".parse().unwrap();
             let socket = SocketAddrV4::new(ip, 8080);
 
             // Add votes from 3 different nodes
-            let nodes = [NodeId::random(), NodeId::random(), NodeId::random()];
-            for &node_id in &nodes {
-                vote_system.insert(node_id, socket);
+            let nodes = [new_voter(), new_voter(), new_voter()];
+            for &voter in &nodes {
+                vote_system.insert(voter, socket);
             }
 
             // The implementation should count each node only once
@@ -445,16 +481,16 @@ mod tests {
                 return TestResult::discard();
             }
 
-            let mut votes = IpVote::new(threshold, Duration::from_secs(10));
+            let mut votes = IpVote::new(threshold, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
             let ip = "192.168.1.1".parse().unwrap();
             let socket1 = SocketAddrV4::new(ip, 8080);
             let socket2 = SocketAddrV4::new(ip, 8081);
 
             for _ in 0..first_votes {
-                votes.insert(NodeId::random(), socket1);
+                votes.insert(new_voter(), socket1);
             }
             for _ in 0..second_votes {
-                votes.insert(NodeId::random(), socket2);
+                votes.insert(new_voter(), socket2);
             }
 
             TestResult::from_bool(votes.majority().0.is_none())
@@ -476,16 +512,16 @@ mod tests {
                 return TestResult::discard();
             }
 
-            let mut votes = IpVote::new(threshold, Duration::from_secs(10));
+            let mut votes = IpVote::new(threshold, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
             let ip = "192.168.1.1".parse().unwrap();
             let socket1 = SocketAddrV4::new(ip, 8080);
             let socket2 = SocketAddrV4::new(ip, 8081);
 
             for _ in 0..first_votes {
-                votes.insert(NodeId::random(), socket1);
+                votes.insert(new_voter(), socket1);
             }
             for _ in 0..second_votes {
-                votes.insert(NodeId::random(), socket2);
+                votes.insert(new_voter(), socket2);
             }
 
             TestResult::from_bool(votes.majority().0 == Some(socket1))
@@ -494,17 +530,17 @@ mod tests {
 
     #[test]
     fn test_exact_threshold_boundary() {
-        let mut votes = IpVote::new(3, Duration::from_secs(10));
+        let mut votes = IpVote::new(3, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
         let ip = "192.168.1.1".parse().unwrap();
         let socket1 = SocketAddrV4::new(ip, 8080);
         let socket2 = SocketAddrV4::new(ip, 8081);
 
         // Add exactly threshold votes for one port
         for _ in 0..3 {
-            votes.insert(NodeId::random(), socket1);
+            votes.insert(new_voter(), socket1);
         }
         // Add 1 vote for another port
-        votes.insert(NodeId::random(), socket2);
+        votes.insert(new_voter(), socket2);
 
         // Should return socket1 (3 votes vs 1 vote, clear majority)
         assert_eq!(votes.majority().0, Some(socket1));
@@ -512,7 +548,7 @@ mod tests {
 
     #[test]
     fn test_competing_votes_within_margin() {
-        let mut votes = IpVote::new(2, Duration::from_secs(10));
+        let mut votes = IpVote::new(2, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
         let ip = "192.168.1.1".parse().unwrap();
         let socket1 = SocketAddrV4::new(ip, 8080);
         let socket2 = SocketAddrV4::new(ip, 8081);
@@ -520,10 +556,10 @@ mod tests {
         // 10 votes for socket1, 8 votes for socket2
         // 8 >= (10 * (1-CLEAR_MAJORITY_PERCENTAGE)) = 7, so within margin - should return None
         for _ in 0..10 {
-            votes.insert(NodeId::random(), socket1);
+            votes.insert(new_voter(), socket1);
         }
         for _ in 0..8 {
-            votes.insert(NodeId::random(), socket2);
+            votes.insert(new_voter(), socket2);
         }
 
         assert_eq!(votes.majority().0, None);
@@ -531,7 +567,7 @@ mod tests {
 
     #[test]
     fn test_clear_majority_outside_margin() {
-        let mut votes = IpVote::new(5, Duration::from_secs(10));
+        let mut votes = IpVote::new(5, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
         let ip = "192.168.1.1".parse().unwrap();
         let socket1 = SocketAddrV4::new(ip, 8080);
         let socket2 = SocketAddrV4::new(ip, 8081);
@@ -539,10 +575,10 @@ mod tests {
         // 10 votes for socket1, 4 votes for socket2
         // 4 < (10 * (1-CLEAR_MAJORITY_PERCENTAGE)) = 7, so outside margin - should return socket1 as clear winner
         for _ in 0..10 {
-            votes.insert(NodeId::random(), socket1);
+            votes.insert(new_voter(), socket1);
         }
         for _ in 0..4 {
-            votes.insert(NodeId::random(), socket2);
+            votes.insert(new_voter(), socket2);
         }
 
         assert_eq!(votes.majority().0, Some(socket1));
@@ -550,7 +586,7 @@ mod tests {
 
     #[test]
     fn test_three_way_competition() {
-        let mut votes = IpVote::new(2, Duration::from_secs(10));
+        let mut votes = IpVote::new(2, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
         let ip = "192.168.1.1".parse().unwrap();
         let socket1 = SocketAddrV4::new(ip, 8080);
         let socket2 = SocketAddrV4::new(ip, 8081);
@@ -558,12 +594,72 @@ mod tests {
 
         // 5 votes each - all within margin of each other
         for _ in 0..5 {
-            votes.insert(NodeId::random(), socket1);
-            votes.insert(NodeId::random(), socket2);
-            votes.insert(NodeId::random(), socket3);
+            votes.insert(new_voter(), socket1);
+            votes.insert(new_voter(), socket2);
+            votes.insert(new_voter(), socket3);
         }
 
         // Should return None due to competition
         assert_eq!(votes.majority().0, None);
     }
+
+    #[test]
+    fn test_single_host_sybil_cannot_flip_majority() {
+        let mut votes = IpVote::new(3, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
+        let ip = "192.168.1.1".parse().unwrap();
+        let honest_socket = SocketAddrV4::new(ip, 8080);
+        let sybil_socket = SocketAddrV4::new("6.6.6.6".parse().unwrap(), 6666);
+
+        for _ in 0..3 {
+            votes.insert(new_voter(), honest_socket);
+        }
+        assert_eq!(votes.majority().0, Some(honest_socket));
+
+        // Many node ids on a single host, and on neighbouring hosts of its subnet, share a vote.
+        let sybil_host: Ipv4Addr = "7.7.7.7".parse().unwrap();
+        for i in 0..100u8 {
+            votes.insert(sybil_host.into(), sybil_socket);
+            votes.insert(Ipv4Addr::new(7, 7, 7, i).into(), sybil_socket);
+        }
+        assert_eq!(votes.majority().0, Some(honest_socket));
+        assert_eq!(votes.has_minimum_threshold(), (true, false));
+    }
+
+    #[test]
+    fn test_sybil_alone_cannot_reach_threshold() {
+        let mut votes = IpVote::new(2, Duration::from_secs(10), IPV6_SUBNET_PREFIX);
+        let sybil_socket = SocketAddrV4::new("6.6.6.6".parse().unwrap(), 6666);
+        for i in 0..100u8 {
+            votes.insert(Ipv4Addr::new(7, 7, 7, i).into(), sybil_socket);
+        }
+        assert_eq!(votes.majority().0, None);
+        assert_eq!(votes.has_minimum_threshold(), (false, false));
+
+        // A second subnet reaches the threshold.
+        votes.insert(new_voter(), sybil_socket);
+        assert_eq!(votes.majority().0, Some(sybil_socket));
+    }
+
+    #[test]
+    fn test_ipv6_voters_share_configured_prefix() {
+        let mut votes = IpVote::new(2, Duration::from_secs(10), 48);
+        let socket: std::net::SocketAddrV6 = "[2001:db8::1]:9000".parse().unwrap();
+
+        let voter_1: IpAddr = "2001:db8:1:1::1".parse().unwrap();
+        let voter_2: IpAddr = "2001:db8:1:2::1".parse().unwrap();
+        votes.insert(voter_1, socket);
+        votes.insert(voter_2, socket);
+        assert_eq!(votes.majority().1, None);
+
+        let voter_3: IpAddr = "2001:db8:2::1".parse().unwrap();
+        votes.insert(voter_3, socket);
+        assert_eq!(votes.majority().1, Some(socket));
+
+        // IPv4-mapped addresses are counted as the IPv4 voter they represent.
+        let mut votes = IpVote::new(2, Duration::from_secs(10), 48);
+        let socket_4 = SocketAddrV4::new("1.2.3.4".parse().unwrap(), 9000);
+        votes.insert("10.0.0.1".parse().unwrap(), socket_4);
+        votes.insert("::ffff:10.0.0.2".parse().unwrap(), socket_4);
+        assert_eq!(votes.majority().0, None);
+    }
 }
@@ -0,0 +1,323 @@
+//! Throttles the requests we send to other nodes.
+//!
+//! Queries, pings of connected peers and application requests can all target the same peer at
+//! the same time. Nodes with strict rate limits may then ban us. The scheduler enforces a maximum
+//! number of requests in flight per peer and a global rate of sent requests.
+//!
+//! Requests that cannot be sent immediately are queued per peer. Queued requests are released
+//! round-robin across peers, so that a burst of requests to a single peer does not delay the
+//! requests to all other peers. The queue is bounded, and requests that wait in it longer than the
+//! queue timeout expire.
+
+use enr::NodeId;
+use futures::future::pending;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+use tokio::time::{sleep_until, Instant};
+
+/// The outcome of scheduling a request.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Scheduled<T> {
+    /// The request can be sent immediately.
+    Ready(T),
+    /// The request has been queued.
+    Queued,
+    /// The queue is full and the request has been rejected.
+    Rejected(T),
+}
+
+/// A queued request released by the scheduler.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Released<T> {
+    /// The request can be sent.
+    Ready(T),
+    /// The request waited longer than the queue timeout and must be failed.
+    Expired(T),
+}
+
+pub(crate) struct OutboundScheduler<T> {
+    /// The maximum number of requests in flight per peer. `None` disables the limit.
+    max_in_flight_per_peer: Option<usize>,
+    /// The number of requests that can be sent per second. `None` disables the limit.
+    requests_per_second: Option<u32>,
+    /// The time a request can wait in the queue before it expires.
+    queue_timeout: Duration,
+    /// The maximum number of queued requests.
+    max_queued: usize,
+    /// The number of tokens currently available to send requests.
+    tokens: f64,
+    /// The last time the tokens were replenished.
+    last_replenish: Instant,
+    /// The number of requests in flight per peer.
+    in_flight: HashMap<NodeId, usize>,
+    /// The requests waiting to be sent, per peer, with the time at which they expire.
+    queues: HashMap<NodeId, VecDeque<(Instant, T)>>,
+    /// The order in which peers with queued requests are served.
+    order: VecDeque<NodeId>,
+}
+
+impl<T> OutboundScheduler<T> {
+    pub fn new(
+        max_in_flight_per_peer: Option<usize>,
+        requests_per_second: Option<u32>,
+        queue_timeout: Duration,
+        max_queued: usize,
+    ) -> Self {
+        OutboundScheduler {
+            max_in_flight_per_peer,
+            requests_per_second,
+            queue_timeout,
+            max_queued,
+            tokens: requests_per_second.unwrap_or_default() as f64,
+            last_replenish: Instant::now(),
+            in_flight: HashMap::new(),
+            queues: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Schedules a request to the given peer. The request is returned if it can be sent
+    /// immediately, otherwise it is queued until [`OutboundScheduler::next_request`] releases it.
+    /// The request is rejected if the queue is full.
+    pub fn schedule(&mut self, node_id: NodeId, request: T) -> Scheduled<T> {
+        if self.order.is_empty() && self.has_capacity(&node_id) && self.take_token() {
+            return Scheduled::Ready(request);
+        }
+        if self.queued() >= self.max_queued {
+            return Scheduled::Rejected(request);
+        }
+
+        let expires_at = Instant::now() + self.queue_timeout;
+        let queue = self.queues.entry(node_id).or_default();
+        if queue.is_empty() {
+            self.order.push_back(node_id);
+        }
+        queue.push_back((expires_at, request));
+        Scheduled::Queued
+    }
+
+    /// Registers a request that has been sent to the given peer.
+    pub fn request_sent(&mut self, node_id: NodeId) {
+        *self.in_flight.entry(node_id).or_default() += 1;
+    }
+
+    /// Registers a request to the given peer that has been answered or has failed.
+    pub fn request_completed(&mut self, node_id: &NodeId) {
+        if let Some(in_flight) = self.in_flight.get_mut(node_id) {
+            *in_flight = in_flight.saturating_sub(1);
+            if *in_flight == 0 {
+                self.in_flight.remove(node_id);
+            }
+        }
+    }
+
+    /// The number of requests waiting to be sent.
+    pub fn queued(&self) -> usize {
+        self.queues.values().map(VecDeque::len).sum()
+    }
+
+    /// Waits for the next queued request that can be sent, or that has expired.
+    ///
+    /// The returned future does not observe requests completing while it is pending. It must
+    /// therefore be re-created whenever the state of the scheduler changes, as is the case when
+    /// polled in a `select!` loop.
+    pub async fn next_request(&mut self) -> (NodeId, Released<T>) {
+        loop {
+            let now = Instant::now();
+            if let Some(position) = self.order.iter().position(|node_id| {
+                self.queues[node_id]
+                    .front()
+                    .is_some_and(|(expires_at, _)| *expires_at <= now)
+            }) {
+                let (node_id, request) = self.pop(position);
+                return (node_id, Released::Expired(request));
+            }
+
+            let next_expiry = self.next_expiry();
+            let Some(position) = self
+                .order
+                .iter()
+                .position(|node_id| self.has_capacity(node_id))
+            else {
+                match next_expiry {
+                    Some(expires_at) => {
+                        sleep_until(expires_at).await;
+                        continue;
+                    }
+                    None => return pending().await,
+                }
+            };
+
+            if !self.take_token() {
+                let next_token_at = self.next_token_at();
+                sleep_until(next_expiry.map_or(next_token_at, |at| at.min(next_token_at))).await;
+                continue;
+            }
+
+            let (node_id, request) = self.pop(position);
+            return (node_id, Released::Ready(request));
+        }
+    }
+
+    /// Removes the first queued request of the peer at the given position of the order.
+    fn pop(&mut self, position: usize) -> (NodeId, T) {
+        let node_id = self
+            .order
+            .remove(position)
+            .expect("The position is within bounds");
+        let queue = self
+            .queues
+            .get_mut(&node_id)
+            .expect("Peers in the order have a queue");
+        let (_, request) = queue.pop_front().expect("Queues are never empty");
+        if queue.is_empty() {
+            self.queues.remove(&node_id);
+        } else {
+            // Serve the other peers before the next request to this peer.
+            self.order.push_back(node_id);
+        }
+        (node_id, request)
+    }
+
+    /// The time at which the first queued request expires. Requests are queued with the same
+    /// timeout, so the first request of each peer expires first.
+    fn next_expiry(&self) -> Option<Instant> {
+        self.queues
+            .values()
+            .filter_map(|queue| queue.front().map(|(expires_at, _)| *expires_at))
+            .min()
+    }
+
+    /// Whether another request can be sent to the given peer.
+    fn has_capacity(&self, node_id: &NodeId) -> bool {
+        match self.max_in_flight_per_peer {
+            Some(max) => self.in_flight.get(node_id).copied().unwrap_or_default() < max,
+            None => true,
+        }
+    }
+
+    /// Consumes a token to send a request, if one is available.
+    fn take_token(&mut self) -> bool {
+        let Some(requests_per_second) = self.requests_per_second else {
+            return true;
+        };
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_replenish).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * requests_per_second as f64).min(requests_per_second as f64);
+        self.last_replenish = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The time at which the next token becomes available.
+    fn next_token_at(&self) -> Instant {
+        let requests_per_second = self.requests_per_second.unwrap_or(1).max(1) as f64;
+        let missing = (1.0 - self.tokens).max(0.0);
+        self.last_replenish + Duration::from_secs_f64(missing / requests_per_second)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    const QUEUE_TIMEOUT: Duration = Duration::from_secs(10);
+    const MAX_QUEUED: usize = 100;
+
+    #[tokio::test]
+    async fn test_in_flight_limit_per_peer() {
+        let mut scheduler = OutboundScheduler::new(Some(1), None, QUEUE_TIMEOUT, MAX_QUEUED);
+        let (peer_a, peer_b) = (NodeId::random(), NodeId::random());
+
+        assert_eq!(scheduler.schedule(peer_a, 1), Scheduled::Ready(1));
+        scheduler.request_sent(peer_a);
+
+        // Further requests to the busy peer are queued, and so are requests to other peers to
+        // keep the ordering fair.
+        assert_eq!(scheduler.schedule(peer_a, 2), Scheduled::Queued);
+        assert_eq!(scheduler.schedule(peer_a, 3), Scheduled::Queued);
+        assert_eq!(scheduler.schedule(peer_b, 4), Scheduled::Queued);
+        assert_eq!(scheduler.queued(), 3);
+
+        // Only the idle peer can be served.
+        assert_eq!(
+            scheduler.next_request().now_or_never(),
+            Some((peer_b, Released::Ready(4)))
+        );
+        scheduler.request_sent(peer_b);
+        assert!(scheduler.next_request().now_or_never().is_none());
+
+        scheduler.request_completed(&peer_a);
+        assert_eq!(
+            scheduler.next_request().now_or_never(),
+            Some((peer_a, Released::Ready(2)))
+        );
+        scheduler.request_sent(peer_a);
+        assert!(scheduler.next_request().now_or_never().is_none());
+        assert_eq!(scheduler.queued(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_queued_requests_are_served_round_robin() {
+        let mut scheduler = OutboundScheduler::new(None, Some(20), QUEUE_TIMEOUT, MAX_QUEUED);
+        let (peer_a, peer_b) = (NodeId::random(), NodeId::random());
+
+        // Use up the initial burst of tokens.
+        for request in 0..20 {
+            assert_eq!(
+                scheduler.schedule(peer_a, request),
+                Scheduled::Ready(request)
+            );
+        }
+        assert_eq!(scheduler.schedule(peer_a, 20), Scheduled::Queued);
+        assert_eq!(scheduler.schedule(peer_a, 21), Scheduled::Queued);
+        assert_eq!(scheduler.schedule(peer_b, 22), Scheduled::Queued);
+
+        // Requests are released at the configured rate, alternating between peers.
+        let start = Instant::now();
+        assert_eq!(
+            scheduler.next_request().await,
+            (peer_a, Released::Ready(20))
+        );
+        assert_eq!(
+            scheduler.next_request().await,
+            (peer_b, Released::Ready(22))
+        );
+        assert_eq!(
+            scheduler.next_request().await,
+            (peer_a, Released::Ready(21))
+        );
+        assert!(start.elapsed() >= Duration::from_millis(140));
+    }
+
+    #[tokio::test]
+    async fn test_queued_requests_expire() {
+        let mut scheduler = OutboundScheduler::new(Some(1), None, Duration::from_millis(50), 2);
+        let peer = NodeId::random();
+
+        assert_eq!(scheduler.schedule(peer, 1), Scheduled::Ready(1));
+        scheduler.request_sent(peer);
+        assert_eq!(scheduler.schedule(peer, 2), Scheduled::Queued);
+        assert_eq!(scheduler.schedule(peer, 3), Scheduled::Queued);
+        // The queue is full.
+        assert_eq!(scheduler.schedule(peer, 4), Scheduled::Rejected(4));
+
+        // The peer never has capacity, so the queued requests expire in order.
+        let start = Instant::now();
+        assert_eq!(scheduler.next_request().await, (peer, Released::Expired(2)));
+        assert_eq!(scheduler.next_request().await, (peer, Released::Expired(3)));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(scheduler.queued(), 0);
+        assert!(scheduler.next_request().now_or_never().is_none());
+    }
+}
@@ -1,10 +1,15 @@
-use crate::{kbucket::Key, rpc::RequestBody, Enr};
+use crate::{
+    distance_strategy::{DistanceStrategy, MAX_DISTANCES_PER_REQUEST},
+    kbucket::Key,
+    rpc::RequestBody,
+    Enr,
+};
 use enr::{k256::sha2::digest::generic_array::GenericArray, NodeId};
 use smallvec::SmallVec;
+use std::{collections::HashMap, sync::Arc};
 use tokio::sync::oneshot;
 
 /// Information about a query.
-#[derive(Debug)]
 pub struct QueryInfo {
     /// What we are querying and why.
     pub query_type: QueryType,
@@ -12,12 +17,45 @@ pub struct QueryInfo {
     /// Temporary ENRs used when trying to reach nodes.
     pub untrusted_enrs: SmallVec<[Enr; 16]>,
 
+    /// The peers that reported each ENR found by the query.
+    pub reporters: HashMap<NodeId, Vec<NodeId>>,
+
     /// A callback channel for the service that requested the query.
-    pub callback: oneshot::Sender<Vec<Enr>>,
+    pub callback: QueryCallback,
+
+    /// Selects the distances we request from each peer.
+    pub distance_strategy: Arc<dyn DistanceStrategy>,
+}
+
+impl std::fmt::Debug for QueryInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueryInfo")
+            .field("query_type", &self.query_type)
+            .field("untrusted_enrs", &self.untrusted_enrs)
+            .field("callback", &self.callback)
+            .finish_non_exhaustive()
+    }
+}
 
-    /// The number of distances we request for each peer.
-    /// NOTE: This must not be larger than 127.
-    pub distances_to_request: usize,
+/// The channel on which the results of a query are returned.
+#[derive(Debug)]
+pub enum QueryCallback {
+    /// Returns the ENRs found.
+    Enrs(oneshot::Sender<Vec<Enr>>),
+    /// Returns the ENRs found along with the peers that reported each of them.
+    Reported(oneshot::Sender<Vec<(Enr, Vec<NodeId>)>>),
+}
+
+impl QueryCallback {
+    /// Sends the results of the query. Returns `false` if the receiver was dropped.
+    pub fn send(self, results: Vec<(Enr, Vec<NodeId>)>) -> bool {
+        match self {
+            QueryCallback::Enrs(callback) => callback
+                .send(results.into_iter().map(|(enr, _)| enr).collect())
+                .is_ok(),
+            QueryCallback::Reported(callback) => callback.send(results).is_ok(),
+        }
+    }
 }
 
 /// Additional information about the query.
@@ -32,12 +70,31 @@ impl QueryInfo {
     pub(crate) fn rpc_request(&self, peer: NodeId) -> RequestBody {
         match self.query_type {
             QueryType::FindNode(node_id) => {
-                let distances = findnode_log2distance(node_id, peer, self.distances_to_request)
-                    .unwrap_or_else(|| vec![0]);
+                let distances = if node_id == peer {
+                    vec![0]
+                } else {
+                    self.request_distances(&node_id, &peer)
+                };
                 RequestBody::FindNode { distances }
             }
         }
     }
+
+    /// Consults the distance strategy, discarding invalid and duplicate distances. Falls back to
+    /// the exact distance of the target from the peer if no valid distance remains.
+    fn request_distances(&self, target: &NodeId, peer: &NodeId) -> Vec<u64> {
+        let mut distances = self.distance_strategy.distances(target, peer);
+        distances.retain(|distance| (1..=256).contains(distance));
+        let mut seen = std::collections::HashSet::new();
+        distances.retain(|distance| seen.insert(*distance));
+        distances.truncate(MAX_DISTANCES_PER_REQUEST);
+
+        if distances.is_empty() {
+            let peer_key: Key<NodeId> = (*peer).into();
+            distances.extend(peer_key.log2_distance(&(*target).into()));
+        }
+        distances
+    }
 }
 
 impl crate::query_pool::TargetKey<NodeId> for QueryInfo {
@@ -50,83 +107,60 @@ impl crate::query_pool::TargetKey<NodeId> for QueryInfo {
     }
 }
 
-/// Calculates the log2 distance for a destination peer given a target and the size (number of
-/// distances to request).
-///
-/// As the iteration increases, FINDNODE requests adjacent distances from the exact peer distance.
-///
-/// As an example, if the target has a distance of 12 from the remote peer, the sequence of distances that are sent for increasing iterations would be [12, 13, 11, 14, 10, .. ].
-fn findnode_log2distance(target: NodeId, peer: NodeId, size: usize) -> Option<Vec<u64>> {
-    if size > 127 {
-        // invoke and endless loop - coding error
-        panic!("Iterations cannot be greater than 127");
-    }
-
-    let dst_key: Key<NodeId> = peer.into();
-    let distance = dst_key.log2_distance(&target.into())?;
-
-    let mut result_list = vec![distance];
-    let mut difference = 1;
-    while result_list.len() < size {
-        if distance + difference <= 256 {
-            result_list.push(distance + difference);
-        }
-        if result_list.len() < size {
-            if let Some(d) = distance.checked_sub(difference) {
-                result_list.push(d);
-            }
-        }
-        difference += 1;
-    }
-    Some(result_list[..size].to_vec())
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::distance_strategy::NeighbourDistances;
 
-    #[test]
-    fn test_log2distance() {
-        let target = NodeId::new(&[0u8; 32]);
-        let mut destination = [0u8; 32];
-        destination[10] = 1; // gives a log2 distance of 169
-        let destination = NodeId::new(&destination);
+    struct FixedDistances(Vec<u64>);
 
-        let expected_distances = vec![169, 170, 168, 171, 167, 172, 166, 173, 165];
+    impl DistanceStrategy for FixedDistances {
+        fn distances(&self, _target: &NodeId, _peer: &NodeId) -> Vec<u64> {
+            self.0.clone()
+        }
+    }
 
-        assert_eq!(
-            findnode_log2distance(target, destination, expected_distances.len()).unwrap(),
-            expected_distances
-        );
+    fn query_info(strategy: impl DistanceStrategy + 'static) -> QueryInfo {
+        QueryInfo {
+            query_type: QueryType::FindNode(NodeId::new(&[0u8; 32])),
+            untrusted_enrs: Default::default(),
+            reporters: Default::default(),
+            callback: QueryCallback::Enrs(oneshot::channel().0),
+            distance_strategy: Arc::new(strategy),
+        }
+    }
+
+    fn requested_distances(query: &QueryInfo, peer: NodeId) -> Vec<u64> {
+        match query.rpc_request(peer) {
+            RequestBody::FindNode { distances } => distances,
+            _ => unreachable!(),
+        }
     }
 
     #[test]
-    fn test_log2distance_lower() {
-        let target = NodeId::new(&[0u8; 32]);
-        let mut destination = [0u8; 32];
-        destination[31] = 8; // gives a log2 distance of 5
-        let destination = NodeId::new(&destination);
+    fn test_strategy_distances_are_requested() {
+        let mut peer = [0u8; 32];
+        peer[10] = 1; // gives a log2 distance of 169
+        let peer = NodeId::new(&peer);
 
-        let expected_distances = vec![4, 5, 3, 6, 2, 7, 1, 8, 0, 9, 10];
+        let query = query_info(NeighbourDistances::default());
+        assert_eq!(requested_distances(&query, peer), vec![169, 170, 168]);
 
-        assert_eq!(
-            findnode_log2distance(target, destination, expected_distances.len()).unwrap(),
-            expected_distances
-        );
-    }
+        let query = query_info(NeighbourDistances::new(1));
+        assert_eq!(requested_distances(&query, peer), vec![169]);
 
-    #[test]
-    fn test_log2distance_upper() {
-        let target = NodeId::new(&[0u8; 32]);
-        let mut destination = [0u8; 32];
-        destination[0] = 8; // gives a log2 distance of 252
-        let destination = NodeId::new(&destination);
+        // Invalid and duplicate distances are discarded
+        let query = query_info(FixedDistances(vec![0, 100, 257, 100, 50]));
+        assert_eq!(requested_distances(&query, peer), vec![100, 50]);
 
-        let expected_distances = vec![252, 253, 251, 254, 250, 255, 249, 256, 248, 247, 246];
+        // Without valid distances, the exact distance is requested
+        let query = query_info(FixedDistances(vec![0, 300]));
+        assert_eq!(requested_distances(&query, peer), vec![169]);
 
+        // The target itself is asked for its own record
         assert_eq!(
-            findnode_log2distance(target, destination, expected_distances.len()).unwrap(),
-            expected_distances
+            requested_distances(&query, NodeId::new(&[0u8; 32])),
+            vec![0]
         );
     }
 }
@@ -0,0 +1,205 @@
+//! Keeps the routing table populated by periodically running lookups towards under-filled
+//! buckets.
+//!
+//! Every refresh interval, the buckets between the closest populated bucket and the furthest
+//! bucket (log2-distance 256) are inspected. Buckets that hold fewer than
+//! `MAX_NODES_PER_BUCKET` entries are considered under-filled and are refreshed in a round-robin
+//! fashion by running a `FIND_NODE` query towards a random target at the bucket's log2-distance.
+//!
+//! If no bucket requires a refresh, the table is considered healthy and the interval between
+//! refreshes is doubled, up to `MAX_REFRESH_BACKOFF_FACTOR` times the configured interval. As soon
+//! as an under-filled bucket is found, the interval is reset to the configured value.
+
+use crate::{kbucket::MAX_NODES_PER_BUCKET, Enr};
+use futures::future::{pending, select, Either};
+use std::{pin::Pin, time::Duration};
+use tokio::{
+    sync::oneshot,
+    time::{sleep, Sleep},
+};
+
+/// The maximum factor by which the refresh interval is multiplied when the table is healthy.
+const MAX_REFRESH_BACKOFF_FACTOR: u32 = 8;
+
+/// The events produced by polling the [`TableRefresh`].
+pub(crate) enum RefreshEvent {
+    /// The refresh timer has fired and a new refresh should be attempted.
+    Ready,
+    /// A refresh lookup has completed.
+    Completed {
+        /// The log2-distance of the bucket that was refreshed.
+        distance: u64,
+        /// The ENRs returned by the lookup.
+        found: Vec<Enr>,
+    },
+}
+
+pub(crate) struct TableRefresh {
+    /// The configured interval between refreshes. If this is `None`, refreshes are disabled.
+    interval: Option<Duration>,
+    /// The current interval between refreshes, including any backoff.
+    current_interval: Duration,
+    /// The timer until the next refresh.
+    next_refresh: Option<Pin<Box<Sleep>>>,
+    /// The distance of the last bucket that was refreshed.
+    last_distance: Option<u64>,
+    /// The refresh lookup currently in progress, if any.
+    in_progress: Option<(u64, oneshot::Receiver<Vec<Enr>>)>,
+}
+
+impl TableRefresh {
+    pub fn new(interval: Option<Duration>) -> Self {
+        TableRefresh {
+            interval,
+            current_interval: interval.unwrap_or_default(),
+            next_refresh: interval.map(|interval| Box::pin(sleep(interval))),
+            last_distance: None,
+            in_progress: None,
+        }
+    }
+
+    /// Selects the log2-distance of the next bucket to refresh, given the number of entries in
+    /// each bucket ordered by increasing distance. The refresh timer is re-armed, backing off if
+    /// no bucket requires a refresh.
+    ///
+    /// Returns `None` if a refresh is already in progress or the table is healthy.
+    pub fn next_distance(&mut self, bucket_sizes: &[usize]) -> Option<u64> {
+        let interval = self.interval?;
+
+        let distance = if self.in_progress.is_some() {
+            None
+        } else {
+            self.select_distance(bucket_sizes)
+        };
+
+        match distance {
+            Some(distance) => {
+                self.current_interval = interval;
+                self.last_distance = Some(distance);
+            }
+            None if self.in_progress.is_none() => {
+                self.current_interval =
+                    (self.current_interval * 2).min(interval * MAX_REFRESH_BACKOFF_FACTOR);
+            }
+            None => {}
+        }
+        self.next_refresh = Some(Box::pin(sleep(self.current_interval)));
+        distance
+    }
+
+    /// Registers a refresh lookup that has been started towards the given distance.
+    pub fn lookup_started(&mut self, distance: u64, result: oneshot::Receiver<Vec<Enr>>) {
+        self.in_progress = Some((distance, result));
+    }
+
+    /// Finds the next under-filled bucket to refresh. Buckets are visited from the furthest to the
+    /// closest, continuing from the last refreshed bucket.
+    fn select_distance(&self, bucket_sizes: &[usize]) -> Option<u64> {
+        // If the table is empty, search the furthest bucket as it covers half of the key space.
+        let Some(closest_index) = bucket_sizes.iter().position(|size| *size > 0) else {
+            return Some(bucket_sizes.len() as u64);
+        };
+
+        let candidates = (closest_index..bucket_sizes.len())
+            .rev()
+            .filter(|index| bucket_sizes[*index] < MAX_NODES_PER_BUCKET)
+            .map(|index| index as u64 + 1)
+            .collect::<Vec<_>>();
+
+        match self.last_distance {
+            Some(last) => candidates
+                .iter()
+                .find(|distance| **distance < last)
+                .or_else(|| candidates.first())
+                .copied(),
+            None => candidates.first().copied(),
+        }
+    }
+
+    pub async fn poll(&mut self) -> RefreshEvent {
+        let Some(timer) = self.next_refresh.as_mut() else {
+            return pending().await;
+        };
+
+        let completed = match self.in_progress.as_mut() {
+            Some((_, result)) => match select(timer.as_mut(), result).await {
+                Either::Left(_) => None,
+                Either::Right((found, _)) => Some(found.unwrap_or_default()),
+            },
+            None => {
+                timer.await;
+                None
+            }
+        };
+
+        match completed {
+            Some(found) => {
+                let (distance, _) = self
+                    .in_progress
+                    .take()
+                    .expect("A refresh lookup is in progress");
+                RefreshEvent::Completed { distance, found }
+            }
+            None => {
+                // Prevent the elapsed timer from firing again until it is re-armed.
+                self.next_refresh = None;
+                RefreshEvent::Ready
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bucket_sizes(populated: &[(u64, usize)]) -> Vec<usize> {
+        let mut sizes = vec![0; 256];
+        for (distance, size) in populated {
+            sizes[*distance as usize - 1] = *size;
+        }
+        sizes
+    }
+
+    #[tokio::test]
+    async fn test_empty_table_refreshes_furthest_bucket() {
+        let mut refresh = TableRefresh::new(Some(Duration::from_secs(10)));
+        assert_eq!(refresh.next_distance(&bucket_sizes(&[])), Some(256));
+    }
+
+    #[tokio::test]
+    async fn test_under_filled_buckets_are_refreshed_round_robin() {
+        let mut refresh = TableRefresh::new(Some(Duration::from_secs(10)));
+        let sizes = bucket_sizes(&[(256, 16), (255, 10), (254, 16), (253, 3)]);
+
+        assert_eq!(refresh.next_distance(&sizes), Some(255));
+        assert_eq!(refresh.next_distance(&sizes), Some(253));
+        assert_eq!(refresh.next_distance(&sizes), Some(255));
+    }
+
+    #[tokio::test]
+    async fn test_healthy_table_backs_off() {
+        let interval = Duration::from_secs(10);
+        let mut refresh = TableRefresh::new(Some(interval));
+        let healthy = bucket_sizes(&[(256, 16), (255, 16)]);
+
+        for _ in 0..10 {
+            assert_eq!(refresh.next_distance(&healthy), None);
+        }
+        assert_eq!(
+            refresh.current_interval,
+            interval * MAX_REFRESH_BACKOFF_FACTOR
+        );
+
+        // An under-filled bucket resets the interval.
+        let unhealthy = bucket_sizes(&[(256, 16), (255, 4)]);
+        assert_eq!(refresh.next_distance(&unhealthy), Some(255));
+        assert_eq!(refresh.current_interval, interval);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_refresh() {
+        let mut refresh = TableRefresh::new(None);
+        assert_eq!(refresh.next_distance(&bucket_sizes(&[])), None);
+    }
+}
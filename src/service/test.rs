@@ -12,13 +12,13 @@ use crate::{
     rpc::RequestId,
     service::{ActiveRequest, Service},
     socket::ListenConfig,
-    ConfigBuilder, Enr,
+    ConfigBuilder, Enr, ExternalAddressMode, Signer, Transport,
 };
 use enr::CombinedKey;
 use parking_lot::RwLock;
 use rand;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     net::{Ipv4Addr, Ipv6Addr},
     sync::Arc,
     time::Duration,
@@ -54,7 +54,7 @@ fn init() {
 
 async fn build_service(
     local_enr: Arc<RwLock<Enr>>,
-    enr_key: Arc<RwLock<CombinedKey>>,
+    enr_key: Arc<RwLock<Arc<dyn Signer>>>,
     filters: bool,
 ) -> Service {
     let listen_config = ListenConfig::Ipv4 {
@@ -65,10 +65,14 @@ async fn build_service(
         .executor(Box::<crate::executor::TokioExecutor>::default())
         .build();
     // build the session service
-    let (_handler_exit, handler_send, handler_recv) =
-        Handler::spawn(local_enr.clone(), enr_key.clone(), config.clone())
-            .await
-            .unwrap();
+    let (_handler_exit, handler_send, handler_recv) = Handler::spawn(
+        local_enr.clone(),
+        enr_key.clone(),
+        Arc::new(RwLock::new(RttTable::new(100))),
+        config.clone(),
+    )
+    .await
+    .unwrap();
 
     let (table_filter, bucket_filter) = if filters {
         (
@@ -91,11 +95,18 @@ async fn build_service(
     let (_discv5_send, discv5_recv) = mpsc::channel(30);
     let (_exit_send, exit) = oneshot::channel();
 
-    let connectivity_state = ConnectivityState::new(config.auto_nat_listen_duration);
+    let connectivity_state = ConnectivityState::new(
+        config.auto_nat_listen_duration,
+        config.ipv4_external_address,
+        config.ipv6_external_address,
+    );
+
+    let bootnode_check = tokio::time::interval(config.bootnode_reseed_interval);
 
     Service {
         local_enr,
         enr_key,
+        enr_writer: None,
         kbuckets,
         queries: QueryPool::new(config.query_timeout),
         active_requests: Default::default(),
@@ -111,12 +122,22 @@ async fn build_service(
         config,
         ip_mode: Default::default(),
         connectivity_state,
+        session_enrs: LruTimeCache::new(Duration::from_secs(60), None),
+        dial_backs_in_progress: Arc::new(AtomicUsize::new(0)),
+        table_refresh: TableRefresh::new(None),
+        enr_propagation: EnrPropagation::new(None, Duration::ZERO, Duration::ZERO),
+        bootnode_check,
+        outbound: OutboundScheduler::new(None, None, Duration::from_secs(10), 1024),
+        amplification: AmplificationLimit::new(None, Duration::from_secs(60), 100),
+        peer_stats: Arc::new(RwLock::new(PeerStatsStore::new(100))),
+        rtts: Arc::new(RwLock::new(RttTable::new(100))),
+        key_rotations: VecDeque::new(),
     }
 }
 
 fn build_non_handler_service(
     local_enr: Arc<RwLock<Enr>>,
-    enr_key: Arc<RwLock<CombinedKey>>,
+    enr_key: Arc<RwLock<Arc<dyn Signer>>>,
     filters: bool,
 ) -> (Service, UnboundedReceiver<HandlerIn>, Sender<HandlerOut>) {
     let listen_config = ListenConfig::Ipv4 {
@@ -146,17 +167,28 @@ fn build_non_handler_service(
         bucket_filter,
     )));
 
-    let ip_vote = IpVote::new(10, Duration::from_secs(10000));
+    let ip_vote = IpVote::new(
+        10,
+        Duration::from_secs(10000),
+        config.vote_ipv6_subnet_prefix,
+    );
 
     // create the required channels.
     let (_discv5_send, discv5_recv) = mpsc::channel(30);
     let (_exit_send, exit) = oneshot::channel();
 
-    let connectivity_state = ConnectivityState::new(config.auto_nat_listen_duration);
+    let connectivity_state = ConnectivityState::new(
+        config.auto_nat_listen_duration,
+        config.ipv4_external_address,
+        config.ipv6_external_address,
+    );
+
+    let bootnode_check = tokio::time::interval(config.bootnode_reseed_interval);
 
     let service = Service {
         local_enr,
         enr_key,
+        enr_writer: None,
         kbuckets,
         queries: QueryPool::new(config.query_timeout),
         active_requests: Default::default(),
@@ -172,6 +204,16 @@ fn build_non_handler_service(
         config,
         ip_mode: IpMode::DualStack,
         connectivity_state,
+        session_enrs: LruTimeCache::new(Duration::from_secs(60), None),
+        dial_backs_in_progress: Arc::new(AtomicUsize::new(0)),
+        table_refresh: TableRefresh::new(None),
+        enr_propagation: EnrPropagation::new(None, Duration::ZERO, Duration::ZERO),
+        bootnode_check,
+        outbound: OutboundScheduler::new(None, None, Duration::from_secs(10), 1024),
+        amplification: AmplificationLimit::new(None, Duration::from_secs(60), 100),
+        peer_stats: Arc::new(RwLock::new(PeerStatsStore::new(100))),
+        rtts: Arc::new(RwLock::new(RttTable::new(100))),
+        key_rotations: VecDeque::new(),
     };
     (service, handler_recv_fake, handler_send_fake)
 }
@@ -196,7 +238,7 @@ async fn test_updating_connection_on_ping() {
 
     let mut service = build_service(
         Arc::new(RwLock::new(enr)),
-        Arc::new(RwLock::new(enr_key1)),
+        Arc::new(RwLock::new(Arc::new(enr_key1))),
         false,
     )
     .await;
@@ -265,7 +307,7 @@ async fn test_connection_direction_on_inject_session_established() {
 
     let mut service = build_service(
         Arc::new(RwLock::new(enr)),
-        Arc::new(RwLock::new(enr_key1)),
+        Arc::new(RwLock::new(Arc::new(enr_key1))),
         false,
     )
     .await;
@@ -315,7 +357,7 @@ async fn test_handling_concurrent_responses() {
             .unwrap();
         build_service(
             Arc::new(RwLock::new(enr)),
-            Arc::new(RwLock::new(enr_key)),
+            Arc::new(RwLock::new(Arc::new(enr_key))),
             false,
         )
         .await
@@ -417,6 +459,176 @@ async fn test_handling_concurrent_responses() {
     assert!(service.active_nodes_responses.is_empty());
 }
 
+#[tokio::test]
+async fn test_nodes_responses_are_capped_by_requested_distances() {
+    init();
+
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = Enr::builder()
+        .ip4(Ipv4Addr::LOCALHOST)
+        .udp4(DEFAULT_UDP_PORT)
+        .build(&enr_key)
+        .unwrap();
+    let mut service = build_service(
+        Arc::new(RwLock::new(enr)),
+        Arc::new(RwLock::new(Arc::new(enr_key))),
+        false,
+    )
+    .await;
+
+    let node_contact: NodeContact = Enr::builder()
+        .ip4(Ipv4Addr::LOCALHOST)
+        .udp4(10006)
+        .build(&CombinedKey::generate_secp256k1())
+        .unwrap()
+        .into();
+    let node_address = node_contact.node_address();
+
+    // A single distance is requested, so at most `max_nodes_responses(1)` responses are accepted,
+    // even though the peer announces more.
+    service.active_requests.insert(
+        RequestId(vec![1]),
+        ActiveRequest {
+            contact: node_contact,
+            request_body: RequestBody::FindNode {
+                distances: vec![256],
+            },
+            query_id: Some(QueryId(1)),
+            callback: None,
+        },
+    );
+
+    let max_responses = max_nodes_responses(1);
+    for _ in 1..max_responses {
+        service.handle_rpc_response(
+            node_address.clone(),
+            Response {
+                id: RequestId(vec![1]),
+                body: ResponseBody::Nodes {
+                    total: 10 * max_responses as u64,
+                    nodes: vec![],
+                },
+            },
+        );
+        assert_eq!(1, service.active_requests.len());
+    }
+
+    service.handle_rpc_response(
+        node_address,
+        Response {
+            id: RequestId(vec![1]),
+            body: ResponseBody::Nodes {
+                total: 10 * max_responses as u64,
+                nodes: vec![],
+            },
+        },
+    );
+    assert!(service.active_requests.is_empty());
+    assert!(service.active_nodes_responses.is_empty());
+}
+
+#[tokio::test]
+async fn test_queued_requests_fail_when_expired_or_rejected() {
+    init();
+
+    let enr_key = CombinedKey::generate_secp256k1();
+    let local_enr = Enr::builder()
+        .ip4(Ipv4Addr::LOCALHOST)
+        .udp4(DEFAULT_UDP_PORT)
+        .build(&enr_key)
+        .unwrap();
+    let (mut service, _handler_recv, _handler_send) = build_non_handler_service(
+        Arc::new(RwLock::new(local_enr)),
+        Arc::new(RwLock::new(Arc::new(enr_key))),
+        false,
+    );
+    // A single request in flight per peer, and a single queued request.
+    service.outbound = OutboundScheduler::new(Some(1), None, Duration::from_millis(50), 1);
+
+    let contact: NodeContact = Enr::builder()
+        .ip4(Ipv4Addr::LOCALHOST)
+        .udp4(DEFAULT_UDP_PORT + 1)
+        .build(&CombinedKey::generate_secp256k1())
+        .unwrap()
+        .into();
+    let talk = |service: &mut Service| {
+        let (callback, response) = oneshot::channel();
+        service.talk_request(contact.clone(), b"test".to_vec(), Vec::new(), callback);
+        response
+    };
+
+    let _sent = talk(&mut service);
+    let mut queued = talk(&mut service);
+    let rejected = talk(&mut service);
+    assert_eq!(rejected.await, Ok(Err(RequestError::OutboundQueueFull)));
+    assert!(queued.try_recv().is_err());
+
+    // The peer stays busy, so the queued request expires and fails with a timeout.
+    let (_, released) = service.outbound.next_request().await;
+    let Released::Expired(active_request) = released else {
+        panic!("The queued request must expire");
+    };
+    service.report_request_failure(None, active_request, RequestError::Timeout);
+    assert_eq!(queued.await, Ok(Err(RequestError::Timeout)));
+}
+
+#[tokio::test]
+async fn test_default_byte_budget_does_not_truncate_lookups() {
+    init();
+
+    let enr_key = CombinedKey::generate_secp256k1();
+    let local_enr = Enr::builder()
+        .ip4(Ipv4Addr::LOCALHOST)
+        .udp4(DEFAULT_UDP_PORT)
+        .build(&enr_key)
+        .unwrap();
+    let (mut service, mut handler_recv, _handler_send) = build_non_handler_service(
+        Arc::new(RwLock::new(local_enr)),
+        Arc::new(RwLock::new(Arc::new(enr_key))),
+        false,
+    );
+    service.amplification = AmplificationLimit::new(
+        service.config.unverified_response_byte_budget,
+        Duration::from_secs(60),
+        100,
+    );
+
+    // Fill the furthest buckets, so that lookups are answered with full responses.
+    for i in 0..64u8 {
+        let key = CombinedKey::generate_secp256k1();
+        let enr = Enr::builder()
+            .ip4(Ipv4Addr::new(10, 0, 0, i))
+            .udp4(9000)
+            .build(&key)
+            .unwrap();
+        let _ = service.kbuckets.write().insert_or_update(
+            &kbucket::Key::from(enr.node_id()),
+            enr,
+            disconnected_state(),
+        );
+    }
+
+    // A peer whose address has not answered one of our PINGs performs several lookups.
+    let node_address = NodeAddress {
+        socket_addr: "10.1.0.1:9000".parse().unwrap(),
+        node_id: NodeId::random(),
+    };
+    for lookup in 0..5u8 {
+        service.send_nodes_response(
+            node_address.clone(),
+            RequestId(vec![lookup]),
+            vec![254, 255, 256],
+        );
+        let mut nodes = 0;
+        while let Ok(HandlerIn::Response(_, response)) = handler_recv.try_recv() {
+            if let ResponseBody::Nodes { nodes: enrs, .. } = response.body {
+                nodes += enrs.len();
+            }
+        }
+        assert_eq!(nodes, service.config.max_nodes_response);
+    }
+}
+
 fn generate_rand_ipv4() -> Ipv4Addr {
     let a: u8 = rand::random();
     let b: u8 = rand::random();
@@ -460,7 +672,7 @@ async fn test_ipv6_update_amongst_ipv4_dominated_network() {
 
     let (mut service, mut handler_recv, _handler_send) = build_non_handler_service(
         Arc::new(RwLock::new(local_enr)),
-        Arc::new(RwLock::new(enr_key)),
+        Arc::new(RwLock::new(Arc::new(enr_key))),
         false,
     );
 
@@ -510,3 +722,345 @@ async fn test_ipv6_update_amongst_ipv4_dominated_network() {
     // Should be 10 ipv6 pings
     assert_eq!(v6_pings, 10)
 }
+
+#[tokio::test]
+async fn test_table_refresh_targets_under_filled_bucket() {
+    init();
+
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = Enr::builder()
+        .ip4(Ipv4Addr::LOCALHOST)
+        .udp4(DEFAULT_UDP_PORT)
+        .build(&enr_key)
+        .unwrap();
+    let local_key = kbucket::Key::from(enr.node_id());
+
+    let (mut service, mut handler_recv, _handler_send) = build_non_handler_service(
+        Arc::new(RwLock::new(enr)),
+        Arc::new(RwLock::new(Arc::new(enr_key))),
+        false,
+    );
+    service.table_refresh = TableRefresh::new(Some(Duration::from_secs(60)));
+
+    // Populate the table with a single peer.
+    let peer_key = CombinedKey::generate_secp256k1();
+    let peer_enr = Enr::builder()
+        .ip4(Ipv4Addr::LOCALHOST)
+        .udp4(10000)
+        .build(&peer_key)
+        .unwrap();
+    let _ = service.kbuckets.write().insert_or_update(
+        &kbucket::Key::from(peer_enr.node_id()),
+        peer_enr.clone(),
+        disconnected_state(),
+    );
+
+    service.refresh_table();
+
+    // A query must have been started towards the furthest under-filled bucket.
+    let query = service
+        .queries
+        .iter()
+        .next()
+        .expect("Refresh query started");
+    let QueryType::FindNode(target) = query.target().query_type;
+    assert_eq!(local_key.log2_distance(&target.into()), Some(256));
+
+    // Drive the query so that the peer is contacted.
+    let QueryEvent::Waiting(query_id, node_id, request_body) =
+        Service::query_event_poll(&mut service.queries, &service.rtts, &service.config).await
+    else {
+        panic!("Query should be waiting on the peer");
+    };
+    assert_eq!(node_id, peer_enr.node_id());
+    service.send_rpc_query(query_id, node_id, request_body);
+    assert!(matches!(
+        handler_recv.recv().await,
+        Some(HandlerIn::Request(..))
+    ));
+}
+
+#[tokio::test]
+async fn test_voted_socket_updates_configured_transports() {
+    init();
+
+    let enr_key = CombinedKey::generate_secp256k1();
+    let local_enr = Enr::builder()
+        .ip4(Ipv4Addr::LOCALHOST)
+        .udp4(DEFAULT_UDP_PORT)
+        .tcp4(DEFAULT_UDP_PORT + 1)
+        .build(&enr_key)
+        .unwrap();
+    let seq = local_enr.seq();
+
+    let (mut service, _handler_recv, _handler_send) = build_non_handler_service(
+        Arc::new(RwLock::new(local_enr)),
+        Arc::new(RwLock::new(Arc::new(enr_key))),
+        false,
+    );
+    service.config.enr_update_transports = vec![Transport::Tcp];
+
+    let voted: SocketAddr = "1.2.3.4:9100".parse().unwrap();
+    service.set_voted_socket(voted).unwrap();
+
+    let local_enr = service.local_enr.read().clone();
+    assert_eq!(local_enr.seq(), seq + 1);
+    assert_eq!(Transport::Udp.socket(&local_enr, false), Some(voted));
+    // The TCP port is kept at the voted IP address.
+    assert_eq!(
+        Transport::Tcp.socket(&local_enr, false),
+        Some(SocketAddr::new(voted.ip(), DEFAULT_UDP_PORT + 1))
+    );
+    // The QUIC port is not configured to follow the vote.
+    assert_eq!(Transport::Quic.socket(&local_enr, false), None);
+
+    // Voting for the advertised socket leaves the record unchanged.
+    service.set_voted_socket(voted).unwrap();
+    assert_eq!(service.local_enr.read().seq(), seq + 1);
+
+    // Transports sharing the UDP port follow the voted port.
+    service.config.enr_update_share_udp_port = true;
+    let voted: SocketAddr = "1.2.3.4:9200".parse().unwrap();
+    service.set_voted_socket(voted).unwrap();
+    let local_enr = service.local_enr.read().clone();
+    assert_eq!(Transport::Udp.socket(&local_enr, false), Some(voted));
+    assert_eq!(Transport::Tcp.socket(&local_enr, false), Some(voted));
+}
+
+#[tokio::test]
+async fn test_single_host_sybil_cannot_flip_advertised_socket() {
+    init();
+
+    let enr_key = CombinedKey::generate_secp256k1();
+    let local_enr = Enr::builder()
+        .ip4(Ipv4Addr::LOCALHOST)
+        .udp4(DEFAULT_UDP_PORT)
+        .build(&enr_key)
+        .unwrap();
+
+    let (mut service, _handler_recv, _handler_send) = build_non_handler_service(
+        Arc::new(RwLock::new(local_enr)),
+        Arc::new(RwLock::new(Arc::new(enr_key))),
+        false,
+    );
+
+    // Casts a vote from a new peer at the given IP.
+    let mut vote = |voter: Ipv4Addr, direction: ConnectionDirection, socket: SocketAddr| {
+        let key = CombinedKey::generate_secp256k1();
+        let enr = Enr::builder()
+            .ip4(voter)
+            .udp4(DEFAULT_UDP_PORT)
+            .build(&key)
+            .unwrap();
+        let node_id = enr.node_id();
+        service.session_enrs.insert(node_id, (enr, direction));
+        service.handle_ip_vote_from_pong(node_id, voter.into(), socket);
+        service.local_enr.read().udp4_socket().map(SocketAddr::V4)
+    };
+
+    // Honest peers of distinct subnets agree on our external socket.
+    let external: SocketAddr = "1.2.3.4:9000".parse().unwrap();
+    let mut socket = None;
+    for i in 0..10 {
+        socket = vote(
+            Ipv4Addr::new(10, i, 0, 1),
+            ConnectionDirection::Outgoing,
+            external,
+        );
+    }
+    assert_eq!(socket, Some(external));
+
+    // A single host with many node ids votes for another socket.
+    let sybil: SocketAddr = "6.6.6.6:6666".parse().unwrap();
+    for _ in 0..50 {
+        let socket = vote(
+            Ipv4Addr::new(7, 7, 7, 7),
+            ConnectionDirection::Outgoing,
+            sybil,
+        );
+        assert_eq!(socket, Some(external));
+    }
+
+    // Peers that initiated their sessions are not counted, whatever their subnets.
+    for i in 0..50 {
+        let socket = vote(
+            Ipv4Addr::new(20, i, 0, 1),
+            ConnectionDirection::Incoming,
+            sybil,
+        );
+        assert_eq!(socket, Some(external));
+    }
+}
+
+#[tokio::test]
+async fn test_pinned_ip_version_ignores_votes() {
+    init();
+
+    let enr_key = CombinedKey::generate_secp256k1();
+    let static_socket: SocketAddr = "1.2.3.4:9000".parse().unwrap();
+    let local_enr = Enr::builder()
+        .ip4(Ipv4Addr::new(1, 2, 3, 4))
+        .udp4(9000)
+        .build(&enr_key)
+        .unwrap();
+
+    let (mut service, _handler_recv, _handler_send) = build_non_handler_service(
+        Arc::new(RwLock::new(local_enr)),
+        Arc::new(RwLock::new(Arc::new(enr_key))),
+        false,
+    );
+    let nat_listen_duration = Duration::from_millis(100);
+    service.connectivity_state = ConnectivityState::new(
+        Some(nat_listen_duration),
+        ExternalAddressMode::Static(static_socket),
+        ExternalAddressMode::Auto,
+    );
+
+    let mut vote = |voter: IpAddr, socket: SocketAddr| {
+        let key = CombinedKey::generate_secp256k1();
+        let enr = Enr::builder().build(&key).unwrap();
+        let node_id = enr.node_id();
+        service
+            .session_enrs
+            .insert(node_id, (enr, ConnectionDirection::Outgoing));
+        service.handle_ip_vote_from_pong(node_id, voter, socket);
+    };
+
+    // Peers of distinct subnets agree on other sockets for both IP versions.
+    let voted_ip4: SocketAddr = "5.6.7.8:9100".parse().unwrap();
+    let voted_ip6: SocketAddr = "[2001:db8::1]:9100".parse().unwrap();
+    for i in 0..10u16 {
+        vote(Ipv4Addr::new(10, i as u8, 0, 1).into(), voted_ip4);
+        vote(
+            Ipv6Addr::new(0x2001, 0xdb8, i, 0, 0, 0, 0, 1).into(),
+            voted_ip6,
+        );
+    }
+
+    // The pinned IPv4 socket is kept, while the IPv6 socket is learned.
+    let local_enr = service.local_enr.read().clone();
+    assert_eq!(
+        local_enr.udp4_socket().map(SocketAddr::V4),
+        Some(static_socket)
+    );
+    assert_eq!(local_enr.udp6_socket().map(SocketAddr::V6), Some(voted_ip6));
+
+    // Only the learned IPv6 socket is subject to the NAT check.
+    let failure =
+        tokio::time::timeout(nat_listen_duration * 10, service.connectivity_state.poll()).await;
+    assert!(matches!(failure, Ok(TimerFailure::V6)));
+    assert!(
+        tokio::time::timeout(nat_listen_duration * 3, service.connectivity_state.poll())
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_probes_are_tracked_per_request() {
+    init();
+
+    let enr_key = CombinedKey::generate_secp256k1();
+    let local_enr = Enr::builder()
+        .ip4(Ipv4Addr::LOCALHOST)
+        .udp4(DEFAULT_UDP_PORT)
+        .build(&enr_key)
+        .unwrap();
+    let (mut service, _handler_recv, _handler_send) = build_non_handler_service(
+        Arc::new(RwLock::new(local_enr)),
+        Arc::new(RwLock::new(Arc::new(enr_key))),
+        false,
+    );
+
+    let key = CombinedKey::generate_secp256k1();
+    let probed = Enr::builder()
+        .ip4(Ipv4Addr::LOCALHOST)
+        .udp4(DEFAULT_UDP_PORT + 1)
+        .build(&key)
+        .unwrap();
+    let node_key = kbucket::Key::from(probed.node_id());
+    let socket = probed.udp4_socket().unwrap().into();
+    let in_table = |service: &Service| {
+        matches!(
+            service
+                .kbuckets
+                .read()
+                .get_bucket(&node_key)
+                .map(|bucket| bucket.get(&node_key)),
+            Some(Some(_))
+        )
+    };
+
+    // Two probes of the same node.
+    let (first_send, first_recv) = oneshot::channel();
+    let (second_send, mut second_recv) = oneshot::channel();
+    service.probe(probed.clone(), first_send);
+    let first_id = service.active_requests.keys().next().cloned().unwrap();
+    service.probe(probed.clone(), second_send);
+    assert_eq!(service.active_requests.len(), 2);
+
+    // The session started by the probes does not insert the node.
+    service.inject_session_established(probed.clone(), &socket, ConnectionDirection::Outgoing);
+    assert!(!in_table(&service));
+
+    // A failed probe reports the PING as failed, and leaves the other probe pending.
+    service.rpc_failure(first_id, RequestError::Timeout);
+    let result = first_recv.await.unwrap();
+    assert!(result.session_established);
+    assert!(matches!(
+        result.failure,
+        Some((ProbeStep::Ping, RequestError::Timeout))
+    ));
+    assert!(second_recv.try_recv().is_err());
+    assert_eq!(service.active_requests.len(), 1);
+
+    // A session the node initiates is not attributed to the probe.
+    service.inject_session_established(probed.clone(), &socket, ConnectionDirection::Incoming);
+    assert!(in_table(&service));
+}
+
+#[tokio::test]
+async fn test_enr_update_is_not_propagated_to_expired_sessions() {
+    init();
+
+    let enr_key = CombinedKey::generate_secp256k1();
+    let local_enr = Enr::builder()
+        .ip4(Ipv4Addr::LOCALHOST)
+        .udp4(DEFAULT_UDP_PORT)
+        .build(&enr_key)
+        .unwrap();
+    let (mut service, _handler_recv, _handler_send) = build_non_handler_service(
+        Arc::new(RwLock::new(local_enr)),
+        Arc::new(RwLock::new(Arc::new(enr_key))),
+        false,
+    );
+    service.enr_propagation = EnrPropagation::new(
+        Some(Duration::from_millis(10)),
+        Duration::from_millis(10),
+        Duration::ZERO,
+    );
+    service.session_enrs = LruTimeCache::new(Duration::from_millis(50), None);
+
+    let mut insert_session = || {
+        let key = CombinedKey::generate_secp256k1();
+        let enr = Enr::builder().build(&key).unwrap();
+        let node_id = enr.node_id();
+        service
+            .session_enrs
+            .insert(node_id, (enr, ConnectionDirection::Outgoing));
+        node_id
+    };
+    insert_session();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let active = insert_session();
+
+    service.propagate_enr_update();
+    assert_eq!(
+        service.enr_propagation.poll().await,
+        PropagationEvent::Ping(active)
+    );
+    assert!(matches!(
+        service.enr_propagation.poll().await,
+        PropagationEvent::Completed { pinged: 1, .. }
+    ));
+}
@@ -0,0 +1,276 @@
+//! Operations requiring the secret key of the local node.
+//!
+//! The secret key is used to sign the local ENR, to sign the id-nonce during handshakes and to
+//! agree on session keys with peers that initiate a handshake. These operations are performed by a
+//! [`Signer`], allowing the key to be held outside of the process, for example in a separate
+//! signing process or a hardware module. A [`CombinedKey`] is a [`Signer`] holding the key in
+//! memory.
+//!
+//! The `enr` crate can only sign records with an in-memory key. Updates to the local ENR are
+//! therefore applied with a scratch key, after which the public key of the signer is restored and
+//! the record is signed again by the [`Signer`].
+#[cfg(unix)]
+mod socket;
+
+#[cfg(unix)]
+pub use socket::{serve_signer, SocketSigner};
+
+use crate::{handler::IdentityScheme, Enr};
+use alloy_rlp::{Decodable, Encodable, Header};
+use enr::{CombinedKey, CombinedPublicKey, EnrKey, EnrPublicKey, Error as EnrError};
+use parking_lot::RwLock;
+use std::{collections::BTreeMap, sync::Arc};
+use zeroize::Zeroizing;
+
+/// Performs the operations requiring the secret key of the local node.
+///
+/// The operations may block. During handshakes they are called on a blocking thread, so that a
+/// slow signer does not stall the handling of other packets.
+pub trait Signer: Send + Sync {
+    /// The public key of the local node.
+    fn public_key(&self) -> CombinedPublicKey;
+
+    /// Signs the RLP encoded content of a node record, as done by [`EnrKey::sign_v4`].
+    fn sign_enr(&self, content: &[u8]) -> Result<Vec<u8>, crate::Error>;
+
+    /// Signs the id-nonce signing input of a handshake using the identity scheme of the local
+    /// node.
+    fn sign_id_nonce(
+        &self,
+        scheme: &dyn IdentityScheme,
+        input: &[u8],
+    ) -> Result<Vec<u8>, crate::Error>;
+
+    /// Agrees on a shared secret with the ephemeral public key of a remote, using the identity
+    /// scheme of the local node.
+    fn ecdh(
+        &self,
+        scheme: &dyn IdentityScheme,
+        ephem_pubkey: &[u8],
+    ) -> Result<Vec<u8>, crate::Error>;
+}
+
+impl Signer for CombinedKey {
+    fn public_key(&self) -> CombinedPublicKey {
+        self.public()
+    }
+
+    fn sign_enr(&self, content: &[u8]) -> Result<Vec<u8>, crate::Error> {
+        self.sign_v4(content)
+            .map_err(|e| crate::Error::Error(format!("Failed to sign ENR: {e}")))
+    }
+
+    fn sign_id_nonce(
+        &self,
+        scheme: &dyn IdentityScheme,
+        input: &[u8],
+    ) -> Result<Vec<u8>, crate::Error> {
+        scheme.sign_id_nonce(&Zeroizing::new(self.encode()), input)
+    }
+
+    fn ecdh(
+        &self,
+        scheme: &dyn IdentityScheme,
+        ephem_pubkey: &[u8],
+    ) -> Result<Vec<u8>, crate::Error> {
+        scheme.recipient_ecdh(ephem_pubkey, &Zeroizing::new(self.encode()))
+    }
+}
+
+/// Applies an update to the local ENR and signs the updated record with the signer. The record is
+/// left unchanged if the update or the signing fails.
+pub(crate) fn update_enr<T>(
+    enr: &mut Enr,
+    signer: &dyn Signer,
+    update: impl FnOnce(&mut Enr, &CombinedKey) -> Result<T, EnrError>,
+) -> Result<T, EnrError> {
+    let scratch_key = CombinedKey::generate_secp256k1();
+    let mut updated = enr.clone();
+    let result = update(&mut updated, &scratch_key)?;
+    *enr = sign_enr(&updated, signer)?;
+    Ok(result)
+}
+
+/// Replaces the shared local ENR with a record derived from it and signed with the signer. The
+/// signer may block, so the record is derived and signed without holding the lock on the local
+/// ENR. It is swapped in only if the sequence number of the local ENR is unchanged, otherwise it is
+/// derived again from the newer record. Returns `None` if `derive` leaves the record unchanged.
+pub(crate) fn swap_local_enr<T>(
+    local_enr: &RwLock<Enr>,
+    signer: &RwLock<Arc<dyn Signer>>,
+    mut derive: impl FnMut(&Enr, &dyn Signer) -> Result<Option<(Enr, T)>, EnrError>,
+) -> Result<Option<(Enr, T)>, EnrError> {
+    loop {
+        // The key is replaced together with the record, so both are read under the same lock.
+        let (current, signer) = {
+            let local_enr = local_enr.read();
+            (local_enr.clone(), signer.read().clone())
+        };
+        let Some((updated, result)) = derive(&current, signer.as_ref())? else {
+            return Ok(None);
+        };
+        let mut local_enr = local_enr.write();
+        if local_enr.seq() == current.seq() {
+            *local_enr = updated.clone();
+            return Ok(Some((updated, result)));
+        }
+    }
+}
+
+/// Signs the shared local ENR with a new key, with an incremented sequence number, and replaces
+/// the key. As in [`swap_local_enr`], the record is signed without holding the lock on the local
+/// ENR. The key is replaced in the same step as the record.
+pub(crate) fn rotate_local_key(
+    local_enr: &RwLock<Enr>,
+    key: &RwLock<Arc<dyn Signer>>,
+    new_key: Arc<dyn Signer>,
+) -> Result<Enr, EnrError> {
+    loop {
+        let current = local_enr.read().clone();
+        let mut updated = current.clone();
+        update_enr(&mut updated, new_key.as_ref(), |enr, key| {
+            let seq = enr
+                .seq()
+                .checked_add(1)
+                .ok_or(EnrError::SequenceNumberTooHigh)?;
+            enr.set_seq(seq, key)
+        })?;
+        let mut local_enr = local_enr.write();
+        if local_enr.seq() == current.seq() {
+            *local_enr = updated.clone();
+            *key.write() = new_key;
+            return Ok(updated);
+        }
+    }
+}
+
+/// The maximum size of an encoded ENR.
+const MAX_ENR_SIZE: usize = 300;
+
+/// Replaces the public key of the record with the signer's and signs the record.
+fn sign_enr(enr: &Enr, signer: &dyn Signer) -> Result<Enr, EnrError> {
+    let content = enr
+        .iter()
+        .map(|(key, value)| (key.clone(), value.to_vec()))
+        .collect();
+    sign_content(enr.seq(), content, signer)
+}
+
+/// Builds a record from its RLP encoded content, replacing any public key with the signer's, and
+/// signs it.
+pub(crate) fn sign_content(
+    seq: u64,
+    mut content: BTreeMap<Vec<u8>, Vec<u8>>,
+    signer: &dyn Signer,
+) -> Result<Enr, EnrError> {
+    let public_key = signer.public_key();
+    content.retain(|key, _| !matches!(key.as_slice(), b"secp256k1" | b"ed25519"));
+    let mut encoded_key = Vec::new();
+    EnrPublicKey::encode(&public_key)
+        .as_slice()
+        .encode(&mut encoded_key);
+    content.insert(public_key.enr_key(), encoded_key);
+
+    let mut payload = Vec::new();
+    seq.encode(&mut payload);
+    for (key, value) in &content {
+        key.as_slice().encode(&mut payload);
+        payload.extend_from_slice(value);
+    }
+    let signature = signer
+        .sign_enr(&rlp_list(&payload))
+        .map_err(|_| EnrError::SigningError)?;
+
+    let mut signed_payload = Vec::new();
+    signature.as_slice().encode(&mut signed_payload);
+    signed_payload.extend_from_slice(&payload);
+    let encoded = rlp_list(&signed_payload);
+    if encoded.len() > MAX_ENR_SIZE {
+        return Err(EnrError::ExceedsMaxSize);
+    }
+    // Decoding verifies the signature and the content of the record.
+    Ok(Enr::decode(&mut encoded.as_slice())?)
+}
+
+fn rlp_list(payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 3);
+    Header {
+        list: true,
+        payload_length: payload.len(),
+    }
+    .encode(&mut out);
+    out.extend_from_slice(payload);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn test_update_enr() {
+        for key in [
+            CombinedKey::generate_secp256k1(),
+            CombinedKey::generate_ed25519(),
+        ] {
+            let mut enr = Enr::builder().build(&key).unwrap();
+            let socket: SocketAddr = "192.168.0.1:9000".parse().unwrap();
+            update_enr(&mut enr, &key, |enr, key| enr.set_udp_socket(socket, key)).unwrap();
+
+            assert_eq!(enr.udp4_socket(), Some("192.168.0.1:9000".parse().unwrap()));
+            assert_eq!(enr.seq(), 2);
+            assert_eq!(enr.public_key(), key.public());
+            assert!(enr.verify());
+        }
+    }
+
+    #[test]
+    fn test_failed_update_leaves_enr_unchanged() {
+        let key = CombinedKey::generate_secp256k1();
+        let mut enr = Enr::builder().build(&key).unwrap();
+        let original = enr.clone();
+        assert!(update_enr(&mut enr, &key, |enr, key| enr.insert(
+            "large",
+            &vec![0u8; 300].as_slice(),
+            key
+        ))
+        .is_err());
+        assert_eq!(enr, original);
+    }
+
+    #[test]
+    fn test_swap_local_enr_rederives_after_concurrent_update() {
+        let key = CombinedKey::generate_secp256k1();
+        let local_enr = RwLock::new(Enr::builder().build(&key).unwrap());
+        let signer: RwLock<Arc<dyn Signer>> = RwLock::new(Arc::new(key));
+        let socket: SocketAddr = "192.168.0.1:9000".parse().unwrap();
+
+        let mut derivations = 0;
+        let (updated, ()) = swap_local_enr(&local_enr, &signer, |enr, signer| {
+            derivations += 1;
+            if derivations == 1 {
+                // Another update is applied while the first derivation is signed.
+                update_enr(&mut local_enr.write(), signer, |enr, key| {
+                    enr.insert("other", &1u8, key)
+                })?;
+            }
+            let mut updated = enr.clone();
+            update_enr(&mut updated, signer, |enr, key| {
+                enr.set_udp_socket(socket, key)
+            })?;
+            Ok(Some((updated, ())))
+        })
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(derivations, 2);
+        assert_eq!(updated.seq(), 3);
+        assert_eq!(
+            updated.udp4_socket(),
+            Some("192.168.0.1:9000".parse().unwrap())
+        );
+        assert_eq!(updated.get_decodable::<u8>("other"), Some(Ok(1)));
+        assert_eq!(*local_enr.read(), updated);
+    }
+}
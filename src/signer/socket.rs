@@ -0,0 +1,254 @@
+//! A [`Signer`] delegating to a signing process over a Unix domain socket.
+//!
+//! This is a minimal stand-in for an external signer, intended for testing. Each request consists
+//! of an operation byte, the name of the identity scheme prefixed by its length as a byte, and a
+//! payload prefixed by its length as a big-endian `u32`. Each response consists of a status byte,
+//! `0` on success, and a payload prefixed by its length. The payload of a failed request is an
+//! error message.
+use super::Signer;
+use crate::{
+    handler::{IdentityScheme, IdentitySchemes},
+    Error,
+};
+use enr::{ed25519_dalek, k256, CombinedKey, CombinedPublicKey, EnrPublicKey};
+use parking_lot::Mutex;
+use std::{
+    convert::TryFrom,
+    io::{self, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+use tracing::debug;
+
+const OP_PUBLIC_KEY: u8 = 0;
+const OP_SIGN_ENR: u8 = 1;
+const OP_SIGN_ID_NONCE: u8 = 2;
+const OP_ECDH: u8 = 3;
+
+/// The default time to wait for the signing process to answer a request.
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A [`Signer`] connected to a signing process via a Unix domain socket. See [`serve_signer`].
+///
+/// A request fails if the signing process does not answer within the request timeout. The
+/// connection is then dropped, so that a late answer is not mistaken for the answer to the next
+/// request, and re-established by the next request.
+pub struct SocketSigner {
+    path: PathBuf,
+    timeout: Duration,
+    stream: Mutex<Option<UnixStream>>,
+    public_key: CombinedPublicKey,
+}
+
+impl SocketSigner {
+    /// Connects to a signing process and retrieves its public key. Requests fail if the signing
+    /// process does not answer within two seconds.
+    pub fn connect(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::connect_with_timeout(path, DEFAULT_REQUEST_TIMEOUT)
+    }
+
+    /// Connects to a signing process and retrieves its public key. Requests fail if the signing
+    /// process does not answer within `timeout`.
+    pub fn connect_with_timeout(path: impl AsRef<Path>, timeout: Duration) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let stream = Mutex::new(Some(connect(&path, timeout)?));
+        let encoded = request(&path, timeout, &stream, OP_PUBLIC_KEY, "", &[])?;
+        let public_key = match encoded.len() {
+            32 => ed25519_dalek::VerifyingKey::try_from(encoded.as_slice())
+                .map(CombinedPublicKey::from)
+                .map_err(|_| Error::InvalidRemotePublicKey)?,
+            _ => k256::ecdsa::VerifyingKey::from_sec1_bytes(&encoded)
+                .map(CombinedPublicKey::from)
+                .map_err(|_| Error::InvalidRemotePublicKey)?,
+        };
+        Ok(SocketSigner {
+            path,
+            timeout,
+            stream,
+            public_key,
+        })
+    }
+
+    fn request(&self, op: u8, scheme: &str, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        request(&self.path, self.timeout, &self.stream, op, scheme, payload)
+    }
+}
+
+impl Signer for SocketSigner {
+    fn public_key(&self) -> CombinedPublicKey {
+        self.public_key.clone()
+    }
+
+    fn sign_enr(&self, content: &[u8]) -> Result<Vec<u8>, Error> {
+        self.request(OP_SIGN_ENR, "", content)
+    }
+
+    fn sign_id_nonce(&self, scheme: &dyn IdentityScheme, input: &[u8]) -> Result<Vec<u8>, Error> {
+        self.request(OP_SIGN_ID_NONCE, scheme.id(), input)
+    }
+
+    fn ecdh(&self, scheme: &dyn IdentityScheme, ephem_pubkey: &[u8]) -> Result<Vec<u8>, Error> {
+        self.request(OP_ECDH, scheme.id(), ephem_pubkey)
+    }
+}
+
+fn connect(path: &Path, timeout: Duration) -> io::Result<UnixStream> {
+    let stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    Ok(stream)
+}
+
+/// Sends a request over the connection, re-connecting if a previous request failed.
+fn request(
+    path: &Path,
+    timeout: Duration,
+    stream: &Mutex<Option<UnixStream>>,
+    op: u8,
+    scheme: &str,
+    payload: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let mut stream = stream.lock();
+    let connection = match stream.as_mut() {
+        Some(connection) => connection,
+        None => stream.insert(connect(path, timeout)?),
+    };
+    let mut message = vec![op, scheme.len() as u8];
+    message.extend_from_slice(scheme.as_bytes());
+    message.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    message.extend_from_slice(payload);
+
+    let mut status = [0u8; 1];
+    let response = connection
+        .write_all(&message)
+        .and_then(|_| connection.read_exact(&mut status))
+        .and_then(|_| read_payload(connection));
+    let response = match response {
+        Ok(response) => response,
+        Err(e) => {
+            // The answer may still arrive, so the connection can not be used again.
+            *stream = None;
+            return Err(e.into());
+        }
+    };
+    match status[0] {
+        0 => Ok(response),
+        _ => Err(Error::Error(
+            String::from_utf8_lossy(&response).into_owned(),
+        )),
+    }
+}
+
+fn read_payload(stream: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let mut payload = vec![0u8; u32::from_be_bytes(len) as usize];
+    stream.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+/// Serves signing requests of [`SocketSigner`]s on the listener with the given key, on a
+/// background thread.
+pub fn serve_signer(
+    listener: UnixListener,
+    key: CombinedKey,
+    schemes: IdentitySchemes,
+) -> thread::JoinHandle<()> {
+    let key = Arc::new(key);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let key = key.clone();
+            let schemes = schemes.clone();
+            thread::spawn(move || {
+                if let Err(e) = serve_connection(stream, &key, &schemes) {
+                    debug!(error = %e, "Signer connection closed");
+                }
+            });
+        }
+    })
+}
+
+fn serve_connection(
+    mut stream: UnixStream,
+    key: &CombinedKey,
+    schemes: &IdentitySchemes,
+) -> io::Result<()> {
+    loop {
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header)?;
+        let mut scheme = vec![0u8; header[1] as usize];
+        stream.read_exact(&mut scheme)?;
+        let payload = read_payload(&mut stream)?;
+
+        let scheme = String::from_utf8_lossy(&scheme);
+        let result = match (header[0], schemes.get(&scheme)) {
+            (OP_PUBLIC_KEY, _) => Ok(key.public_key().encode()),
+            (OP_SIGN_ENR, _) => key.sign_enr(&payload),
+            (OP_SIGN_ID_NONCE, Some(scheme)) => key.sign_id_nonce(scheme, &payload),
+            (OP_ECDH, Some(scheme)) => key.ecdh(scheme, &payload),
+            (OP_SIGN_ID_NONCE | OP_ECDH, None) => {
+                Err(Error::Error(format!("Unknown identity scheme {scheme}")))
+            }
+            (op, _) => Err(Error::Error(format!("Unknown operation {op}"))),
+        };
+
+        let (status, response) = match result {
+            Ok(response) => (0, response),
+            Err(e) => (1, format!("{e:?}").into_bytes()),
+        };
+        let mut message = vec![status];
+        message.extend_from_slice(&(response.len() as u32).to_be_bytes());
+        message.extend_from_slice(&response);
+        stream.write_all(&message)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    /// A signing process that stops answering fails requests after the timeout instead of
+    /// blocking the caller.
+    #[test]
+    fn unanswered_requests_time_out() {
+        let path =
+            std::env::temp_dir().join(format!("discv5-signer-{}.sock", rand::random::<u64>()));
+        let listener = UnixListener::bind(&path).unwrap();
+        let key = CombinedKey::generate_secp256k1();
+        let public_key = key.public_key();
+        let server = thread::spawn(move || {
+            // Answer the public key request of the first connection, then stop answering.
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 6];
+            stream.read_exact(&mut request).unwrap();
+            let encoded = key.public_key().encode();
+            let mut message = vec![0];
+            message.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+            message.extend_from_slice(&encoded);
+            stream.write_all(&message).unwrap();
+            let (second, _) = listener.accept().unwrap();
+            (stream, second)
+        });
+
+        let timeout = Duration::from_millis(200);
+        let signer = SocketSigner::connect_with_timeout(&path, timeout).unwrap();
+        assert_eq!(signer.public_key(), public_key);
+
+        let start = Instant::now();
+        assert!(matches!(signer.sign_enr(&[1, 2, 3]), Err(Error::Io(_))));
+        assert!(start.elapsed() >= timeout);
+        assert!(signer.stream.lock().is_none());
+
+        // The next request connects again.
+        assert!(signer.sign_enr(&[1, 2, 3]).is_err());
+        let _connections = server.join().unwrap();
+        let _ = std::fs::remove_file(path);
+    }
+}
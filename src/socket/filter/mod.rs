@@ -1,6 +1,12 @@
 //! A filter which decides whether to accept/reject incoming UDP packets.
 
-use crate::{discv5::PERMIT_BAN_LIST, metrics::METRICS, node_info::NodeAddress, packet::Packet};
+use crate::{
+    discv5::PERMIT_BAN_LIST,
+    metrics::METRICS,
+    node_info::NodeAddress,
+    packet::Packet,
+    permit_ban::{BanEntry, BanSource},
+};
 use cache::ReceivedPacketCache;
 use enr::NodeId;
 use hashlink::LruCache;
@@ -79,11 +85,11 @@ impl Filter {
     /// The first check. This determines if a new UDP packet should be decoded or dropped.
     /// Only unsolicited packets arrive here.
     pub fn initial_pass(&mut self, src: &SocketAddr) -> bool {
-        if PERMIT_BAN_LIST.read().permit_ips.contains(&src.ip()) {
+        if PERMIT_BAN_LIST.read().is_ip_permitted(&src.ip()) {
             return true;
         }
 
-        if PERMIT_BAN_LIST.read().ban_ips.contains_key(&src.ip()) {
+        if PERMIT_BAN_LIST.read().is_ip_banned(&src.ip()) {
             debug!(?src, "Dropped unsolicited packet from banned src");
             return false;
         }
@@ -109,10 +115,10 @@ impl Filter {
                 warn!(ip = ?src.ip(), "Banning IP for excessive requests");
                 // Ban the IP address
                 let ban_timeout = self.ban_duration.map(|v| Instant::now() + v);
-                PERMIT_BAN_LIST
-                    .write()
-                    .ban_ips
-                    .insert(src.ip(), ban_timeout);
+                PERMIT_BAN_LIST.write().ban_ips.insert(
+                    src.ip(),
+                    BanEntry::new(BanSource::Filter, "Excessive requests", ban_timeout),
+                );
                 return false;
             }
 
@@ -162,10 +168,10 @@ impl Filter {
 
                 // The node is being banned
                 let ban_timeout = self.ban_duration.map(|v| Instant::now() + v);
-                PERMIT_BAN_LIST
-                    .write()
-                    .ban_nodes
-                    .insert(node_address.node_id, ban_timeout);
+                PERMIT_BAN_LIST.write().ban_nodes.insert(
+                    node_address.node_id,
+                    BanEntry::new(BanSource::Filter, "Excessive requests", ban_timeout),
+                );
 
                 // If we are tracking banned nodes per IP, add to the count. If the count is higher
                 // than our tolerance, ban the IP.
@@ -174,7 +180,14 @@ impl Filter {
                     if let Some(banned_count) = self.banned_nodes.get_mut(&ip) {
                         *banned_count += 1;
                         if *banned_count >= max_bans_per_ip {
-                            PERMIT_BAN_LIST.write().ban_ips.insert(ip, ban_timeout);
+                            PERMIT_BAN_LIST.write().ban_ips.insert(
+                                ip,
+                                BanEntry::new(
+                                    BanSource::Filter,
+                                    "Too many banned nodes",
+                                    ban_timeout,
+                                ),
+                            );
                         }
                     } else {
                         self.banned_nodes.insert(ip, 0);
@@ -205,7 +218,10 @@ impl Filter {
                 warn!(%ip, "IP has exceeded its node-id limit and is now banned");
                 // The node is being banned
                 let ban_timeout = self.ban_duration.map(|v| Instant::now() + v);
-                PERMIT_BAN_LIST.write().ban_ips.insert(ip, ban_timeout);
+                PERMIT_BAN_LIST.write().ban_ips.insert(
+                    ip,
+                    BanEntry::new(BanSource::Filter, "Too many node ids", ban_timeout),
+                );
                 self.known_addrs.remove(&ip);
                 return false;
             }
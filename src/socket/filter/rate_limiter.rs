@@ -1,6 +1,8 @@
+use crate::rpc::RequestBody;
 use enr::NodeId;
 use fnv::FnvHashMap;
 use std::{
+    collections::HashMap,
     convert::TryInto,
     hash::Hash,
     net::IpAddr,
@@ -262,6 +264,178 @@ impl RateLimiter {
     }
 }
 
+/// How inbound requests exceeding their quota in the [`RequestRateLimiter`] are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RateLimitedAction {
+    /// The request is dropped silently and the requester eventually times out.
+    #[default]
+    Drop,
+    /// FINDNODE requests are answered with an empty NODES response and TALKREQ requests with an
+    /// empty TALKRESP. PING requests are always dropped.
+    RespondEmpty,
+}
+
+/// Rate limits decrypted inbound requests per node, with differentiated quotas per request type.
+///
+/// Unlike the [`RateLimiter`], which counts raw packets before they are decrypted, this limiter
+/// knows the kind of each request and can therefore limit expensive requests more strictly than
+/// cheap ones. A FINDNODE request costs one token per requested distance, as each distance can
+/// produce up to a full bucket of ENRs. All other requests cost a single token.
+#[derive(Debug, Clone)]
+pub struct RequestRateLimiter {
+    /// Creation time of the rate limiter.
+    init_time: Instant,
+    /// Rate limit of PING requests for each node.
+    ping_rl: Option<Limiter<NodeId>>,
+    /// Rate limit of FINDNODE requests for each node.
+    findnode_rl: Option<Limiter<NodeId>>,
+    /// Rate limit of TALKREQ requests for each node, for protocols without a dedicated quota.
+    talk_rl: Option<Limiter<NodeId>>,
+    /// Rate limits of TALKREQ requests for each node, per TALK protocol.
+    talk_protocol_rl: HashMap<Vec<u8>, Limiter<NodeId>>,
+    /// How requests exceeding their quota are handled.
+    action: RateLimitedAction,
+}
+
+/// User-friendly builder of a [`RequestRateLimiter`]. Every quota is optional, requests of a kind
+/// without a quota are not limited. A quota set for a specific TALK protocol replaces the general
+/// TALK quota for that protocol.
+#[derive(Default)]
+pub struct RequestRateLimiterBuilder {
+    /// Quota for PING requests of each node.
+    ping_quota: Option<Quota>,
+    /// Quota for FINDNODE requests of each node.
+    findnode_quota: Option<Quota>,
+    /// Quota for TALKREQ requests of each node.
+    talk_quota: Option<Quota>,
+    /// Quotas for TALKREQ requests of each node, per TALK protocol.
+    talk_protocol_quotas: HashMap<Vec<u8>, Quota>,
+    /// How requests exceeding their quota are handled.
+    action: RateLimitedAction,
+}
+
+impl RequestRateLimiterBuilder {
+    /// Get an empty `RequestRateLimiterBuilder`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Allow `n` PING requests every `time_period` per node.
+    pub fn ping_n_every(mut self, n: u64, time_period: Duration) -> Self {
+        self.ping_quota = Some(Quota {
+            replenish_all_every: time_period,
+            max_tokens: n,
+        });
+        self
+    }
+
+    /// Allow `n` FINDNODE distances to be requested every `time_period` per node.
+    pub fn findnode_n_every(mut self, n: u64, time_period: Duration) -> Self {
+        self.findnode_quota = Some(Quota {
+            replenish_all_every: time_period,
+            max_tokens: n,
+        });
+        self
+    }
+
+    /// Allow `n` TALKREQ requests every `time_period` per node.
+    pub fn talk_n_every(mut self, n: u64, time_period: Duration) -> Self {
+        self.talk_quota = Some(Quota {
+            replenish_all_every: time_period,
+            max_tokens: n,
+        });
+        self
+    }
+
+    /// Allow `n` TALKREQ requests of the given `protocol` every `time_period` per node.
+    pub fn talk_protocol_n_every(
+        mut self,
+        protocol: impl Into<Vec<u8>>,
+        n: u64,
+        time_period: Duration,
+    ) -> Self {
+        self.talk_protocol_quotas.insert(
+            protocol.into(),
+            Quota {
+                replenish_all_every: time_period,
+                max_tokens: n,
+            },
+        );
+        self
+    }
+
+    /// Sets how requests exceeding their quota are handled. Default: [`RateLimitedAction::Drop`].
+    pub fn action(mut self, action: RateLimitedAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    pub fn build(self) -> Result<RequestRateLimiter, &'static str> {
+        let limiter = |quota: Option<Quota>| quota.map(Limiter::from_quota).transpose();
+
+        let talk_protocol_rl = self
+            .talk_protocol_quotas
+            .into_iter()
+            .map(|(protocol, quota)| Ok((protocol, Limiter::from_quota(quota)?)))
+            .collect::<Result<_, &'static str>>()?;
+
+        Ok(RequestRateLimiter {
+            init_time: Instant::now(),
+            ping_rl: limiter(self.ping_quota)?,
+            findnode_rl: limiter(self.findnode_quota)?,
+            talk_rl: limiter(self.talk_quota)?,
+            talk_protocol_rl,
+            action: self.action,
+        })
+    }
+}
+
+impl RequestRateLimiter {
+    /// Indicates whether a request from the given node is allowed by the configured quotas.
+    pub fn allows(
+        &mut self,
+        node_id: &NodeId,
+        request: &RequestBody,
+    ) -> Result<(), RateLimitedErr> {
+        let time_since_start = self.init_time.elapsed();
+
+        let (limiter, tokens) = match request {
+            RequestBody::Ping { .. } => (self.ping_rl.as_mut(), 1),
+            RequestBody::FindNode { distances } => {
+                (self.findnode_rl.as_mut(), distances.len().max(1) as u64)
+            }
+            RequestBody::Talk { protocol, .. } => match self.talk_protocol_rl.get_mut(protocol) {
+                Some(limiter) => (Some(limiter), 1),
+                None => (self.talk_rl.as_mut(), 1),
+            },
+        };
+
+        match limiter {
+            Some(limiter) => limiter.allows(time_since_start, node_id, tokens),
+            None => Ok(()),
+        }
+    }
+
+    /// How requests exceeding their quota are handled.
+    pub fn action(&self) -> RateLimitedAction {
+        self.action
+    }
+
+    /// Prunes excess entries. Should be called regularly (30 seconds) to remove old entries.
+    pub fn prune(&mut self) {
+        let time_since_start = self.init_time.elapsed();
+        for limiter in self
+            .ping_rl
+            .iter_mut()
+            .chain(self.findnode_rl.iter_mut())
+            .chain(self.talk_rl.iter_mut())
+            .chain(self.talk_protocol_rl.values_mut())
+        {
+            limiter.prune(time_since_start);
+        }
+    }
+}
+
 /// Per key rate limiter using the token bucket / leaky bucket as a meter rate limiting algorithm,
 /// with the GCRA implementation.
 #[derive(Debug, Clone)]
@@ -343,7 +517,9 @@ impl<Key: Hash + Eq + Clone> Limiter<Key> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Limiter, Quota};
+    use super::{Limiter, Quota, RequestRateLimiterBuilder};
+    use crate::rpc::RequestBody;
+    use enr::NodeId;
     use std::time::Duration;
 
     #[test]
@@ -411,4 +587,45 @@ mod tests {
             .allows(Duration::from_secs_f32(0.4), &key, 1)
             .is_err());
     }
+
+    #[test]
+    fn request_limiter_quotas_per_request_type() {
+        let mut limiter = RequestRateLimiterBuilder::new()
+            .findnode_n_every(4, Duration::from_secs(60))
+            .talk_n_every(1, Duration::from_secs(60))
+            .talk_protocol_n_every("expensive", 2, Duration::from_secs(60))
+            .build()
+            .unwrap();
+        let node_id = NodeId::random();
+        let other_node_id = NodeId::random();
+
+        let ping = RequestBody::Ping { enr_seq: 1 };
+        let findnode = RequestBody::FindNode {
+            distances: vec![255, 256, 254],
+        };
+        let talk = |protocol: &str| RequestBody::Talk {
+            protocol: protocol.as_bytes().to_vec(),
+            request: vec![],
+        };
+
+        // PINGs are not limited
+        for _ in 0..10 {
+            assert!(limiter.allows(&node_id, &ping).is_ok());
+        }
+
+        // Each requested distance costs a token
+        assert!(limiter.allows(&node_id, &findnode).is_ok());
+        assert!(limiter.allows(&node_id, &findnode).is_err());
+        assert!(limiter
+            .allows(&node_id, &RequestBody::FindNode { distances: vec![0] })
+            .is_ok());
+        assert!(limiter.allows(&other_node_id, &findnode).is_ok());
+
+        // TALK protocols with a dedicated quota are limited separately
+        assert!(limiter.allows(&node_id, &talk("cheap")).is_ok());
+        assert!(limiter.allows(&node_id, &talk("other")).is_err());
+        assert!(limiter.allows(&node_id, &talk("expensive")).is_ok());
+        assert!(limiter.allows(&node_id, &talk("expensive")).is_ok());
+        assert!(limiter.allows(&node_id, &talk("expensive")).is_err());
+    }
 }
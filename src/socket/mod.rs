@@ -20,7 +20,10 @@ mod recv;
 mod send;
 
 pub use filter::{
-    rate_limiter::{RateLimiter, RateLimiterBuilder},
+    rate_limiter::{
+        RateLimitedAction, RateLimiter, RateLimiterBuilder, RequestRateLimiter,
+        RequestRateLimiterBuilder,
+    },
     FilterConfig,
 };
 pub use recv::{InboundPacket, RecvPacket, UnrecognizedFrame};
@@ -76,6 +79,8 @@ pub struct Socket {
     pub recv: mpsc::Receiver<RecvPacket>,
     sender_exit: Option<oneshot::Sender<()>>,
     recv_exit: Option<oneshot::Sender<()>>,
+    /// The local node id used by the recv handler to decrypt headers.
+    local_node_id: Arc<RwLock<enr::NodeId>>,
 }
 
 impl Socket {
@@ -152,12 +157,13 @@ impl Socket {
         };
 
         // spawn the recv handler
+        let local_node_id = Arc::new(RwLock::new(local_node_id));
         let recv_config = RecvHandlerConfig {
             filter_config,
             executor: executor.clone(),
             recv: first_recv,
             second_recv,
-            local_node_id,
+            local_node_id: local_node_id.clone(),
             protocol_identity,
             expected_responses,
             ban_duration,
@@ -172,8 +178,14 @@ impl Socket {
             recv,
             sender_exit: Some(sender_exit),
             recv_exit: Some(recv_exit),
+            local_node_id,
         })
     }
+
+    /// Sets the local node id used to decrypt the headers of received packets.
+    pub(crate) fn set_local_node_id(&self, node_id: enr::NodeId) {
+        *self.local_node_id.write() = node_id;
+    }
 }
 
 impl ListenConfig {
@@ -49,7 +49,7 @@ pub struct RecvHandlerConfig {
     pub executor: Box<dyn Executor>,
     pub recv: Arc<UdpSocket>,
     pub second_recv: Option<Arc<UdpSocket>>,
-    pub local_node_id: enr::NodeId,
+    pub local_node_id: Arc<RwLock<enr::NodeId>>,
     pub protocol_identity: ProtocolIdentity,
     pub expected_responses: Arc<RwLock<HashMap<SocketAddr, usize>>>,
 }
@@ -66,8 +66,9 @@ pub(crate) struct RecvHandler {
     expected_responses: Arc<RwLock<HashMap<SocketAddr, usize>>>,
     /// The packet filter which decides whether to accept or reject inbound packets.
     filter: Filter,
-    /// The local node id used to decrypt headers of messages.
-    node_id: enr::NodeId,
+    /// The local node id used to decrypt headers of messages. Updated when the local key is
+    /// rotated.
+    node_id: Arc<RwLock<enr::NodeId>>,
     /// The protocol identity expected in received packets.
     protocol_identity: ProtocolIdentity,
     /// The channel to send the packet handler.
@@ -202,27 +203,25 @@ impl RecvHandler {
             return;
         }
         // Decodes the packet
-        let (packet, authenticated_data) = match Packet::decode(
-            &self.node_id,
-            self.protocol_identity,
-            &recv_buffer[..length],
-        ) {
-            Ok(p) => p,
-            Err(e) => {
-                debug!(error = ?e, "Packet decoding failed"); // could not decode the packet, drop it
-                let frame = UnrecognizedFrame {
-                    src_address,
-                    packet: recv_buffer[..length].to_vec(),
-                };
-                self.handler
+        let node_id = *self.node_id.read();
+        let (packet, authenticated_data) =
+            match Packet::decode(&node_id, self.protocol_identity, &recv_buffer[..length]) {
+                Ok(p) => p,
+                Err(e) => {
+                    debug!(error = ?e, "Packet decoding failed"); // could not decode the packet, drop it
+                    let frame = UnrecognizedFrame {
+                        src_address,
+                        packet: recv_buffer[..length].to_vec(),
+                    };
+                    self.handler
                     .send(RecvPacket::UnrecognizedFrame(frame))
                     .await
                     .unwrap_or_else(
                         |err| warn!(error = %err, "Could not send unrecognized frame to handler"),
                     );
-                return;
-            }
-        };
+                    return;
+                }
+            };
 
         // If this is not a challenge packet, we immediately know its src_id and so pass it
         // through the second filter.